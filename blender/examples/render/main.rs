@@ -58,24 +58,29 @@ async fn render_with_manager() {
     let frames = Arc::new(RwLock::new(frames));
 
     // render the frame. Completed render will return the path of the rendered frame, error indicates failure to render due to blender incompatible hardware settings or configurations. (CPU vs GPU / Metal vs OpenGL)
-    let listener = blender
+    let handle = blender
         .render(args, move || {
             let mut frame = frames.write().unwrap();
             frame.get_next_frame()
         })
-        .await;
+        .await
+        .expect("Should be able to start blender!");
 
-    // Handle blender status
-    while let Ok(status) = listener.recv() {
+    // Handle blender status. Call handle.cancel() to stop the render early.
+    while let Ok(status) = handle.recv() {
         match status {
-            Status::Completed { frame, result } => {
-                println!("[Completed] {frame} {result:?}");
+            Status::Completed {
+                frame,
+                result,
+                passes,
+            } => {
+                println!("[Completed] {frame} {result:?} {passes:?}");
             }
             Status::Log { status } => {
                 println!("[Info] {}", status);
             }
-            Status::Running { status } => {
-                println!("[Running] {}", status);
+            Status::Running { progress } => {
+                println!("[Running] {}", progress);
             }
             Status::Error(e) => {
                 println!("[ERROR] {:?}", e);
@@ -54,30 +54,37 @@ TODO:
         of just letting BlendFarm do all the work.
     */
 extern crate xml_rpc;
+use crate::log_parser::{parse_error_line, parse_line, Fatal, LogEvent};
 pub use crate::manager::{Manager, ManagerError};
 pub use crate::models::args::Args;
 use crate::models::{
-    blender_peek_response::BlenderPeekResponse, blender_render_setting::BlenderRenderSetting,
+    blender_peek_response::{BlenderPeekResponse, BlenderScene},
+    blender_render_setting::BlenderRenderSetting,
+    dependency::{self, Dependency, DependencyKind},
+    device::Device,
+    render_handle::{wait_for_exit, RenderHandle},
+    render_progress::{RenderPhase, RenderProgress},
     status::Status,
 };
 
-use blend::Blend;
-#[cfg(test)]
-use blend::Instance;
+use blend::{Blend, Instance};
 use regex::Regex;
 use semver::Version;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
-use std::process::{Command, Stdio};
-use std::sync::Arc;
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, sleep};
+use std::time::Duration;
 use std::{
     fs,
     io::{BufRead, BufReader},
     path::{Path, PathBuf},
-    sync::mpsc::{self, Receiver},
+    sync::mpsc,
 };
 use thiserror::Error;
-use tokio::spawn;
 use xml_rpc::{Fault, Server};
 
 // TODO: this is ugly, and I want to get rid of this. How can I improve this?
@@ -99,6 +106,52 @@ pub enum BlenderError {
     RenderError(String),
     #[error("Unable to launch blender! Received Python errors: {0}")]
     PythonError(String),
+    #[error("Unable to control blender process! {0}")]
+    ProcessError(String),
+    #[error("Blender ran out of memory! {0}")]
+    OutOfMemory(String),
+    #[error("Blender crashed! {0}")]
+    Crashed(String),
+    #[error("Blend file requires an add-on that is not installed! {0}")]
+    MissingAddon(String),
+    #[error("Blender exit with error code {0}")]
+    ExitCode(i32),
+}
+
+impl From<Fatal> for BlenderError {
+    fn from(fatal: Fatal) -> Self {
+        match fatal {
+            Fatal::OutOfMemory(msg) => BlenderError::OutOfMemory(msg),
+            Fatal::Crashed(msg) => BlenderError::Crashed(msg),
+            Fatal::MissingAddon(msg) => BlenderError::MissingAddon(msg),
+        }
+    }
+}
+
+impl BlenderError {
+    /// Map the exit status of blender process into an error. Returns None if blender exit successfully.
+    pub fn from_exit_status(status: &ExitStatus) -> Option<Self> {
+        // windows report crashes as NTSTATUS codes e.g. 0xC0000005 (access violation), 0xC00000FD (stack overflow)
+        const WIN_ACCESS_VIOLATION: i32 = 0xC0000005_u32 as i32;
+        const WIN_STACK_OVERFLOW: i32 = 0xC00000FD_u32 as i32;
+
+        match status.code() {
+            Some(0) => None,
+            Some(code @ (WIN_ACCESS_VIOLATION | WIN_STACK_OVERFLOW)) => {
+                Some(BlenderError::Crashed(format!("exit code {code:#X}")))
+            }
+            Some(code) => Some(BlenderError::ExitCode(code)),
+            // unix does not give us exit code if the process was killed by a signal (SIGSEGV, SIGABRT, etc.)
+            None => Some(BlenderError::Crashed(status.to_string())),
+        }
+    }
+}
+
+// The frame blender is working on, and the files saved for it so far.
+#[derive(Debug, Default)]
+struct FrameOutput {
+    frame: Frame,
+    saved: Vec<PathBuf>,
 }
 
 /// Blender structure to hold path to executable and version of blender installed.
@@ -134,6 +187,53 @@ impl Ord for Blender {
 }
 
 impl Blender {
+    // Translate the parsed log event into status for the render handle.
+    // The final Status::Exit is sent once the process actually exits, so we can report the exit code before that.
+    fn to_status(event: LogEvent, output: &mut FrameOutput) -> Option<Status> {
+        let status = match event {
+            LogEvent::Progress(progress) => {
+                output.frame = progress.frame;
+                Status::Running { progress }
+            }
+            // hold on to the files until render.py tells us the frame is done, the passes are saved after the image.
+            LogEvent::Saved(path) => {
+                let status = format!("Saved {}", path.display());
+                if !output.saved.contains(&path) {
+                    output.saved.push(path);
+                }
+                Status::Log { status }
+            }
+            LogEvent::FrameComplete => {
+                let frame = output.frame;
+                let mut saved = std::mem::take(&mut output.saved).into_iter();
+                match saved.next() {
+                    Some(result) => Status::Completed {
+                        frame,
+                        result,
+                        passes: saved.collect(),
+                    },
+                    None => Status::Warning {
+                        message: format!("Frame {frame} finished without saving an image!"),
+                    },
+                }
+            }
+            LogEvent::FrameTime { elapsed, saving } => {
+                let mut progress =
+                    RenderProgress::new(output.frame, RenderPhase::Saving { duration: saving });
+                progress.elapsed = Some(elapsed);
+                Status::Running { progress }
+            }
+            // Strange how this was thrown, but doesn't report back to this program?
+            LogEvent::Exception(line) => Status::Error(BlenderError::PythonError(line)),
+            LogEvent::Fatal(fatal) => Status::Error(fatal.into()),
+            LogEvent::Warning(message) => Status::Warning { message },
+            LogEvent::Error(line) => Status::Error(BlenderError::RenderError(line)),
+            LogEvent::Quit => return None,
+            LogEvent::Message(status) => Status::Log { status },
+        };
+        Some(status)
+    }
+
     /* Private method impl */
 
     /// Create a new blender struct with provided path and version. This does not checked and enforced!
@@ -175,11 +275,36 @@ impl Blender {
         Err(BlenderError::ExecutableInvalid)
     }
 
+    /// Write the python script into the config directory and return the path to the script.
+    /// The script is rewritten whenever it differs from the one bundled with this library, so changes to the script are picked up after an upgrade.
+    fn install_script(name: &str, data: &[u8]) -> Result<PathBuf, BlenderError> {
+        let config_path = Self::get_config_path();
+        fs::create_dir_all(&config_path).map_err(|e| BlenderError::ProcessError(e.to_string()))?;
+        let script_path = config_path.join(name);
+        let is_current = fs::read(&script_path).is_ok_and(|content| content.eq(data));
+        if !is_current {
+            fs::write(&script_path, data).map_err(|e| BlenderError::ProcessError(e.to_string()))?;
+        }
+        Ok(script_path)
+    }
+
     /// Fetch the configuration path for blender. This is used to store temporary files and configuration files for blender.
     pub fn get_config_path() -> PathBuf {
         dirs::config_dir().unwrap().join("BlendFarm")
     }
 
+    /// Ask blender which cycles backends it can render with on this machine, CPU is always one of them.
+    /// This starts blender in the background to run probe.py, so expect it to take a few seconds.
+    pub fn devices(&self) -> Result<Vec<Device>, BlenderError> {
+        let script_path = Self::install_script("probe.py", include_bytes!("./probe.py"))?;
+        let output = Command::new(&self.executable)
+            .args(["--factory-startup", "-noaudio", "-b", "-P"])
+            .arg(&script_path)
+            .output()
+            .map_err(|e| BlenderError::ProcessError(e.to_string()))?;
+        Ok(Device::from_probe(&String::from_utf8_lossy(&output.stdout)))
+    }
+
     /// Return the executable path to blender (Entry point for CLI)
     pub fn get_executable(&self) -> &Path {
         &self.executable
@@ -327,105 +452,293 @@ impl Blender {
             }
         };
 
-        let mut scenes: Vec<String> = Vec::new();
-        let mut cameras: Vec<String> = Vec::new();
-
-        let mut frame_start: i32 = 0;
-        let mut frame_end: i32 = 0;
-        let mut render_width: i32 = 0;
-        let mut render_height: i32 = 0;
-        let mut fps: u16 = 0;
-        let mut samples: i32 = 0;
-        let mut output: PathBuf = PathBuf::new();
-        let mut engine = String::from("");
-
-        // this denotes how many scene objects there are.
-        for obj in blend.instances_with_code(*b"SC") {
-            let scene = obj.get("id").get_string("name").replace("SC", ""); // not the correct name usage?
-                                                                            // get render data
-            let render = &obj.get("r");
-
-            engine = render.get_string("engine"); // will show BLENDER_EEVEE_NEXT properly
-            samples = obj.get("eevee").get_i32("taa_render_samples");
-
-            // Issue, Cannot find cycles info! Blender show that it should be here under SCscene, just like eevee, but I'm looking it over and over and it's not there? Where is cycle?
-            // Use this for development only!
-            // Self::explore_value(&obj.get("eevee"));
-
-            render_width = render.get_i32("xsch");
-            render_height = render.get_i32("ysch");
-            frame_start = render.get_i32("sfra");
-            frame_end = render.get_i32("efra");
-            fps = render.get_u16("frs_sec");
-            output = render
-                .get_string("pic")
-                .parse::<PathBuf>()
-                .map_err(|e| BlenderError::PythonError(e.to_string()))?;
-
-            scenes.push(scene);
-        }
+        // python defined settings (like Cycles) are only saved when they differ from the default.
+        let default_samples = match major < 3 {
+            true => 128,
+            false => 4096,
+        };
+        let scenes = blend
+            .instances_with_code(*b"SC")
+            .map(|obj| Self::peek_scene(&obj, default_samples))
+            .collect::<Vec<_>>();
+
+        // the scene blender opens with is the one shown in the window.
+        let selected_scene = blend
+            .instances_with_code(*b"WM")
+            .flat_map(|wm| wm.get_iter("windows").collect::<Vec<_>>())
+            .find(|window| window.is_valid("scene"))
+            .map(|window| id_name(&window.get("scene")))
+            .or_else(|| scenes.first().map(|scene| scene.name.clone()))
+            .unwrap_or_default();
+
+        let result = BlenderPeekResponse {
+            last_version: blend_version,
+            scenes,
+            selected_scene,
+        };
+
+        Ok(result)
+    }
+
+    // Read the render settings of a single scene.
+    fn peek_scene(obj: &Instance, default_samples: i32) -> BlenderScene {
+        let render = obj.get("r");
+        let engine = render.get_string("engine");
+
+        // Cycles keeps its settings in the scene's custom properties, under "cycles".
+        let cycles = match obj.get("id").is_valid("properties") {
+            true => id_property(&obj.get("id").get("properties"), "cycles"),
+            false => None,
+        };
+        let cycles_int = |name: &str, default: i32| {
+            cycles
+                .as_ref()
+                .and_then(|group| id_property(group, name))
+                .map(|property| property.get("data").get_i32("val"))
+                .unwrap_or(default)
+        };
+        let (samples, denoiser) = match engine.as_str() {
+            "CYCLES" => {
+                // denoiser enum from cycles' properties.py
+                let denoiser = match cycles_int("denoiser", 4) {
+                    2 => "OPTIX",
+                    _ => "OPENIMAGEDENOISE",
+                };
+                let denoise = cycles_int("use_denoising", 1) != 0;
+                (
+                    cycles_int("samples", default_samples),
+                    denoise.then(|| denoiser.to_owned()),
+                )
+            }
+            "BLENDER_EEVEE" | "BLENDER_EEVEE_NEXT" => {
+                (obj.get("eevee").get_i32("taa_render_samples"), None)
+            }
+            _ => (0, None),
+        };
 
-        // interesting - I'm picking up the wrong camera here?
-        for obj in blend.instances_with_code(*b"CA") {
-            let camera = obj.get("id").get_string("name").replace("CA", "");
-            cameras.push(camera);
+        // cameras are the camera objects in the scene's collections.
+        let mut cameras = Vec::new();
+        if obj.is_valid("master_collection") {
+            collect_cameras(&obj.get("master_collection"), &mut cameras);
         }
+        cameras.sort();
+        cameras.dedup();
+        let active_camera = match obj.is_valid("camera") {
+            true => id_name(&obj.get("camera")),
+            false => cameras.first().cloned().unwrap_or_default(),
+        };
 
-        let selected_camera = cameras.get(0).unwrap_or(&"".to_owned()).to_owned();
-        let selected_scene = scenes.get(0).unwrap_or(&"".to_owned()).to_owned();
+        let view_layers = obj
+            .get_iter("view_layers")
+            .map(|layer| layer.get_string("name"))
+            .collect();
 
-        // parse i32 into u16
-        let result = BlenderPeekResponse {
-            last_version: blend_version,
-            render_width,
-            render_height,
-            frame_start,
-            frame_end,
+        // frs_sec_base is 1.001 for NTSC rates, e.g. 30 / 1.001 = 29.97
+        let fps_base = render.get_f32("frs_sec_base");
+        let fps = match fps_base > 0.0 {
+            true => (render.get_u16("frs_sec") as f32 / fps_base).round() as u16,
+            false => render.get_u16("frs_sec"),
+        };
+
+        BlenderScene {
+            name: id_name(obj),
+            engine,
+            render_width: render.get_i32("xsch"),
+            render_height: render.get_i32("ysch"),
+            resolution_percentage: render.get_u16("size"),
+            frame_start: render.get_i32("sfra"),
+            frame_end: render.get_i32("efra"),
+            frame_step: render.get_i32("frame_step").max(1),
             fps,
             samples,
+            denoiser,
             cameras,
-            selected_camera,
-            scenes,
-            selected_scene,
-            engine,
-            output,
+            active_camera,
+            view_layers,
+            output: PathBuf::from(render.get_string("pic")),
+            format: image_format(render.get("im_format").get_u8("imtype")).to_owned(),
+        }
+    }
+
+    /// List the files outside the blend file that the project needs to render: textures, linked libraries, volumes, caches and bakes.
+    /// Linked libraries are read as well, their paths are relative to the library. Packed files are left out, they're inside the blend file already.
+    /// See dependency.rs
+    pub fn dependencies(blend_file: &Path) -> Result<Vec<Dependency>, BlenderError> {
+        let mut visited = HashSet::new();
+        let mut found = Vec::new();
+        Self::collect_dependencies(blend_file, &mut visited, &mut found)?;
+
+        // the same texture can be used by more than one library, only send it once.
+        let mut seen = HashSet::new();
+        found.retain(|dependency: &Dependency| seen.insert(dependency.path.clone()));
+        Ok(found)
+    }
+
+    fn collect_dependencies(
+        blend_file: &Path,
+        visited: &mut HashSet<PathBuf>,
+        found: &mut Vec<Dependency>,
+    ) -> Result<(), BlenderError> {
+        if !visited.insert(blend_file.to_path_buf()) {
+            return Ok(());
+        }
+        let blend = Blend::from_path(blend_file)
+            .map_err(|_| BlenderError::InvalidFile("Received BlenderParseError".to_owned()))?;
+        let base_dir = blend_file.parent().unwrap_or(Path::new(""));
+
+        // Blender 3.0 renamed name to filepath, older files still have name.
+        let file_path = |obj: &Instance| match obj.is_valid("filepath") {
+            true => obj.get_string("filepath"),
+            false => obj.get_string("name"),
+        };
+        let is_packed = |obj: &Instance| {
+            obj.is_valid("packedfile")
+                || (obj.is_valid("packedfiles") && obj.get_iter("packedfiles").next().is_some())
+        };
+        let mut add = |kind: DependencyKind, files: Vec<PathBuf>, relative: bool| {
+            found.extend(files.into_iter().map(|path| Dependency {
+                kind,
+                path,
+                relative,
+            }));
+        };
+        // a single file we expect to be there. Let the user know if it isn't, the render will be missing it.
+        let existing = |path: PathBuf| match path.is_file() {
+            true => vec![path],
+            false => {
+                eprintln!("Missing file {path:?} used by {blend_file:?}");
+                Vec::new()
+            }
         };
 
-        Ok(result)
+        for image in blend.instances_with_code(*b"IM") {
+            if is_packed(&image) {
+                continue;
+            }
+            let Some((path, relative)) = dependency::resolve(base_dir, &file_path(&image)) else {
+                continue;
+            };
+            // 2: image sequence, 6: UDIM tiles. Generated and viewer images have no file.
+            let files = match image.get_u16("source") {
+                2 | 6 => dependency::expand_sequence(&path),
+                4 | 5 => continue,
+                _ => existing(path),
+            };
+            add(DependencyKind::Image, files, relative);
+        }
+
+        let mut libraries = Vec::new();
+        for library in blend.instances_with_code(*b"LI") {
+            if is_packed(&library) {
+                continue;
+            }
+            if let Some((path, relative)) = dependency::resolve(base_dir, &file_path(&library)) {
+                libraries.push(path.clone());
+                add(DependencyKind::Library, existing(path), relative);
+            }
+        }
+
+        for volume in blend.instances_with_code(*b"VO") {
+            if is_packed(&volume) {
+                continue;
+            }
+            if let Some((path, relative)) = dependency::resolve(base_dir, &file_path(&volume)) {
+                let files = match volume.get_u8("is_sequence") {
+                    0 => existing(path),
+                    _ => dependency::expand_sequence(&path),
+                };
+                add(DependencyKind::Volume, files, relative);
+            }
+        }
+
+        // alembic and usd caches
+        for cache in blend.instances_with_code(*b"CF") {
+            if let Some((path, relative)) = dependency::resolve(base_dir, &file_path(&cache)) {
+                add(DependencyKind::Cache, existing(path), relative);
+            }
+        }
+
+        // fluid simulations bake into the cache directory of the domain.
+        for obj in blend.instances_with_code(*b"OB") {
+            for modifier in obj.get_iter("modifiers") {
+                if !modifier.is_valid("domain") {
+                    continue;
+                }
+                let cache_dir = modifier.get("domain").get_string("cache_directory");
+                if let Some((dir, relative)) = dependency::resolve(base_dir, &cache_dir) {
+                    add(DependencyKind::Bake, dependency::files_in(&dir), relative);
+                }
+            }
+        }
+
+        // point caches saved to disk go next to the blend file by default.
+        if let Some(stem) = blend_file.file_stem().and_then(|s| s.to_str()) {
+            let dir = base_dir.join(format!("blendcache_{stem}"));
+            add(DependencyKind::Bake, dependency::files_in(&dir), true);
+        }
+
+        for library in libraries.into_iter().filter(|path| path.is_file()) {
+            if let Err(e) = Self::collect_dependencies(&library, visited, found) {
+                eprintln!("Unable to read linked library {library:?}! {e:?}");
+            }
+        }
+        Ok(())
     }
 
     /// Render one frame - can we make the assumption that ProjectFile may have configuration predefined Or is that just a system global setting to apply on?
+    /// Returns a [RenderHandle] to receive status from blender, cancel, pause, or wait for the render to finish.
     /// # Examples
     /// ```
     /// use blender::Blender;
     /// use blender::args::Args;
     /// let blender = Blender::from_executable("path/to/blender").unwrap();
     /// let args = Args::new(PathBuf::from("path/to/project.blend"), PathBuf::from("path/to/output.png"));
-    /// let final_output = blender.render(&args).unwrap();
+    /// let handle = blender.render(&args).unwrap();
+    /// while let Ok(status) = handle.recv() { println!("{status:?}") }
     /// ```
+    /// # Errors
+    /// If blender runs into an error it can't recover from (see [crate::log_parser::Fatal]), or exits with a non-zero code,
+    /// the handle receives [Status::Error] followed by [Status::Exit] so the caller knows the frame failed.
     // so instead of just returning the string of render result or blender error, we'll simply use the single producer to produce result from this class.
-    pub async fn render<F>(&self, args: Args, get_next_frame: F) -> Receiver<Status>
+    pub async fn render<F>(
+        &self,
+        args: Args,
+        get_next_frame: F,
+    ) -> Result<RenderHandle, BlenderError>
     where
         F: Fn() -> Option<i32> + Send + Sync + 'static,
     {
         let (rx, tx) = mpsc::channel::<Status>();
         let (signal, listener) = mpsc::channel::<Status>();
-        let executable = self.executable.clone();
+        let paused = Arc::new(AtomicBool::new(false));
+        let cancelled = Arc::new(AtomicBool::new(false));
 
-        let blend_info = Self::peek(&args.file)
-            .await
-            .expect("Fail to parse blend file!"); // TODO: Need to clean this error up a bit.
+        let blend_info = Self::peek(&args.file).await?;
+
+        // nothing has started yet, so there's nothing to clean up if this fails.
+        let script_path = Self::install_script("render.py", include_bytes!("./render.py"))?;
 
         // this is the only place used for BlenderRenderSetting... thoughts?
         let settings = BlenderRenderSetting::parse_from(&args, &blend_info);
         let global_settings = Arc::new(settings);
 
-        let socket = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 8081);
         let mut server = Server::new();
 
-        server.register_simple("next_render_queue", move |_i: i32| match get_next_frame() {
-            Some(frame) => Ok(frame),
-            None => Err(Fault::new(1, "No more frames to render!")),
+        let (is_paused, is_cancelled) = (paused.clone(), cancelled.clone());
+        server.register_simple("next_render_queue", move |_i: i32| {
+            // hold back the next frame until the render is resumed or cancelled.
+            while is_paused.load(Ordering::SeqCst) && !is_cancelled.load(Ordering::SeqCst) {
+                sleep(Duration::from_millis(250));
+            }
+
+            if is_cancelled.load(Ordering::SeqCst) {
+                return Err(Fault::new(2, "Render was cancelled!"));
+            }
+
+            match get_next_frame() {
+                Some(frame) => Ok(frame),
+                None => Err(Fault::new(1, "No more frames to render!")),
+            }
         });
 
         server.register_simple("fetch_info", move |_i: i32| {
@@ -433,120 +746,174 @@ impl Blender {
             Ok(setting)
         });
 
-        let bind_server = server
-            .bind(&socket)
-            .expect("Unable to open socket for xml_rpc!");
+        // each render gets its own port, so multiple renders (or blender add-ons) on this machine won't fight over the same socket.
+        // Port 0 lets the operating system pick a free one while we bind, we read back which one it was for render.py.
+        let socket = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0);
+        let bind_server = server.bind(&socket).map_err(|e| {
+            BlenderError::ProcessError(format!("Unable to open socket for xml_rpc! {e:?}"))
+        })?;
+        let port = bind_server.local_addr().port();
+
+        // blender ignores anything after "--", which is how we pass our own arguments to render.py
+        let col = vec![
+            "--factory-startup".to_string(),
+            "-noaudio".to_owned(),
+            "-b".to_owned(),
+            args.file.to_str().unwrap().to_string(),
+            // exit with an error if render.py throws, so a failed frame never looks like a finished one.
+            "--python-exit-code".to_owned(),
+            "1".to_owned(),
+            "-P".to_owned(),
+            script_path.to_str().unwrap().to_string(),
+            "--".to_owned(),
+            "--port".to_owned(),
+            port.to_string(),
+        ];
+
+        // spawn the process here so the handle can hold on to the child and kill it later.
+        let mut child = match Command::new(&self.executable)
+            .args(col)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(e) => return Err(BlenderError::ProcessError(e.to_string())),
+        };
+
+        // without the output we can't tell how the render went, don't leave blender running in the dark.
+        let (Some(stdout), Some(stderr)) = (child.stdout.take(), child.stderr.take()) else {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(BlenderError::ProcessError(
+                "Unable to read blender's output!".to_owned(),
+            ));
+        };
+        let child = Arc::new(Mutex::new(child));
 
-        // spin up XML-RPC server
-        spawn(async move {
+        // spin up XML-RPC server, only once blender is running so every early return above leaves nothing behind.
+        // The socket is already bound, blender's first call waits in the backlog until we poll.
+        // Polling blocks (and next_render_queue sleeps while paused), so it gets a thread of its own instead of a runtime thread.
+        thread::spawn(move || {
             loop {
-                // if the program shut down or if we've completed the render, then we should stop the server
+                // stop once the render is over, or everyone who could tell us so is gone.
                 match listener.try_recv() {
-                    Ok(Status::Exit) => break,
+                    Ok(Status::Exit) | Err(mpsc::TryRecvError::Disconnected) => break,
                     _ => bind_server.poll(),
                 }
             }
         });
 
-        spawn(async move {
-            let script_path = Blender::get_config_path().join("render.py");
-            if !script_path.exists() {
-                let data = include_bytes!("./render.py");
-                fs::write(&script_path, data).unwrap();
+        // stderr is where blender dumps crash reports, CUDA errors, and python tracebacks.
+        let err_sender = rx.clone();
+        let stderr_reader = thread::spawn(move || {
+            let reader = BufReader::new(stderr);
+            let mut output = FrameOutput::default();
+            for line in reader.lines().map_while(Result::ok) {
+                let event = parse_error_line(&line);
+                if let Some(msg) = event.and_then(|e| Self::to_status(e, &mut output)) {
+                    let _ = err_sender.send(msg);
+                }
             }
+        });
 
-            let col = vec![
-                "--factory-startup".to_string(),
-                "-noaudio".to_owned(),
-                "-b".to_owned(),
-                args.file.to_str().unwrap().to_string(),
-                "-P".to_owned(),
-                script_path.to_str().unwrap().to_string(),
-            ];
-
-            let stdout = Command::new(executable)
-                .args(col)
-                .stdout(Stdio::piped())
-                .spawn()
-                .unwrap()
-                .stdout
-                .unwrap();
-
+        // the handle holds its own copy of the senders to emit the final status on cancel.
+        let (status_sender, server_signal) = (rx.clone(), signal.clone());
+        let (process, is_cancelled) = (child.clone(), cancelled.clone());
+        // reading stdout blocks until blender exits, so keep this off the async runtime.
+        thread::spawn(move || {
             let reader = BufReader::new(stdout);
-            let mut frame: i32 = 0;
+            let mut output = FrameOutput::default();
 
             // parse stdout for human to read
-            reader.lines().for_each(|line| {
-                if let Ok(line) = line {
-                    match line {
-                        line if line.contains("Fra:") => {
-                            let col = line.split('|').collect::<Vec<&str>>();
-
-                            // this seems a bit expensive?
-                            let init = col[0].split(" ").next();
-                            if let Some(value) = init {
-                                frame = value.replace("Fra:", "").parse().unwrap_or(1);
-                            }
-                            let last = col.last().unwrap().trim();
-                            let slice = last.split(' ').collect::<Vec<&str>>();
-                            let msg = match slice[0] {
-                                "Rendering" => {
-                                    let current = slice[1].parse::<f32>().unwrap();
-                                    let total = slice[3].parse::<f32>().unwrap();
-                                    let percentage = current / total * 100.0;
-                                    let render_perc = format!("{} {:.2}%", last, percentage);
-                                    Status::Running {
-                                        status: render_perc,
-                                    }
-                                }
-                                "Sample" => Status::Running {
-                                    status: last.to_owned(),
-                                },
-                                _ => Status::Log {
-                                    status: last.to_owned(),
-                                },
-                            };
-                            rx.send(msg).unwrap();
-                        }
-                        line if line.contains("Saved:") => {
-                            let location = line.split('\'').collect::<Vec<&str>>();
-                            let result = PathBuf::from(location[1]);
-                            rx.send(Status::Completed { frame, result }).unwrap();
-                        }
-                        // Strange how this was thrown, but doesn't report back to this program?
-                        line if line.contains("EXCEPTION:") => {
-                            signal.send(Status::Exit).unwrap();
-                            rx.send(Status::Error(BlenderError::PythonError(line.to_owned())))
-                                .unwrap();
-                        }
-                        line if line.contains("Warning:") => {
-                            rx.send(Status::Warning {
-                                message: line.to_owned(),
-                            })
-                            .unwrap();
-                        }
-                        line if line.contains("Error:") => {
-                            let msg = Status::Error(BlenderError::RenderError(line.to_owned()));
-                            rx.send(msg).unwrap();
-                        }
-                        line if line.contains("Blender quit") => {
-                            signal.send(Status::Exit).unwrap();
-                            rx.send(Status::Exit).unwrap();
-                        }
-                        line if !line.is_empty() => {
-                            let msg = Status::Running {
-                                status: line.to_owned(),
-                            };
-                            rx.send(msg).unwrap();
-                        }
-                        _ => {
-                            // Only empty log entry would show up here...
-                        }
-                    };
-                };
-            });
+            for line in reader.lines().map_while(Result::ok) {
+                if let Some(msg) = parse_line(&line).and_then(|e| Self::to_status(e, &mut output)) {
+                    let _ = rx.send(msg);
+                }
+            }
+
+            // make sure everything from stderr made it through before we report how blender exit.
+            let _ = stderr_reader.join();
+            let result = wait_for_exit(&process);
+
+            // cancel() already killed the process and sent the final exit status.
+            if is_cancelled.load(Ordering::SeqCst) {
+                return;
+            }
+
+            let error = match result {
+                Ok(status) => BlenderError::from_exit_status(&status),
+                Err(e) => Some(e),
+            };
+            if let Some(e) = error {
+                let _ = rx.send(Status::Error(e));
+            }
+
+            let _ = signal.send(Status::Exit);
+            let _ = rx.send(Status::Exit);
         });
-        tx
+
+        Ok(RenderHandle::new(
+            tx,
+            status_sender,
+            server_signal,
+            child,
+            paused,
+            cancelled,
+        ))
+    }
+}
+
+// Name of the data block without the two letter code in front, e.g. SCScene is Scene.
+fn id_name(obj: &Instance) -> String {
+    let name = obj.get("id").get_string("name");
+    name.get(2..).unwrap_or_default().to_owned()
+}
+
+// Find a custom property by name inside a property group.
+fn id_property<'a>(group: &Instance<'a>, name: &str) -> Option<Instance<'a>> {
+    group
+        .get("data")
+        .get_iter("group")
+        .find(|property| property.get_string("name") == name)
+}
+
+// Walk the collection and its children for camera objects.
+fn collect_cameras(collection: &Instance, cameras: &mut Vec<String>) {
+    for item in collection.get_iter("gobject") {
+        // 11 is OB_CAMERA
+        if item.is_valid("ob") && item.get("ob").get_u16("type") == 11 {
+            cameras.push(id_name(&item.get("ob")));
+        }
+    }
+    for child in collection.get_iter("children") {
+        if child.is_valid("collection") {
+            collect_cameras(&child.get("collection"), cameras);
+        }
+    }
+}
+
+// Blender's R_IMF_IMTYPE values, named the way python sets image_settings.file_format
+fn image_format(imtype: u8) -> &'static str {
+    match imtype {
+        0 => "TARGA",
+        1 => "IRIS",
+        4 => "JPEG",
+        14 => "TARGA_RAW",
+        15 => "AVI_RAW",
+        16 => "AVI_JPEG",
+        17 => "PNG",
+        20 => "BMP",
+        21 => "HDR",
+        22 => "TIFF",
+        23 => "OPEN_EXR",
+        24 => "FFMPEG",
+        26 => "CINEON",
+        27 => "DPX",
+        28 => "OPEN_EXR_MULTILAYER",
+        30 => "JPEG2000",
+        35 => "WEBP",
+        _ => "UNKNOWN",
     }
 }
 
@@ -1,4 +1,5 @@
 pub mod blender;
+pub mod log_parser;
 pub mod manager;
 pub mod models;
 pub mod page_cache;
@@ -0,0 +1,500 @@
+/*
+    Developer blog:
+    - Blender prints progress to stdout in a pipe delimited format. The layout is slightly different between engines and version of blender.
+        Cycles:    Fra:1 Mem:159.46M (Peak 159.46M) | Time:00:01.45 | Remaining:00:08.25 | Mem:10.84M, Peak:10.84M | Scene, ViewLayer | Sample 1/128
+        Cycles<3:  Fra:1 Mem:27.42M (Peak 27.75M) | Time:00:00.58 | Remaining:00:03.28 | Mem:8.59M, Peak:8.59M | Scene, RenderLayer | Rendered 3/16 Tiles, Sample 16/128
+        EEVEE:     Fra:1 Mem:58.73M (Peak 70.92M) | Time:00:00.63 | Rendering 1 / 64 samples
+    - Nothing here should panic. If we can't make sense of the line, we hand it back as a plain message instead.
+    - The same parser is used for stderr. Blender dumps crash reports, CUDA errors and missing add-on messages there, which we classify as fatal
+        so the host can tell "ran out of VRAM" apart from "blend file needs an add-on we don't have".
+    - Only stderr is checked for those, and only against the exact messages blender prints. Stdout echoes scene and object names,
+        and add-ons print whatever they like, a cube called "out of memory" shouldn't fail the frame.
+*/
+use crate::blender::Frame;
+use crate::models::render_progress::{RenderPhase, RenderProgress};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Failures that prevent blender from finishing the render.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fatal {
+    /// Host or GPU ran out of memory. E.g. "CUDA error: Out of memory in cuMemAlloc"
+    OutOfMemory(String),
+    /// Blender itself crashed. E.g. "Segmentation fault (core dumped)"
+    Crashed(String),
+    /// Blend file or driver script needs an add-on that isn't installed.
+    MissingAddon(String),
+}
+
+/// Event parsed from a single line of blender's stdout or stderr.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LogEvent {
+    Progress(RenderProgress),
+    /// Blender finish writing the image to disk. render.py reports each render pass it wrote the same way.
+    Saved(PathBuf),
+    /// render.py is done with the frame, every file of the frame was saved before this.
+    FrameComplete,
+    /// Total time to render the frame, and how long it took to save the image.
+    FrameTime {
+        elapsed: Duration,
+        saving: Duration,
+    },
+    /// Python exception thrown from render.py
+    Exception(String),
+    Warning(String),
+    Error(String),
+    Quit,
+    Fatal(Fatal),
+    Message(String),
+}
+
+// Cycles cancels the render with this on the progress line. E.g. "Fra:1 ... | Cancel | System is out of GPU memory"
+const GPU_OUT_OF_MEMORY: &str = "System is out of GPU memory";
+// Messages are matched as blender, the GPU drivers and python print them, case and all.
+// E.g. "CUDA error: Out of memory in cuMemAlloc(&device_pointer, size), line 568"
+const OUT_OF_MEMORY: [&str; 5] = [
+    GPU_OUT_OF_MEMORY,
+    "Out of memory in cuMemAlloc",
+    "CUDA_ERROR_OUT_OF_MEMORY",
+    "hipErrorOutOfMemory",
+    "std::bad_alloc",
+];
+// Start of the line, the shell or blender's crash handler prints these on their own.
+const CRASHED: [&str; 4] = [
+    "Segmentation fault",
+    "Aborted (core dumped)",
+    "Illegal instruction",
+    "Error   : EXCEPTION_ACCESS_VIOLATION",
+];
+// Last line of the traceback when python can't import the add-on, and blender's own message that follows it.
+const MISSING_ADDON: [&str; 2] = ["ModuleNotFoundError: No module named", "Add-on not loaded:"];
+
+/// Check whether the stderr line tells us blender can't finish the render.
+pub fn classify_fatal(line: &str) -> Option<Fatal> {
+    let line = line.trim();
+    let starts = |patterns: &[&str]| patterns.iter().any(|p| line.starts_with(p));
+    // blender writes the crash report and tells us where. E.g. "Writing: /tmp/untitled.crash.txt"
+    let crash_report = line.starts_with("Writing:") && line.ends_with(".crash.txt");
+
+    if OUT_OF_MEMORY.iter().any(|p| line.contains(p)) {
+        Some(Fatal::OutOfMemory(line.to_owned()))
+    } else if starts(&CRASHED) || crash_report {
+        Some(Fatal::Crashed(line.to_owned()))
+    } else if starts(&MISSING_ADDON) {
+        Some(Fatal::MissingAddon(line.to_owned()))
+    } else {
+        None
+    }
+}
+
+// Parse memory usage into megabytes. E.g. "146.87M", "1.25G", "512K"
+fn parse_memory(value: &str) -> Option<f32> {
+    let value = value.trim();
+    let (number, unit) = value.split_at(value.len().checked_sub(1)?);
+    let number = number.parse::<f32>().ok()?;
+    match unit {
+        "K" => Some(number / 1024.0),
+        "M" => Some(number),
+        "G" => Some(number * 1024.0),
+        _ => None,
+    }
+}
+
+// Parse blender's time format. E.g. "00:08.25" or "01:02:03.50"
+fn parse_time(value: &str) -> Option<Duration> {
+    let mut seconds = 0.0;
+    for part in value.trim().split(':') {
+        seconds = seconds * 60.0 + part.parse::<f64>().ok()?;
+    }
+    if seconds.is_finite() && seconds >= 0.0 {
+        Some(Duration::from_secs_f64(seconds))
+    } else {
+        None
+    }
+}
+
+// Parse "current/total" or "current / total" counter
+fn parse_counter(value: &str) -> Option<(u32, u32)> {
+    let (current, total) = value.split_once('/')?;
+    let current = current.trim().parse().ok()?;
+    // ignore anything trailing the total e.g. "16/128 samples" or "3/16 Tiles"
+    let total = total.split_whitespace().next()?.parse().ok()?;
+    Some((current, total))
+}
+
+fn parse_phase(message: &str) -> RenderPhase {
+    let message = message.trim();
+    let counter = |prefix: &str| {
+        message
+            .strip_prefix(prefix)
+            .and_then(|rest| parse_counter(rest.split(',').next().unwrap_or(rest)))
+    };
+
+    if let Some((current, total)) = counter("Sample ") {
+        return RenderPhase::Sample { current, total };
+    }
+    if let Some((current, total)) = counter("Rendering ") {
+        return RenderPhase::Sample { current, total };
+    }
+    if let Some((current, total)) = counter("Rendered ").or(counter("Path Tracing Tile ")) {
+        return RenderPhase::Tile { current, total };
+    }
+
+    match message {
+        "Finished" => RenderPhase::Finished,
+        msg if msg.starts_with("Denoising") => RenderPhase::Denoising,
+        msg if msg.starts_with("Compositing") => {
+            let message = msg
+                .trim_start_matches("Compositing")
+                .trim_start_matches([' ', '|'])
+                .to_owned();
+            RenderPhase::Compositing { message }
+        }
+        msg => RenderPhase::Preparing {
+            message: msg.to_owned(),
+        },
+    }
+}
+
+// Parse the progress line that starts with "Fra:"
+fn parse_progress(line: &str) -> Option<RenderProgress> {
+    let mut segments = line.split('|').map(str::trim);
+
+    // first segment: Fra:1 Mem:146.87M (Peak 147.80M)
+    let header = segments.next()?;
+    let mut tokens = header.split_whitespace();
+    let frame: Frame = tokens.next()?.strip_prefix("Fra:")?.parse().ok()?;
+    let mut progress = RenderProgress::new(frame, RenderPhase::Finished);
+
+    let header = header.replace(['(', ')'], "");
+    let mut tokens = header.split_whitespace();
+    while let Some(token) = tokens.next() {
+        if let Some(memory) = token.strip_prefix("Mem:") {
+            progress.memory = parse_memory(memory);
+        } else if token == "Peak" {
+            progress.peak_memory = tokens.next().and_then(parse_memory);
+        }
+    }
+
+    // The header is followed by key value pairs and "Scene, ViewLayer". Whatever comes after that is the phase blender is in.
+    // The phase may span over multiple segments. E.g. "Synchronizing object | Cube" or "Compositing | Tile 1-4"
+    let rest = segments.collect::<Vec<&str>>();
+    let mut phase_index = rest.len();
+    for (index, segment) in rest.iter().enumerate() {
+        if let Some(time) = segment.strip_prefix("Time:") {
+            progress.elapsed = parse_time(time);
+        } else if let Some(time) = segment.strip_prefix("Remaining:") {
+            progress.remaining = parse_time(time);
+        } else if segment.starts_with("Mem:") {
+            // device memory, we already have the host memory usage.
+        } else if let Some((scene, view_layer)) = segment
+            .split_once(", ")
+            .filter(|_| progress.scene.is_none() && index + 1 < rest.len())
+        {
+            progress.scene = Some(scene.to_owned());
+            progress.view_layer = Some(view_layer.to_owned());
+        } else {
+            phase_index = index;
+            break;
+        }
+    }
+
+    progress.phase = match rest.get(phase_index..) {
+        Some(phase) if !phase.is_empty() => parse_phase(&phase.join(" | ")),
+        // We only received the header, nothing more we can tell.
+        _ => RenderPhase::Preparing {
+            message: String::new(),
+        },
+    };
+    Some(progress)
+}
+
+// Parse the line blender prints after saving the image. E.g. " Time: 00:06.02 (Saving: 00:00.19)"
+fn parse_frame_time(line: &str) -> Option<LogEvent> {
+    let rest = line.trim().strip_prefix("Time:")?;
+    let (elapsed, saving) = rest.split_once("(Saving:")?;
+    let elapsed = parse_time(elapsed)?;
+    let saving = parse_time(saving.trim_end_matches(')'))?;
+    Some(LogEvent::FrameTime { elapsed, saving })
+}
+
+/// Parse a single line of blender's stdout into a typed event.
+/// Returns None for empty lines.
+pub fn parse_line(line: &str) -> Option<LogEvent> {
+    let trimmed = line.trim();
+    if trimmed.is_empty() {
+        return None;
+    }
+
+    let event = match trimmed {
+        // the only fatal error blender reports on stdout, at the very end of the progress line.
+        line if line.starts_with("Fra:") && line.ends_with(GPU_OUT_OF_MEMORY) => {
+            LogEvent::Fatal(Fatal::OutOfMemory(line.to_owned()))
+        }
+        line if line.starts_with("Fra:") => match parse_progress(line) {
+            Some(progress) => LogEvent::Progress(progress),
+            None => LogEvent::Message(line.to_owned()),
+        },
+        line if line.starts_with("Saved:") => {
+            let path = line.split('\'').nth(1).unwrap_or_default();
+            match path.is_empty() {
+                true => LogEvent::Message(line.to_owned()),
+                false => LogEvent::Saved(PathBuf::from(path)),
+            }
+        }
+        line if line.starts_with("SUCCESS:") => LogEvent::FrameComplete,
+        line if line.starts_with("Time:") => {
+            parse_frame_time(line).unwrap_or_else(|| LogEvent::Message(line.to_owned()))
+        }
+        line if line.contains("EXCEPTION:") => LogEvent::Exception(line.to_owned()),
+        line if line.contains("Warning:") => LogEvent::Warning(line.to_owned()),
+        line if line.contains("Error:") => LogEvent::Error(line.to_owned()),
+        line if line.contains("Blender quit") => LogEvent::Quit,
+        line => LogEvent::Message(line.to_owned()),
+    };
+    Some(event)
+}
+
+/// Parse a single line of blender's stderr. Crash reports, GPU errors and python tracebacks end up here,
+/// so this is where we look for errors blender can't recover from. Anything else is parsed like stdout.
+pub fn parse_error_line(line: &str) -> Option<LogEvent> {
+    match classify_fatal(line) {
+        Some(fatal) => Some(LogEvent::Fatal(fatal)),
+        None => parse_line(line),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_fixture(content: &str) -> Vec<LogEvent> {
+        content.lines().filter_map(parse_line).collect()
+    }
+
+    fn progress(event: &LogEvent) -> &RenderProgress {
+        match event {
+            LogEvent::Progress(progress) => progress,
+            _ => panic!("Expected progress event, got {event:?}"),
+        }
+    }
+
+    #[test]
+    fn should_parse_cycles_log() {
+        let events = parse_fixture(include_str!("../tests/fixtures/cycles.log"));
+        assert_eq!(events.len(), 16);
+        assert_eq!(
+            events[0],
+            LogEvent::Message(
+                "Blender 4.2.1 LTS (hash 396f546c9d82 built 2024-08-19 11:21:52)".to_owned()
+            )
+        );
+
+        let sync = progress(&events[2]);
+        assert_eq!(sync.frame, 1);
+        assert_eq!(sync.memory, Some(146.87));
+        assert_eq!(sync.peak_memory, Some(147.80));
+        assert_eq!(sync.elapsed, Some(Duration::from_millis(390)));
+        assert_eq!(sync.remaining, None);
+        assert_eq!(sync.scene.as_deref(), Some("Scene"));
+        assert_eq!(sync.view_layer.as_deref(), Some("ViewLayer"));
+        assert_eq!(
+            sync.phase,
+            RenderPhase::Preparing {
+                message: "Synchronizing object | Cube".to_owned()
+            }
+        );
+
+        let sample = progress(&events[4]);
+        assert_eq!(
+            sample.phase,
+            RenderPhase::Sample {
+                current: 1,
+                total: 128
+            }
+        );
+        assert_eq!(sample.remaining, Some(Duration::from_millis(8250)));
+        assert_eq!(progress(&events[6]).percentage(), Some(100.0));
+        assert_eq!(progress(&events[7]).phase, RenderPhase::Denoising);
+        assert_eq!(progress(&events[8]).phase, RenderPhase::Finished);
+        assert_eq!(
+            progress(&events[9]).phase,
+            RenderPhase::Compositing {
+                message: "".to_owned()
+            }
+        );
+        assert_eq!(
+            progress(&events[10]).phase,
+            RenderPhase::Compositing {
+                message: "Determining resolution".to_owned()
+            }
+        );
+
+        assert_eq!(
+            events[11],
+            LogEvent::Saved(PathBuf::from("/tmp/RenderData/00001.png"))
+        );
+        assert_eq!(
+            events[12],
+            LogEvent::FrameTime {
+                elapsed: Duration::from_millis(6020),
+                saving: Duration::from_millis(190),
+            }
+        );
+        assert!(matches!(events[13], LogEvent::Warning(_)));
+        assert!(matches!(events[14], LogEvent::Error(_)));
+        assert_eq!(events[15], LogEvent::Quit);
+    }
+
+    #[test]
+    fn should_parse_legacy_cycles_tiles() {
+        let events = parse_fixture(include_str!("../tests/fixtures/cycles_tiles.log"));
+        let tiles = progress(&events[0]);
+        assert_eq!(tiles.frame, 12);
+        assert_eq!(
+            tiles.phase,
+            RenderPhase::Tile {
+                current: 3,
+                total: 16
+            }
+        );
+        assert_eq!(tiles.view_layer.as_deref(), Some("RenderLayer"));
+
+        let last = progress(&events[1]);
+        assert_eq!(
+            last.phase,
+            RenderPhase::Tile {
+                current: 16,
+                total: 16
+            }
+        );
+        assert_eq!(last.elapsed, Some(Duration::from_secs_f64(3723.5)));
+        assert_eq!(
+            events[2],
+            LogEvent::Saved(PathBuf::from("/tmp/RenderData/00012.exr"))
+        );
+    }
+
+    #[test]
+    fn should_parse_eevee_log() {
+        let events = parse_fixture(include_str!("../tests/fixtures/eevee.log"));
+        let first = progress(&events[0]);
+        assert_eq!(first.frame, 3);
+        assert_eq!(
+            first.phase,
+            RenderPhase::Sample {
+                current: 1,
+                total: 64
+            }
+        );
+        assert_eq!(first.scene, None);
+        assert_eq!(first.percentage(), Some(1.0 / 64.0 * 100.0));
+        assert_eq!(progress(&events[1]).percentage(), Some(100.0));
+    }
+
+    #[test]
+    fn should_parse_workbench_log() {
+        let events = parse_fixture(include_str!("../tests/fixtures/workbench.log"));
+        let progress = progress(&events[0]);
+        assert_eq!(progress.frame, 250);
+        assert_eq!(progress.memory, Some(1280.0));
+        assert_eq!(progress.peak_memory, Some(2048.0));
+        assert_eq!(
+            progress.phase,
+            RenderPhase::Sample {
+                current: 4,
+                total: 8
+            }
+        );
+    }
+
+    #[test]
+    fn should_not_panic_on_malformed_lines() {
+        let events = parse_fixture(include_str!("../tests/fixtures/exception.log"));
+        assert_eq!(
+            events[0],
+            LogEvent::Message("Fra:not-a-number | Sample".to_owned())
+        );
+
+        let garbage = progress(&events[1]);
+        assert_eq!(garbage.frame, 1);
+        assert_eq!(garbage.memory, None);
+        assert_eq!(garbage.elapsed, None);
+        assert_eq!(
+            garbage.phase,
+            RenderPhase::Preparing {
+                message: "Rendering / samples".to_owned()
+            }
+        );
+        assert!(matches!(events[2], LogEvent::Exception(_)));
+    }
+
+    #[test]
+    fn should_parse_every_saved_pass() {
+        let events = parse_fixture(include_str!("../tests/fixtures/passes.log"));
+        let saved = events
+            .iter()
+            .filter_map(|event| match event {
+                LogEvent::Saved(path) => Some(path.to_str().unwrap()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            saved,
+            vec![
+                "/tmp/RenderData/00007.png",
+                "/tmp/RenderData/ViewLayer_Depth_00007.exr",
+                "/tmp/RenderData/ViewLayer_CryptoObject00_00007.exr",
+            ]
+        );
+        assert_eq!(events.last(), Some(&LogEvent::FrameComplete));
+    }
+
+    #[test]
+    fn should_classify_fatal_errors() {
+        let events = include_str!("../tests/fixtures/stderr.log")
+            .lines()
+            .filter_map(parse_error_line)
+            .collect::<Vec<LogEvent>>();
+        let fatal = events
+            .iter()
+            .filter_map(|e| match e {
+                LogEvent::Fatal(fatal) => Some(fatal),
+                _ => None,
+            })
+            .collect::<Vec<&Fatal>>();
+
+        assert_eq!(fatal.len(), 6);
+        assert!(matches!(fatal[0], Fatal::MissingAddon(_)));
+        assert!(matches!(fatal[1], Fatal::MissingAddon(_)));
+        assert!(matches!(fatal[2], Fatal::OutOfMemory(_)));
+        assert!(matches!(fatal[3], Fatal::OutOfMemory(_)));
+        assert!(matches!(fatal[4], Fatal::Crashed(_)));
+        assert!(matches!(fatal[5], Fatal::Crashed(_)));
+
+        // plain errors should not be mistaken as fatal
+        assert_eq!(
+            parse_error_line("Error: Not freed memory blocks: 2"),
+            Some(LogEvent::Error(
+                "Error: Not freed memory blocks: 2".to_owned()
+            ))
+        );
+    }
+
+    #[test]
+    fn should_not_mistake_names_and_warnings_for_fatal_errors() {
+        let fixture = include_str!("../tests/fixtures/false_positives.log");
+        let fatal = |event: &LogEvent| matches!(event, LogEvent::Fatal(_));
+        assert!(!parse_fixture(fixture).iter().any(fatal));
+        assert!(!fixture
+            .lines()
+            .filter_map(parse_error_line)
+            .any(|e| fatal(&e)));
+    }
+
+    #[test]
+    fn should_skip_empty_lines() {
+        assert_eq!(parse_line(""), None);
+        assert_eq!(parse_line("   "), None);
+    }
+}
@@ -290,7 +290,7 @@ impl Manager {
         let path = link
             .download_and_extract(&destination)
             .map_err(|e| ManagerError::IoError(e.to_string()))?;
-        dbg!(&path);
+        println!("Blender installed at {path:?}");
         let blender =
             Blender::from_executable(path).map_err(|e| ManagerError::BlenderError { source: e })?;
         self.config.blenders.push(blender.clone());
@@ -2,10 +2,16 @@ pub mod args;
 pub mod blender_peek_response;
 pub mod blender_render_setting;
 pub mod category;
+pub mod dependency;
+pub mod denoiser;
 pub mod device;
 pub mod download_link;
 pub mod engine;
 pub mod format;
 pub mod home;
 pub mod mode;
+pub mod render_handle;
+pub mod render_override;
+pub mod render_pass;
+pub mod render_progress;
 pub mod status;
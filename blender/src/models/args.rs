@@ -18,19 +18,19 @@
 
 // May Subject to change.
 
-use crate::models::{device::Device, engine::Engine, format::Format};
+use crate::models::{blender_render_setting::Window, render_override::RenderOverride};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
 // ref: https://docs.blender.org/manual/en/latest/advanced/command_line/render.html
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Args {
-    pub file: PathBuf,          // required
-    pub output: PathBuf,        // optional
-    pub engine: Engine,         // optional
-    pub device: Device,         // optional
-    pub format: Format,         // optional - default to Png
-    pub use_continuation: bool, // optional - default to false
+    pub file: PathBuf,             // required
+    pub output: PathBuf,           // optional
+    pub overrides: RenderOverride, // optional - default to the settings in the blend file
+    pub use_continuation: bool,    // optional - default to false
+    pub border: Window,            // optional - default to the whole frame
+    pub crop: bool,                // optional - crop the image to the border, default to false
 }
 
 impl Args {
@@ -38,11 +38,11 @@ impl Args {
         Args {
             file: file,
             output: output,
-            // TODO: Change this so that we can properly reflect the engine used by A) Blendfile B) User request, and C) allowlist from machine config
-            engine: Default::default(),
-            device: Default::default(),
-            format: Default::default(),
+            // engine, samples, format etc. come from the blend file unless the job overrides them.
+            overrides: Default::default(),
             use_continuation: false,
+            border: Default::default(),
+            crop: false,
         }
     }
 }
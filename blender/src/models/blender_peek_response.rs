@@ -3,21 +3,102 @@ use std::path::PathBuf;
 use semver::Version;
 use serde::{Deserialize, Serialize};
 
+/// Render settings of a single scene in the blend file.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
-pub struct BlenderPeekResponse {
-    pub last_version: Version,
+pub struct BlenderScene {
+    pub name: String,
+    /// Render engine, e.g. CYCLES, BLENDER_EEVEE_NEXT, BLENDER_WORKBENCH
+    pub engine: String,
     pub render_width: i32,
     pub render_height: i32,
+    /// The image comes out render_width * resolution_percentage / 100 wide.
+    pub resolution_percentage: u16,
     pub frame_start: i32,
     pub frame_end: i32,
+    pub frame_step: i32,
     #[serde(rename = "FPS")]
     pub fps: u16,
+    /// Samples of the engine the scene renders with. 0 if the engine doesn't use samples.
     pub samples: i32,
+    /// Cycles denoiser (OPENIMAGEDENOISE, OPTIX), None if denoising is off or the scene doesn't render with Cycles.
+    pub denoiser: Option<String>,
+    /// Cameras linked to this scene
     pub cameras: Vec<String>,
-    pub selected_camera: String,
-    pub scenes: Vec<String>,
-    pub selected_scene: String,
-    pub engine: String,
+    /// Camera the scene renders from
+    pub active_camera: String,
+    pub view_layers: Vec<String>,
     pub output: PathBuf,
+    /// Image format of the output, e.g. PNG, OPEN_EXR
+    pub format: String,
+}
+
+impl BlenderScene {
+    /// Size of the rendered image, after the resolution percentage.
+    pub fn resolution(&self) -> (i32, i32) {
+        let scale = |size: i32| size * self.resolution_percentage as i32 / 100;
+        (scale(self.render_width), scale(self.render_height))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct BlenderPeekResponse {
+    pub last_version: Version,
+    pub scenes: Vec<BlenderScene>,
+    /// Scene the blend file was saved with, blender renders this one unless told otherwise.
+    pub selected_scene: String,
+}
+
+impl BlenderPeekResponse {
+    /// Find the scene by name.
+    pub fn scene(&self, name: &str) -> Option<&BlenderScene> {
+        self.scenes.iter().find(|scene| scene.name == name)
+    }
+
+    /// The scene blender renders by default.
+    pub fn selected(&self) -> Option<&BlenderScene> {
+        self.scene(&self.selected_scene).or(self.scenes.first())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scene(name: &str) -> BlenderScene {
+        BlenderScene {
+            name: name.to_owned(),
+            engine: "CYCLES".to_owned(),
+            render_width: 1920,
+            render_height: 1080,
+            resolution_percentage: 50,
+            frame_start: 1,
+            frame_end: 250,
+            frame_step: 1,
+            fps: 24,
+            samples: 4096,
+            denoiser: Some("OPENIMAGEDENOISE".to_owned()),
+            cameras: vec!["Camera".to_owned()],
+            active_camera: "Camera".to_owned(),
+            view_layers: vec!["ViewLayer".to_owned()],
+            output: PathBuf::from("/tmp/"),
+            format: "PNG".to_owned(),
+        }
+    }
+
+    #[test]
+    fn should_pick_selected_scene() {
+        let mut peek = BlenderPeekResponse {
+            last_version: Version::new(4, 2, 0),
+            scenes: vec![scene("Layout"), scene("Shot")],
+            selected_scene: "Shot".to_owned(),
+        };
+        assert_eq!(peek.selected().unwrap().name, "Shot");
+        assert_eq!(peek.selected().unwrap().resolution(), (960, 540));
+
+        // the window's scene is gone, fall back to the first one.
+        peek.selected_scene = "Missing".to_owned();
+        assert_eq!(peek.selected().unwrap().name, "Layout");
+    }
 }
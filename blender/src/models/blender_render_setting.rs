@@ -1,6 +1,10 @@
 use super::{
-    args::Args, blender_peek_response::BlenderPeekResponse, device::Device, engine::Engine,
+    args::Args,
+    blender_peek_response::{BlenderPeekResponse, BlenderScene},
+    device::Device,
+    engine::Engine,
     format::Format,
+    render_pass::{Aov, RenderPass},
 };
 use serde::{de::Visitor, ser::SerializeStruct, Deserialize, Serialize};
 use std::{ops::Range, path::PathBuf};
@@ -29,6 +33,27 @@ impl Default for Window {
     }
 }
 
+impl Window {
+    /// Split the whole frame into a grid of regions, left to right, top to bottom.
+    /// Blender measures the border from the bottom left corner, so the top row has the highest y.
+    /// Neighbouring regions share the exact same edge value, so nothing falls in between.
+    pub fn grid(columns: u16, rows: u16) -> Vec<Window> {
+        let (columns, rows) = (columns.max(1), rows.max(1));
+        let edge = |index: u16, count: u16| index as f32 / count as f32;
+
+        let mut windows = Vec::with_capacity(columns as usize * rows as usize);
+        for row in (0..rows).rev() {
+            for column in 0..columns {
+                windows.push(Window {
+                    x: edge(column, columns)..edge(column + 1, columns),
+                    y: edge(row, rows)..edge(row + 1, rows),
+                });
+            }
+        }
+        windows
+    }
+}
+
 impl Serialize for Window {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -70,6 +95,25 @@ impl<'de> Deserialize<'de> for Window {
                     y: Range { start: y, end: y2 },
                 })
             }
+
+            // self describing formats (json, cbor) hand us the fields by name.
+            fn visit_map<V>(self, mut map: V) -> Result<Self::Value, V::Error>
+            where
+                V: serde::de::MapAccess<'de>,
+            {
+                let mut window = Window::default();
+                while let Some(key) = map.next_key::<String>()? {
+                    let value = map.next_value::<f32>()?;
+                    match key.as_str() {
+                        "X" => window.x.start = value,
+                        "X2" => window.x.end = value,
+                        "Y" => window.y.start = value,
+                        "Y2" => window.y.end = value,
+                        _ => {}
+                    }
+                }
+                Ok(window)
+            }
         }
 
         const FIELDS: &[&str] = &["X", "X2", "Y", "Y2"];
@@ -92,12 +136,25 @@ pub struct BlenderRenderSetting {
     pub border: Window,
     pub tile_width: i32,
     pub tile_height: i32,
+    /// 0 keeps the samples saved in the blend file.
     pub samples: i32,
     pub width: i32,
     pub height: i32,
-    pub engine: i32,
-    #[serde(rename = "RenderFormat")]
-    pub format: Format,
+    /// The image comes out width * resolution_percentage / 100 wide.
+    pub resolution_percentage: u16,
+    /// Only render these view layers, empty renders every layer the scene uses.
+    pub view_layers: Vec<String>,
+    /// View layer properties that turn on the render passes, e.g. use_pass_z
+    pub passes: Vec<String>,
+    pub aovs: Vec<Aov>,
+    /// Blender's identifier of the engine, empty keeps the engine saved in the blend file.
+    pub engine: String,
+    /// Cycles denoiser, empty keeps the blend file's setting.
+    pub denoiser: String,
+    /// Image format and its options, None keeps the format saved in the blend file.
+    // left out instead of sent as nil, python's xml-rpc doesn't accept None.
+    #[serde(rename = "RenderFormat", skip_serializing_if = "Option::is_none")]
+    pub format: Option<Format>,
     // discourage?
     pub crop: bool,
 }
@@ -115,8 +172,9 @@ impl BlenderRenderSetting {
         samples: i32,
         width: i32,
         height: i32,
-        engine: Engine,
-        format: Format,
+        engine: Option<Engine>,
+        format: Option<Format>,
+        crop: bool,
     ) -> Self {
         let id = Uuid::new_v4();
         Self {
@@ -133,33 +191,170 @@ impl BlenderRenderSetting {
             samples,
             width,
             height,
-            engine: engine as i32,
+            resolution_percentage: 100,
+            view_layers: Vec::new(),
+            passes: Vec::new(),
+            aovs: Vec::new(),
+            engine: engine.map(|e| e.to_string()).unwrap_or_default(),
+            denoiser: String::new(),
             format,
-            crop: false,
+            crop,
         }
     }
 
+    /// Settings for the render, from the job's overrides first, then what's saved in the blend file.
     pub fn parse_from(args: &Args, info: &BlenderPeekResponse) -> Self {
-        let output = args.output.clone();
-        let compute_unit = args.device.clone();
-        let border = Default::default();
-        let engine = args.engine.clone();
-        let format = args.format.clone();
-
-        BlenderRenderSetting::new(
-            output.to_owned(),
-            info.selected_scene.to_owned(),
-            info.selected_camera.to_owned(),
-            compute_unit.to_owned(),
-            info.fps,
-            border,
+        let overrides = &args.overrides;
+        // render the scene blender would have picked, unless the job asks for another one.
+        let scene = match &overrides.scene {
+            Some(name) => info.scene(name),
+            None => info.selected(),
+        };
+        let file = |get: fn(&BlenderScene) -> i32| scene.map(get).unwrap_or_default();
+
+        let mut setting = BlenderRenderSetting::new(
+            args.output.to_owned(),
+            overrides
+                .scene
+                .clone()
+                .or(scene.map(|s| s.name.clone()))
+                .unwrap_or_default(),
+            overrides
+                .camera
+                .clone()
+                .or(scene.map(|s| s.active_camera.clone()))
+                .unwrap_or_default(),
+            overrides.device.unwrap_or_default(),
+            scene.map(|s| s.fps).unwrap_or_default(),
+            args.border.clone(),
             -1,
             -1,
-            info.samples,
-            info.render_width,
-            info.render_height,
-            engine,
-            format,
-        )
+            // engine, samples, denoiser and format are left alone unless overridden, render.py keeps what's in the file.
+            overrides.samples.unwrap_or(0),
+            overrides.width.unwrap_or(file(|s| s.render_width)),
+            overrides.height.unwrap_or(file(|s| s.render_height)),
+            overrides.engine.clone(),
+            overrides.format.clone(),
+            args.crop,
+        );
+        setting.view_layers = overrides.view_layers.clone();
+        setting.passes = overrides
+            .passes
+            .iter()
+            .filter_map(RenderPass::property)
+            .map(str::to_owned)
+            .collect();
+        setting.aovs = overrides
+            .passes
+            .iter()
+            .filter_map(RenderPass::aov)
+            .cloned()
+            .collect();
+        setting.denoiser = overrides
+            .denoiser
+            .map(|d| d.to_string())
+            .unwrap_or_default();
+        setting.resolution_percentage = overrides
+            .resolution_percentage
+            .or(scene.map(|s| s.resolution_percentage))
+            .unwrap_or(100);
+        setting
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::denoiser::Denoiser;
+
+    #[test]
+    fn should_split_frame_into_grid() {
+        let windows = Window::grid(3, 2);
+        assert_eq!(windows.len(), 6);
+        // top left first, blender counts y from the bottom.
+        assert_eq!(windows[0].x, 0.0..1.0 / 3.0);
+        assert_eq!(windows[0].y, 0.5..1.0);
+        assert_eq!(windows[5].x, 2.0 / 3.0..1.0);
+        assert_eq!(windows[5].y, 0.0..0.5);
+
+        // regions share edges, and together cover the whole frame.
+        let area: f32 = windows
+            .iter()
+            .map(|w| (w.x.end - w.x.start) * (w.y.end - w.y.start))
+            .sum();
+        assert!((area - 1.0).abs() < f32::EPSILON * 4.0);
+        assert_eq!(Window::grid(0, 0), vec![Window::default()]);
+    }
+
+    #[test]
+    fn should_deserialize_window_from_json() {
+        let window = Window::grid(2, 2).remove(3);
+        let json = serde_json::to_string(&window).unwrap();
+        assert_eq!(json, r#"{"X":0.5,"X2":1.0,"Y":0.0,"Y2":0.5}"#);
+        assert_eq!(serde_json::from_str::<Window>(&json).unwrap(), window);
+    }
+
+    #[test]
+    fn should_prefer_overrides_over_file_settings() {
+        let scene = BlenderScene {
+            name: "Shot".to_owned(),
+            engine: "CYCLES".to_owned(),
+            render_width: 1920,
+            render_height: 1080,
+            resolution_percentage: 50,
+            frame_start: 1,
+            frame_end: 250,
+            frame_step: 1,
+            fps: 24,
+            samples: 4096,
+            denoiser: None,
+            cameras: vec!["Camera".to_owned(), "Closeup".to_owned()],
+            active_camera: "Camera".to_owned(),
+            view_layers: vec!["ViewLayer".to_owned()],
+            output: PathBuf::from("/tmp/"),
+            format: "TIFF".to_owned(),
+        };
+        let info = BlenderPeekResponse {
+            last_version: semver::Version::new(4, 2, 0),
+            scenes: vec![scene],
+            selected_scene: "Shot".to_owned(),
+        };
+        let mut args = Args::new(PathBuf::from("shot.blend"), PathBuf::from("/tmp/"));
+
+        // nothing overridden, the file decides.
+        let setting = BlenderRenderSetting::parse_from(&args, &info);
+        assert_eq!(setting.engine, "");
+        assert_eq!(setting.samples, 0);
+        assert_eq!(setting.denoiser, "");
+        assert_eq!((setting.width, setting.height), (1920, 1080));
+        assert_eq!(setting.resolution_percentage, 50);
+        assert_eq!(setting.format, None);
+        assert_eq!(setting.camera, "Camera");
+        assert!(setting.passes.is_empty());
+
+        args.overrides.camera = Some("Closeup".to_owned());
+        args.overrides.engine = Some(Engine::Workbench);
+        args.overrides.samples = Some(64);
+        args.overrides.denoiser = Some(Denoiser::Off);
+        args.overrides.resolution_percentage = Some(100);
+        args.overrides.format = Some(Format::default());
+        args.overrides.passes = vec![
+            RenderPass::Depth,
+            RenderPass::CryptomatteObject,
+            RenderPass::Aov("Dirt".parse().unwrap()),
+        ];
+        let setting = BlenderRenderSetting::parse_from(&args, &info);
+        assert_eq!(setting.camera, "Closeup");
+        assert_eq!(setting.engine, "BLENDER_WORKBENCH");
+        assert_eq!(setting.samples, 64);
+        assert_eq!(setting.denoiser, "OFF");
+        assert_eq!(setting.resolution_percentage, 100);
+        assert_eq!(setting.format, Some(Format::default()));
+        assert_eq!(setting.scene, "Shot");
+        assert_eq!(
+            setting.passes,
+            vec!["use_pass_z", "use_pass_cryptomatte_object"]
+        );
+        assert_eq!(setting.aovs.len(), 1);
     }
 }
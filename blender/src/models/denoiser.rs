@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+/// Cycles denoiser, see scene.cycles.denoiser
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Denoiser {
+    /// Turn denoising off
+    Off,
+    OpenImageDenoise,
+    /// Needs an NVIDIA card on the worker.
+    OptiX,
+}
+
+// what render.py expects, OFF turns scene.cycles.use_denoising off.
+impl Display for Denoiser {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Denoiser::Off => write!(f, "OFF"),
+            Denoiser::OpenImageDenoise => write!(f, "OPENIMAGEDENOISE"),
+            Denoiser::OptiX => write!(f, "OPTIX"),
+        }
+    }
+}
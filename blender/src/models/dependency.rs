@@ -0,0 +1,176 @@
+/*
+    Developer blog:
+    - Workers only used to get the .blend file. Anything it pointed to (textures, linked libraries, VDBs, alembic, bakes) was missing, so renders came out pink or empty.
+        Blender::dependencies() reads the blend file (and the libraries it links) with the blend crate, and lists the files on disk the project needs.
+    - Blender paths starting with // are relative to the blend file that refers to them. Those we can rebuild on the worker.
+        Absolute paths only work if the worker happens to have the same folders. Use File > External Data > Make Paths Relative before sending the job.
+    - One image in blender can be many files on disk: image sequences and UDIM tiles. We pick up every file next to it that only differs by the number.
+    - Caches and bakes are folders. Everything in the folder goes along.
+    - TODO: geometry nodes bakes keep their folder in a char pointer, which the blend crate can't read yet. Bake them into the default blendcache folder for now.
+*/
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path, PathBuf};
+
+/// What the project uses the file for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DependencyKind {
+    Image,
+    /// Linked .blend library
+    Library,
+    /// OpenVDB volume
+    Volume,
+    /// Alembic or USD cache
+    Cache,
+    /// Simulation bake or point cache
+    Bake,
+}
+
+/// A file outside the blend file that the project needs to render.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Dependency {
+    pub kind: DependencyKind,
+    /// Where the file is on this machine.
+    pub path: PathBuf,
+    /// Blender refers to the file relative to the blend file (the path starts with //).
+    pub relative: bool,
+}
+
+/// Turn a path stored in the blend file into a path on this machine.
+/// base_dir is the folder of the blend file the path came from. Returns the path, and whether it was relative. None if there's no path.
+pub fn resolve(base_dir: &Path, raw: &str) -> Option<(PathBuf, bool)> {
+    let raw = raw.trim_end_matches('\0');
+    if raw.is_empty() {
+        return None;
+    }
+
+    match raw.strip_prefix("//") {
+        // blend files made on windows use backslash, split on both so it works everywhere.
+        Some(relative) => {
+            let path = relative
+                .split(['/', '\\'])
+                .filter(|part| !part.is_empty())
+                .fold(base_dir.to_path_buf(), |path, part| path.join(part));
+            Some((normalize(&path), true))
+        }
+        None => Some((normalize(Path::new(raw)), false)),
+    }
+}
+
+/// Clean up . and .. without touching the disk, the file may not exist here.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match result.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    result.pop();
+                }
+                // can't go above the root.
+                Some(Component::RootDir) | Some(Component::Prefix(_)) => {}
+                _ => result.push(".."),
+            },
+            component => result.push(component),
+        }
+    }
+    result
+}
+
+/// Every file of an image sequence or UDIM set. The path is any one of them, or has a <UDIM> token where the tile number goes.
+pub fn expand_sequence(path: &Path) -> Vec<PathBuf> {
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name().and_then(|n| n.to_str())) else {
+        return Vec::new();
+    };
+    let (prefix, suffix) = match name.split_once("<UDIM>") {
+        Some((prefix, suffix)) => (prefix.to_owned(), suffix.to_owned()),
+        None => {
+            // the number is at the end of the name, right before the extension.
+            let (stem, extension) = match name.rsplit_once('.') {
+                Some((stem, extension)) => (stem, format!(".{extension}")),
+                None => (name, String::new()),
+            };
+            (
+                stem.trim_end_matches(|c: char| c.is_ascii_digit())
+                    .to_owned(),
+                extension,
+            )
+        }
+    };
+
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|file| file.is_file())
+        .filter(|file| {
+            let Some(name) = file.file_name().and_then(|n| n.to_str()) else {
+                return false;
+            };
+            name.len() > prefix.len() + suffix.len()
+                && name.starts_with(&prefix)
+                && name.ends_with(&suffix)
+                && name[prefix.len()..name.len() - suffix.len()]
+                    .chars()
+                    .all(|c| c.is_ascii_digit())
+        })
+        .collect::<Vec<_>>();
+    files.sort();
+    files
+}
+
+/// Every file inside the folder and its sub folders.
+pub fn files_in(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files = Vec::new();
+    for path in entries.filter_map(|entry| entry.ok().map(|e| e.path())) {
+        if path.is_dir() {
+            files.extend(files_in(&path));
+        } else if path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_resolve_relative_to_blend_file() {
+        let base = Path::new("/projects/shot/scenes");
+        assert_eq!(
+            resolve(base, "//..\\textures\\wood.png"),
+            Some((PathBuf::from("/projects/shot/textures/wood.png"), true))
+        );
+        assert_eq!(
+            resolve(base, "/library/./rock.blend"),
+            Some((PathBuf::from("/library/rock.blend"), false))
+        );
+        assert_eq!(resolve(base, ""), None);
+    }
+
+    #[test]
+    fn should_find_every_file_of_a_sequence() {
+        let dir = std::env::temp_dir().join(format!("sequence_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in [
+            "wood.1001.png",
+            "wood.1002.png",
+            "wood.1001.exr",
+            "wood.png",
+            "woodwork.1001.png",
+        ] {
+            std::fs::write(dir.join(name), b"").unwrap();
+        }
+
+        let expected = vec![dir.join("wood.1001.png"), dir.join("wood.1002.png")];
+        assert_eq!(expand_sequence(&dir.join("wood.<UDIM>.png")), expected);
+        assert_eq!(expand_sequence(&dir.join("wood.1001.png")), expected);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
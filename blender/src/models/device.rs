@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::fmt::Display;
 
 /*
 Developer blog-
@@ -8,7 +9,7 @@ Once I get this part of the code working, then I'll go back and refactor python
 */
 
 // TODO: Once python code is working with this rust code - refactor python to reduce this garbage mess below:
-#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
 #[allow(dead_code, non_camel_case_types)]
 pub enum Device {
     #[default]
@@ -27,17 +28,113 @@ pub enum Device {
     OPTIX_GPUONLY = 12,
 }
 
-// Append +CPU to a GPU device to render on both CPU and GPU.
-impl ToString for Device {
-    fn to_string(&self) -> String {
+// GPU backends from the most to the least preferred. OptiX is CUDA with RTX acceleration on top, so it goes first.
+const PREFERENCE: [Device; 6] = [
+    Device::OPTIX,
+    Device::CUDA,
+    Device::HIP,
+    Device::METAL,
+    Device::ONEAPI,
+    Device::OPENCL,
+];
+
+impl Device {
+    // name of the backend, as blender calls it.
+    fn name(self) -> &'static str {
+        match self.backend() {
+            Device::CUDA => "CUDA",
+            Device::OPENCL => "OPENCL",
+            Device::HIP => "HIP",
+            Device::METAL => "METAL",
+            Device::ONEAPI => "ONEAPI",
+            Device::OPTIX => "OPTIX",
+            _ => "CPU",
+        }
+    }
+
+    /// The backend, without the GPU only flag. E.g. CUDA_GPUONLY is CUDA.
+    pub fn backend(self) -> Device {
         match self {
-            Device::CPU => "CPU".to_owned(),
-            Device::CUDA => "CUDA".to_owned(),
-            Device::OPTIX => "OPTIX".to_owned(),
-            Device::HIP => "HIP".to_owned(),
-            Device::ONEAPI => "ONEAPI".to_owned(),
-            Device::METAL => "METAL".to_owned(),
-            _ => todo!("to be implemented after getting this to work with python!"),
+            Device::CUDA_GPUONLY => Device::CUDA,
+            Device::OPENCL_GPUONLY => Device::OPENCL,
+            Device::HIP_GPUONLY => Device::HIP,
+            Device::METAL_GPUONLY => Device::METAL,
+            Device::ONEAPI_GPUONLY => Device::ONEAPI,
+            Device::OPTIX_GPUONLY => Device::OPTIX,
+            other => other,
+        }
+    }
+
+    /// Render on the graphic card alone, leave the CPU out of it. CPU stays CPU.
+    pub fn gpu_only(self) -> Device {
+        match self.backend() {
+            Device::CUDA => Device::CUDA_GPUONLY,
+            Device::OPENCL => Device::OPENCL_GPUONLY,
+            Device::HIP => Device::HIP_GPUONLY,
+            Device::METAL => Device::METAL_GPUONLY,
+            Device::ONEAPI => Device::ONEAPI_GPUONLY,
+            Device::OPTIX => Device::OPTIX_GPUONLY,
+            other => other,
         }
     }
+
+    /// A machine that has these backends can render with this device. Every machine has a CPU.
+    pub fn is_available(self, backends: &[Device]) -> bool {
+        self.backend() == Device::CPU || backends.contains(&self.backend())
+    }
+
+    /// Fastest device out of the backends a machine has. Mixing in the CPU usually slows the GPU down, so it's left out.
+    pub fn best(backends: &[Device]) -> Device {
+        PREFERENCE
+            .into_iter()
+            .find(|device| backends.contains(device))
+            .map(Device::gpu_only)
+            .unwrap_or(Device::CPU)
+    }
+
+    /// Backends listed by probe.py, one DEVICE: line each. CPU is always included.
+    pub fn from_probe(output: &str) -> Vec<Device> {
+        let mut devices = vec![Device::CPU];
+        for line in output.lines() {
+            let Some(name) = line.trim().strip_prefix("DEVICE: ") else {
+                continue;
+            };
+            let found = PREFERENCE.into_iter().find(|device| device.name() == name);
+            if let Some(device) = found.filter(|device| !devices.contains(device)) {
+                devices.push(device);
+            }
+        }
+        devices
+    }
+}
+
+// Same as blender's --cycles-device. Append +CPU to a GPU device to render on both CPU and GPU.
+impl Display for Device {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.backend() == *self && *self != Device::CPU {
+            true => write!(f, "{}+CPU", self.name()),
+            false => write!(f, "{}", self.name()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_pick_fastest_device_the_worker_has() {
+        let output = "Blender 4.2.0\nDEVICE: CUDA\nDEVICE: OPTIX\nDEVICE: VULKAN\n";
+        let devices = Device::from_probe(output);
+        assert_eq!(devices, vec![Device::CPU, Device::CUDA, Device::OPTIX]);
+        assert_eq!(Device::best(&devices), Device::OPTIX_GPUONLY);
+        assert_eq!(Device::best(&[Device::CPU]), Device::CPU);
+
+        // a job that asks for OptiX only renders on workers that have it.
+        assert!(Device::OPTIX_GPUONLY.is_available(&devices));
+        assert!(!Device::HIP.is_available(&devices));
+        assert!(Device::CPU.is_available(&[]));
+        assert_eq!(Device::OPTIX_GPUONLY.to_string(), "OPTIX");
+        assert_eq!(Device::HIP.to_string(), "HIP+CPU");
+    }
 }
@@ -1,20 +1,51 @@
 use serde::{Deserialize, Serialize};
+use std::fmt::Display;
 
-#[derive(Debug, Copy, Default, Clone, Serialize, Deserialize, PartialEq, Eq)]
+/// Render engine of a scene.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum Engine {
-    Cycles = 0,
-    #[default]
-    Eevee = 1,
-    OptiX = 2,
+    Cycles,
+    /// EEVEE, or EEVEE Next on blender 4.2 and above. render.py picks the one the blender version has.
+    Eevee,
+    Workbench,
+    /// Engine from an add-on, e.g. OCTANE or LUXCORE. Holds blender's identifier for it.
+    Other(String),
 }
 
-impl ToString for Engine {
-    fn to_string(&self) -> String {
+impl Engine {
+    /// The engine from blender's identifier, as stored in the blend file (scene.render.engine)
+    pub fn from_blender(identifier: &str) -> Self {
+        match identifier {
+            "CYCLES" => Engine::Cycles,
+            "BLENDER_EEVEE" | "BLENDER_EEVEE_NEXT" => Engine::Eevee,
+            "BLENDER_WORKBENCH" => Engine::Workbench,
+            other => Engine::Other(other.to_owned()),
+        }
+    }
+}
+
+// blender's identifier, what render.py assigns to scene.render.engine
+impl Display for Engine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Engine::Cycles => "CYCLES".to_owned(),
-            // Blender 4.2 introduce a new enum called BLENDER_EEVEE_NEXT, which is currently handle in python file atm.
-            Engine::Eevee => "EEVEE".to_owned(),
-            Engine::OptiX => "WORKBENCH".to_owned(),
+            Engine::Cycles => write!(f, "CYCLES"),
+            Engine::Eevee => write!(f, "BLENDER_EEVEE"),
+            Engine::Workbench => write!(f, "BLENDER_WORKBENCH"),
+            Engine::Other(identifier) => write!(f, "{identifier}"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_read_engine_from_blend_file() {
+        assert_eq!(Engine::from_blender("BLENDER_EEVEE_NEXT"), Engine::Eevee);
+        assert_eq!(Engine::from_blender("BLENDER_WORKBENCH"), Engine::Workbench);
+        let octane = Engine::from_blender("octane");
+        assert_eq!(octane, Engine::Other("octane".to_owned()));
+        assert_eq!(octane.to_string(), "octane");
+    }
+}
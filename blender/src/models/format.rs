@@ -1,68 +1,326 @@
+/*
+    Developer blog:
+    - Each format has its own options, the same way blender only shows the options that apply to the format picked in the output properties.
+    - The field names are the names of the properties on scene.render.image_settings, render.py sets them one by one.
+        That way adding an option here doesn't need any change to the python script.
+    - Color depth is a single list for every format. Formats only take some of them, e.g. EXR is 16 (half) or 32 (full float). See Format::color_depths()
+*/
 use serde::{Deserialize, Serialize};
-use std::str::FromStr;
+use std::{fmt::Display, str::FromStr};
+use thiserror::Error;
 
+#[derive(Debug, Error, PartialEq)]
 pub enum FormatError {
-    InvalidInput,
+    #[error("Unknown image format: {0}")]
+    UnknownFormat(String),
+    #[error("Unknown codec: {0}")]
+    UnknownCodec(String),
+    #[error("{format} does not support {option}")]
+    Unsupported { format: String, option: String },
+}
+
+/// Bits per color channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ColorDepth {
+    #[serde(rename = "8")]
+    Bits8,
+    #[serde(rename = "10")]
+    Bits10,
+    #[serde(rename = "12")]
+    Bits12,
+    /// Half float for EXR
+    #[serde(rename = "16")]
+    Bits16,
+    /// Full float for EXR
+    #[serde(rename = "32")]
+    Bits32,
+}
+
+/// OpenEXR compression
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExrCodec {
+    NONE,
+    PXR24,
+    #[default]
+    ZIP,
+    PIZ,
+    RLE,
+    ZIPS,
+    B44,
+    B44A,
+    DWAA,
+    DWAB,
+}
+
+/// TIFF compression
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TiffCodec {
+    NONE,
+    #[default]
+    DEFLATE,
+    LZW,
+    PACKBITS,
 }
 
 // More context: https://docs.blender.org/manual/en/latest/advanced/command_line/arguments.html#format-options
-#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+// serialize into the properties of scene.render.image_settings, e.g. {"file_format":"PNG","color_depth":"16","compression":15}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "file_format")]
+#[allow(non_camel_case_types)]
 pub enum Format {
+    #[serde(rename = "TARGA")]
     TGA,
+    #[serde(rename = "TARGA_RAW")]
     RAWTGA,
-    JPEG,
+    JPEG {
+        quality: u8,
+    },
     IRIS,
+    #[serde(rename = "AVI_RAW")]
     AVIRAW,
-    AVIJPEG,
-    #[default]
-    PNG,
+    #[serde(rename = "AVI_JPEG")]
+    AVIJPEG {
+        quality: u8,
+    },
+    PNG {
+        color_depth: ColorDepth,
+        /// 0 to 100, higher is smaller but slower to save.
+        compression: u8,
+    },
     BMP,
     HDR,
-    TIFF,
+    TIFF {
+        color_depth: ColorDepth,
+        tiff_codec: TiffCodec,
+    },
+    OPEN_EXR {
+        color_depth: ColorDepth,
+        exr_codec: ExrCodec,
+    },
+    /// Every render pass and view layer in a single file.
+    OPEN_EXR_MULTILAYER {
+        color_depth: ColorDepth,
+        exr_codec: ExrCodec,
+    },
+    WEBP {
+        /// 100 is lossless.
+        quality: u8,
+    },
+    JPEG2000 {
+        color_depth: ColorDepth,
+        quality: u8,
+    },
+    CINEON,
+    DPX {
+        color_depth: ColorDepth,
+    },
 }
 
-impl Serialize for Format {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: serde::Serializer,
-    {
-        serializer.serialize_str(&self.to_string())
+// same as blender's defaults.
+impl Default for Format {
+    fn default() -> Self {
+        Format::PNG {
+            color_depth: ColorDepth::Bits8,
+            compression: 15,
+        }
+    }
+}
+
+impl Format {
+    /// Color depths the format can save with. Empty if the format doesn't let us pick.
+    pub fn color_depths(&self) -> &'static [ColorDepth] {
+        use ColorDepth::*;
+        match self {
+            Format::PNG { .. } | Format::TIFF { .. } => &[Bits8, Bits16],
+            Format::OPEN_EXR { .. } | Format::OPEN_EXR_MULTILAYER { .. } => &[Bits16, Bits32],
+            Format::JPEG2000 { .. } => &[Bits8, Bits12, Bits16],
+            Format::DPX { .. } => &[Bits8, Bits10, Bits12, Bits16],
+            _ => &[],
+        }
+    }
+
+    pub fn set_color_depth(&mut self, depth: ColorDepth) -> Result<(), FormatError> {
+        if !self.color_depths().contains(&depth) {
+            return Err(self.unsupported(format!("{depth:?} color depth")));
+        }
+        match self {
+            Format::PNG { color_depth, .. }
+            | Format::TIFF { color_depth, .. }
+            | Format::OPEN_EXR { color_depth, .. }
+            | Format::OPEN_EXR_MULTILAYER { color_depth, .. }
+            | Format::JPEG2000 { color_depth, .. }
+            | Format::DPX { color_depth } => *color_depth = depth,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Quality of lossy formats, or the compression of PNG. 0 to 100.
+    pub fn set_quality(&mut self, value: u8) -> Result<(), FormatError> {
+        let value = value.min(100);
+        match self {
+            Format::JPEG { quality }
+            | Format::AVIJPEG { quality }
+            | Format::WEBP { quality }
+            | Format::JPEG2000 { quality, .. } => *quality = value,
+            Format::PNG { compression, .. } => *compression = value,
+            _ => return Err(self.unsupported("quality".to_owned())),
+        }
+        Ok(())
+    }
+
+    /// Compression codec of EXR and TIFF, by blender's name. E.g. DWAA or LZW
+    pub fn set_codec(&mut self, codec: &str) -> Result<(), FormatError> {
+        let name = serde_json::Value::String(codec.to_uppercase());
+        let unknown = |_| FormatError::UnknownCodec(codec.to_owned());
+        match self {
+            Format::OPEN_EXR { exr_codec, .. } | Format::OPEN_EXR_MULTILAYER { exr_codec, .. } => {
+                *exr_codec = serde_json::from_value(name).map_err(unknown)?
+            }
+            Format::TIFF { tiff_codec, .. } => {
+                *tiff_codec = serde_json::from_value(name).map_err(unknown)?
+            }
+            _ => return Err(self.unsupported("codecs".to_owned())),
+        }
+        Ok(())
+    }
+
+    fn unsupported(&self, option: String) -> FormatError {
+        FormatError::Unsupported {
+            format: self.to_string(),
+            option,
+        }
     }
 }
 
+// Accepts blender's name of the format. Options start out with blender's defaults.
 impl FromStr for Format {
     type Err = FormatError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s.to_uppercase().as_str() {
-            "TGA" => Ok(Format::TGA),
-            "RAWTGA" => Ok(Format::RAWTGA),
-            "JPEG" => Ok(Format::JPEG),
+            "TGA" | "TARGA" => Ok(Format::TGA),
+            "RAWTGA" | "TARGA_RAW" => Ok(Format::RAWTGA),
+            "JPEG" | "JPG" => Ok(Format::JPEG { quality: 90 }),
             "IRIS" => Ok(Format::IRIS),
-            "AVIRAW" => Ok(Format::AVIRAW),
-            "AVIJPEG" => Ok(Format::AVIJPEG),
-            "PNG" => Ok(Format::PNG),
+            "AVIRAW" | "AVI_RAW" => Ok(Format::AVIRAW),
+            "AVIJPEG" | "AVI_JPEG" => Ok(Format::AVIJPEG { quality: 90 }),
+            "PNG" => Ok(Format::default()),
             "BMP" => Ok(Format::BMP),
             "HDR" => Ok(Format::HDR),
-            "TIFF" => Ok(Format::TIFF),
-            _ => Err(FormatError::InvalidInput),
+            "TIFF" => Ok(Format::TIFF {
+                color_depth: ColorDepth::Bits8,
+                tiff_codec: TiffCodec::default(),
+            }),
+            "OPEN_EXR" | "EXR" => Ok(Format::OPEN_EXR {
+                color_depth: ColorDepth::Bits16,
+                exr_codec: ExrCodec::default(),
+            }),
+            "OPEN_EXR_MULTILAYER" => Ok(Format::OPEN_EXR_MULTILAYER {
+                color_depth: ColorDepth::Bits16,
+                exr_codec: ExrCodec::default(),
+            }),
+            "WEBP" => Ok(Format::WEBP { quality: 90 }),
+            "JPEG2000" => Ok(Format::JPEG2000 {
+                color_depth: ColorDepth::Bits8,
+                quality: 90,
+            }),
+            "CINEON" => Ok(Format::CINEON),
+            "DPX" => Ok(Format::DPX {
+                color_depth: ColorDepth::Bits8,
+            }),
+            _ => Err(FormatError::UnknownFormat(s.to_owned())),
         }
     }
 }
 
-impl ToString for Format {
-    fn to_string(&self) -> String {
-        match self {
-            Format::TGA => "TARGA".to_owned(),
-            Format::RAWTGA => "RAWTARGA".to_owned(),
-            Format::JPEG => "JPEG".to_owned(),
-            Format::IRIS => "IRIS".to_owned(),
-            Format::AVIRAW => "AVIRAW".to_owned(),
-            Format::AVIJPEG => "AVIJPEG".to_owned(),
-            Format::PNG => "PNG".to_owned(),
-            Format::BMP => "BMP".to_owned(),
-            Format::HDR => "HDR".to_owned(),
-            Format::TIFF => "TIFF".to_owned(),
+// blender's name of the format, what render.py assigns to image_settings.file_format
+impl Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Format::TGA => "TARGA",
+            Format::RAWTGA => "TARGA_RAW",
+            Format::JPEG { .. } => "JPEG",
+            Format::IRIS => "IRIS",
+            Format::AVIRAW => "AVI_RAW",
+            Format::AVIJPEG { .. } => "AVI_JPEG",
+            Format::PNG { .. } => "PNG",
+            Format::BMP => "BMP",
+            Format::HDR => "HDR",
+            Format::TIFF { .. } => "TIFF",
+            Format::OPEN_EXR { .. } => "OPEN_EXR",
+            Format::OPEN_EXR_MULTILAYER { .. } => "OPEN_EXR_MULTILAYER",
+            Format::WEBP { .. } => "WEBP",
+            Format::JPEG2000 { .. } => "JPEG2000",
+            Format::CINEON => "CINEON",
+            Format::DPX { .. } => "DPX",
+        };
+        write!(f, "{name}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAMES: [&str; 16] = [
+        "TARGA",
+        "TARGA_RAW",
+        "JPEG",
+        "IRIS",
+        "AVI_RAW",
+        "AVI_JPEG",
+        "PNG",
+        "BMP",
+        "HDR",
+        "TIFF",
+        "OPEN_EXR",
+        "OPEN_EXR_MULTILAYER",
+        "WEBP",
+        "JPEG2000",
+        "CINEON",
+        "DPX",
+    ];
+
+    #[test]
+    fn should_round_trip_every_format() {
+        for name in NAMES {
+            let format = Format::from_str(name).unwrap();
+            assert_eq!(format.to_string(), name);
+
+            let json = serde_json::to_string(&format).unwrap();
+            assert!(json.starts_with(&format!(r#"{{"file_format":"{name}""#)));
+            assert_eq!(serde_json::from_str::<Format>(&json).unwrap(), format);
         }
+        assert_eq!(
+            Format::from_str("GIF"),
+            Err(FormatError::UnknownFormat("GIF".to_owned()))
+        );
+    }
+
+    #[test]
+    fn should_serialize_options_as_image_settings() {
+        let mut exr = Format::from_str("OPEN_EXR").unwrap();
+        exr.set_color_depth(ColorDepth::Bits32).unwrap();
+        exr.set_codec("dwaa").unwrap();
+        let json = serde_json::to_string(&exr).unwrap();
+        assert_eq!(
+            json,
+            r#"{"file_format":"OPEN_EXR","color_depth":"32","exr_codec":"DWAA"}"#
+        );
+        assert_eq!(serde_json::from_str::<Format>(&json).unwrap(), exr);
+
+        let mut png = Format::default();
+        png.set_color_depth(ColorDepth::Bits16).unwrap();
+        png.set_quality(100).unwrap();
+        assert_eq!(
+            serde_json::to_string(&png).unwrap(),
+            r#"{"file_format":"PNG","color_depth":"16","compression":100}"#
+        );
+
+        // PNG doesn't do floats, and has no codec to pick.
+        assert!(png.set_color_depth(ColorDepth::Bits32).is_err());
+        assert!(png.set_codec("ZIP").is_err());
+        assert!(exr.set_codec("MP3").is_err());
     }
 }
@@ -1,8 +1,38 @@
 // use std::default;
-use std::ops::Range;
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ModeError {
+    #[error("No frames provided!")]
+    Empty,
+    #[error("Invalid frame: {0}")]
+    InvalidFrame(String),
+    #[error("Invalid step: {0}. Step must be greater than zero")]
+    InvalidStep(String),
+    #[error("Range end before start: {0}")]
+    ReversedRange(String),
+}
+
+/// Inclusive range of frames, rendering every step-th frame. E.g. "10..20x2" renders 10, 12, ... 20
+#[derive(Debug, Clone, PartialEq, Hash, Serialize, Deserialize)]
+pub struct FrameRange {
+    pub start: i32,
+    pub end: i32,
+    pub step: i32,
+}
+
+impl FrameRange {
+    fn frames(&self) -> impl Iterator<Item = i32> {
+        (self.start..=self.end).step_by(self.step.max(1) as usize)
+    }
+}
 
 // context for serde: https://serde.rs/enum-representations.html
+// New variants go at the end, jobs saved in the database still use the old representation for Frame and Animation.
 #[derive(Debug, Clone, PartialEq, Hash, Serialize, Deserialize)]
 pub enum Mode {
     // JSON: "Frame": "i32",
@@ -12,12 +42,259 @@ pub enum Mode {
     // JSON: "Animation": {"start":"i32", "end":"i32"}
     // contains the target start frame to the end target frame.
     Animation(Range<i32>),
-    
-    // future project - allow network node to only render section of the frame instead of whole to visualize realtime rendering view solution.
-    // JSON: "Section": {"frame":"i32", "coord":{"i32", "i32"}, "size": {"i32", "i32"} }
-    // Section {
-    //     frame: i32,
-    //     coord: (i32, i32),
-    //     size: (i32, i32),
-    // },
+
+    // JSON: "Step": {"range": {"start":"i32", "end":"i32"}, "step":"i32"}
+    // every step-th frame from start to end. Handy for previews.
+    Step { range: Range<i32>, step: i32 },
+
+    // JSON: "List": ["i32"]
+    // hand picked frames, e.g. re-render the frames that failed.
+    List(Vec<i32>),
+
+    // JSON: "Ranges": [{"start":"i32", "end":"i32", "step":"i32"}]
+    // several ranges at once, e.g. "1..10,20..30x2"
+    Ranges(Vec<FrameRange>),
+
+    // JSON: "Section": {"frame":"i32", "columns":"u16", "rows":"u16"}
+    // split a single heavy frame into a grid of border regions, so different workers can render a piece each.
+    // See Window::grid() for the regions.
+    Section { frame: i32, columns: u16, rows: u16 },
+}
+
+impl Mode {
+    /// Every frame to render, sorted and without duplicates. End frames are inclusive, same as blender.
+    pub fn frames(&self) -> Vec<i32> {
+        let mut frames: Vec<i32> = match self {
+            Mode::Frame(frame) | Mode::Section { frame, .. } => vec![*frame],
+            Mode::Animation(range) => (range.start..=range.end).collect(),
+            Mode::Step { range, step } => (range.start..=range.end)
+                .step_by((*step).max(1) as usize)
+                .collect(),
+            Mode::List(frames) => frames.clone(),
+            Mode::Ranges(ranges) => ranges.iter().flat_map(FrameRange::frames).collect(),
+        };
+        frames.sort_unstable();
+        frames.dedup();
+        frames
+    }
+
+    /// Distance between frames, if every range shares the same step. Otherwise 1.
+    pub fn step(&self) -> i32 {
+        match self {
+            Mode::Step { step, .. } => (*step).max(1),
+            Mode::Ranges(ranges) => match ranges.first() {
+                Some(first) if ranges.iter().all(|r| r.step == first.step) => first.step.max(1),
+                _ => 1,
+            },
+            _ => 1,
+        }
+    }
+
+    // Pick the simplest mode that represent the ranges.
+    fn from_ranges(mut ranges: Vec<FrameRange>) -> Self {
+        if ranges.len() == 1 {
+            let FrameRange { start, end, step } = ranges.remove(0);
+            return match (start == end, step) {
+                (true, _) => Mode::Frame(start),
+                (false, 1) => Mode::Animation(start..end),
+                (false, step) => Mode::Step {
+                    range: start..end,
+                    step,
+                },
+            };
+        }
+
+        if ranges.iter().all(|r| r.start == r.end) {
+            return Mode::List(ranges.into_iter().map(|r| r.start).collect());
+        }
+
+        Mode::Ranges(ranges)
+    }
+}
+
+// Parse a single piece of the expression. E.g. "5", "1..10", "1-10", "1-100x5"
+fn parse_range(part: &str) -> Result<FrameRange, ModeError> {
+    let (range, step) = match part.split_once('x') {
+        Some((range, step)) => {
+            let step = step
+                .trim()
+                .parse::<i32>()
+                .ok()
+                .filter(|s| *s > 0)
+                .ok_or_else(|| ModeError::InvalidStep(part.to_owned()))?;
+            (range.trim(), step)
+        }
+        None => (part, 1),
+    };
+
+    // frames can be negative, so the leading '-' is a sign and not a range separator.
+    let (start, end) = match range.split_once("..") {
+        Some(pair) => pair,
+        None => match range.get(1..).and_then(|rest| rest.find('-')) {
+            Some(index) => (&range[..index + 1], &range[index + 2..]),
+            None => (range, range),
+        },
+    };
+
+    let parse = |frame: &str| {
+        frame
+            .trim()
+            .parse::<i32>()
+            .map_err(|_| ModeError::InvalidFrame(part.to_owned()))
+    };
+    let (start, end) = (parse(start)?, parse(end)?);
+    if end < start {
+        return Err(ModeError::ReversedRange(part.to_owned()));
+    }
+
+    Ok(FrameRange { start, end, step })
+}
+
+/// Blender style frame expression. Comma separated frames and ranges, ranges use ".." or "-" and an optional "x" step.
+/// E.g. "1,5,10-20" or "1..250x10"
+impl FromStr for Mode {
+    type Err = ModeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let ranges = s
+            .split(',')
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+            .map(parse_range)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if ranges.is_empty() {
+            return Err(ModeError::Empty);
+        }
+        Ok(Mode::from_ranges(ranges))
+    }
+}
+
+impl fmt::Display for FrameRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.start == self.end, self.step) {
+            (true, _) => write!(f, "{}", self.start),
+            (false, 1) => write!(f, "{}..{}", self.start, self.end),
+            (false, step) => write!(f, "{}..{}x{}", self.start, self.end, step),
+        }
+    }
+}
+
+// Writes the mode back out as a frame expression that FromStr understands. Except for Section, which also shows the grid.
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ranges = match self {
+            Mode::Section {
+                frame,
+                columns,
+                rows,
+            } => return write!(f, "{frame} ({columns}x{rows} regions)"),
+            Mode::Frame(frame) => vec![FrameRange {
+                start: *frame,
+                end: *frame,
+                step: 1,
+            }],
+            Mode::Animation(range) => vec![FrameRange {
+                start: range.start,
+                end: range.end,
+                step: 1,
+            }],
+            Mode::Step { range, step } => vec![FrameRange {
+                start: range.start,
+                end: range.end,
+                step: *step,
+            }],
+            Mode::List(frames) => frames
+                .iter()
+                .map(|frame| FrameRange {
+                    start: *frame,
+                    end: *frame,
+                    step: 1,
+                })
+                .collect(),
+            Mode::Ranges(ranges) => ranges.clone(),
+        };
+
+        let expression = ranges
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(",");
+        write!(f, "{expression}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_frame_expressions() {
+        assert_eq!("7".parse(), Ok(Mode::Frame(7)));
+        assert_eq!("1-250".parse(), Ok(Mode::Animation(1..250)));
+        assert_eq!("-10..-2".parse(), Ok(Mode::Animation(-10..-2)));
+        assert_eq!(
+            "1..100x5".parse(),
+            Ok(Mode::Step {
+                range: 1..100,
+                step: 5
+            })
+        );
+        assert_eq!("3, 1, 8".parse(), Ok(Mode::List(vec![3, 1, 8])));
+
+        let mode: Mode = "1,5,10-20".parse().unwrap();
+        assert_eq!(mode.frames(), [vec![1, 5], (10..=20).collect()].concat());
+        assert_eq!(mode.step(), 1);
+    }
+
+    #[test]
+    fn should_reject_bad_expressions() {
+        assert_eq!("".parse::<Mode>(), Err(ModeError::Empty));
+        assert_eq!(
+            "1,a".parse::<Mode>(),
+            Err(ModeError::InvalidFrame("a".to_owned()))
+        );
+        assert_eq!(
+            "1-10x0".parse::<Mode>(),
+            Err(ModeError::InvalidStep("1-10x0".to_owned()))
+        );
+        assert_eq!(
+            "10-1".parse::<Mode>(),
+            Err(ModeError::ReversedRange("10-1".to_owned()))
+        );
+    }
+
+    #[test]
+    fn should_list_frames_once_in_order() {
+        let mode: Mode = "20..30x5,1..3,2,25".parse().unwrap();
+        assert_eq!(mode.frames(), vec![1, 2, 3, 20, 25, 30]);
+        // start after end has nothing to render.
+        let reversed = Mode::Animation(Range { start: 5, end: 2 });
+        assert!(reversed.frames().is_empty());
+    }
+
+    #[test]
+    fn should_display_as_expression() {
+        for expression in ["4", "-5..5", "1..99x7", "1,4,9", "1..10,20..30x2"] {
+            let mode: Mode = expression.parse().unwrap();
+            assert_eq!(mode.to_string(), expression);
+        }
+    }
+
+    #[test]
+    fn should_read_modes_saved_before_new_variants() {
+        let frame: Mode = serde_json::from_str(r#"{"Frame":12}"#).unwrap();
+        assert_eq!(frame, Mode::Frame(12));
+        let animation: Mode =
+            serde_json::from_str(r#"{"Animation":{"start":1,"end":250}}"#).unwrap();
+        assert_eq!(animation, Mode::Animation(1..250));
+        assert_eq!(
+            serde_json::to_string(&animation).unwrap(),
+            r#"{"Animation":{"start":1,"end":250}}"#
+        );
+
+        let ranges: Mode = "1..10,20..30x2".parse().unwrap();
+        let json = serde_json::to_string(&ranges).unwrap();
+        assert_eq!(serde_json::from_str::<Mode>(&json).unwrap(), ranges);
+    }
 }
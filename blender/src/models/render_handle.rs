@@ -0,0 +1,138 @@
+use super::status::Status;
+use crate::blender::BlenderError;
+use std::process::{Child, ExitStatus};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvError, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread::sleep;
+use std::time::Duration;
+
+// how often we check on the child process when waiting for it to finish.
+const WAIT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Handle to an active blender render returned from [crate::blender::Blender::render].
+/// Use this to receive render status, or to cancel, pause, or wait for the render to finish.
+/// The handle can be shared across threads (e.g. wrap it in an Arc) so one side can listen for status while the other side controls the render.
+#[derive(Debug)]
+pub struct RenderHandle {
+    // status produced by the blender process
+    receiver: Mutex<Receiver<Status>>,
+    // used to emit the final status when we cancel the render ourselves.
+    sender: Sender<Status>,
+    // used to shut down the xml-rpc server
+    signal: Sender<Status>,
+    child: Arc<Mutex<Child>>,
+    paused: Arc<AtomicBool>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl RenderHandle {
+    pub(crate) fn new(
+        receiver: Receiver<Status>,
+        sender: Sender<Status>,
+        signal: Sender<Status>,
+        child: Arc<Mutex<Child>>,
+        paused: Arc<AtomicBool>,
+        cancelled: Arc<AtomicBool>,
+    ) -> Self {
+        Self {
+            receiver: Mutex::new(receiver),
+            sender,
+            signal,
+            child,
+            paused,
+            cancelled,
+        }
+    }
+
+    /// Block until blender sends the next status update.
+    pub fn recv(&self) -> Result<Status, RecvError> {
+        match self.receiver.lock() {
+            Ok(receiver) => receiver.recv(),
+            Err(_) => Err(RecvError),
+        }
+    }
+
+    /// Return the next status update if there is one available.
+    pub fn try_recv(&self) -> Result<Status, TryRecvError> {
+        match self.receiver.lock() {
+            Ok(receiver) => receiver.try_recv(),
+            Err(_) => Err(TryRecvError::Disconnected),
+        }
+    }
+
+    /// Stop the render. This kills blender, shut down the xml-rpc server, and emits a final [Status::Exit].
+    pub fn cancel(&self) -> Result<(), BlenderError> {
+        self.cancelled.store(true, Ordering::SeqCst);
+        self.paused.store(false, Ordering::SeqCst);
+
+        {
+            let mut child = self
+                .child
+                .lock()
+                .map_err(|e| BlenderError::ProcessError(e.to_string()))?;
+
+            // if the process already exit on its own, there's nothing left to kill.
+            if let Ok(None) = child.try_wait() {
+                child
+                    .kill()
+                    .map_err(|e| BlenderError::ProcessError(e.to_string()))?;
+                // reap the process so it doesn't linger around as a zombie.
+                let _ = child.wait();
+            }
+        }
+
+        let _ = self.signal.send(Status::Exit);
+        let _ = self.sender.send(Status::Exit);
+        Ok(())
+    }
+
+    /// Hold off handing out the next frame. The frame blender is currently rendering will still finish.
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    /// Resume handing out frames after [RenderHandle::pause]
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Check whether the blender process is still running.
+    pub fn is_running(&self) -> bool {
+        match self.child.lock() {
+            Ok(mut child) => matches!(child.try_wait(), Ok(None)),
+            Err(_) => false,
+        }
+    }
+
+    /// Block until blender exits and return the exit status of the process.
+    pub fn wait(&self) -> Result<ExitStatus, BlenderError> {
+        wait_for_exit(&self.child)
+    }
+}
+
+// Poll the child until it exits. We can't hold on to the lock with child.wait() or else cancel() would never get through.
+pub(crate) fn wait_for_exit(child: &Mutex<Child>) -> Result<ExitStatus, BlenderError> {
+    loop {
+        {
+            let mut child = child
+                .lock()
+                .map_err(|e| BlenderError::ProcessError(e.to_string()))?;
+            if let Some(status) = child
+                .try_wait()
+                .map_err(|e| BlenderError::ProcessError(e.to_string()))?
+            {
+                return Ok(status);
+            }
+        }
+        sleep(WAIT_INTERVAL);
+    }
+}
@@ -0,0 +1,33 @@
+use super::{
+    denoiser::Denoiser, device::Device, engine::Engine, format::Format, render_pass::RenderPass,
+};
+use serde::{Deserialize, Serialize};
+
+/// Settings picked for the job instead of the ones saved in the blend file. None keeps what's in the file, see BlenderRenderSetting::parse_from
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenderOverride {
+    pub scene: Option<String>,
+    pub camera: Option<String>,
+    /// Only render these view layers. Empty renders the layers the blend file uses.
+    pub view_layers: Vec<String>,
+    pub engine: Option<Engine>,
+    /// The blend file doesn't know which device the worker has. None lets the host pick the best one each worker has, see Device::best()
+    pub device: Option<Device>,
+    pub samples: Option<i32>,
+    /// Only used by Cycles.
+    pub denoiser: Option<Denoiser>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub resolution_percentage: Option<u16>,
+    pub format: Option<Format>,
+    /// Passes to turn on for every view layer that renders.
+    pub passes: Vec<RenderPass>,
+}
+
+impl RenderOverride {
+    /// Nothing is overridden, the blend file renders as it was saved.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
@@ -0,0 +1,156 @@
+/*
+    Developer blog:
+    - Passes are turned on per view layer, on every layer the job renders.
+    - With OPEN_EXR_MULTILAYER every pass goes into the one image blender saves.
+        Any other format can't hold them, so render.py adds a File Output node to the compositor and writes each pass to its own 32 bit EXR next to the image.
+        EXR because depth, normal and vector passes don't fit in 8 bit, and cryptomatte is useless without full float.
+    - AOVs are declared on the view layer by name. The materials write to them with an AOV Output node of the same name.
+*/
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum AovError {
+    #[error("AOV needs a name: {0}")]
+    MissingName(String),
+    #[error("Unknown AOV type: {0}")]
+    UnknownKind(String),
+}
+
+/// Data type of an AOV, see view_layer.aovs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AovKind {
+    #[serde(rename = "COLOR")]
+    Color,
+    #[serde(rename = "VALUE")]
+    Value,
+}
+
+/// Custom shader output, filled in by an AOV Output node in the materials.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Aov {
+    pub name: String,
+    #[serde(rename = "Type")]
+    pub kind: AovKind,
+}
+
+// "Name" for a color AOV, or "Name:value" for a single channel one.
+impl FromStr for Aov {
+    type Err = AovError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, kind) = match s.trim().split_once(':') {
+            Some((name, kind)) => (name.trim(), kind.trim().to_uppercase()),
+            None => (s.trim(), "COLOR".to_owned()),
+        };
+        if name.is_empty() {
+            return Err(AovError::MissingName(s.to_owned()));
+        }
+        let kind = match kind.as_str() {
+            "COLOR" => AovKind::Color,
+            "VALUE" => AovKind::Value,
+            _ => return Err(AovError::UnknownKind(kind)),
+        };
+        Ok(Aov {
+            name: name.to_owned(),
+            kind,
+        })
+    }
+}
+
+/// Render pass the job wants next to the image.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RenderPass {
+    /// Z depth
+    Depth,
+    Mist,
+    Normal,
+    Vector,
+    Position,
+    DiffuseDirect,
+    DiffuseIndirect,
+    DiffuseColor,
+    GlossyDirect,
+    GlossyIndirect,
+    GlossyColor,
+    TransmissionDirect,
+    TransmissionIndirect,
+    TransmissionColor,
+    Emission,
+    Environment,
+    AmbientOcclusion,
+    Shadow,
+    CryptomatteObject,
+    CryptomatteMaterial,
+    CryptomatteAsset,
+    Aov(Aov),
+}
+
+impl RenderPass {
+    /// Property of the view layer that turns the pass on. None for AOVs, those are added to view_layer.aovs instead.
+    pub fn property(&self) -> Option<&'static str> {
+        let property = match self {
+            RenderPass::Depth => "use_pass_z",
+            RenderPass::Mist => "use_pass_mist",
+            RenderPass::Normal => "use_pass_normal",
+            RenderPass::Vector => "use_pass_vector",
+            RenderPass::Position => "use_pass_position",
+            RenderPass::DiffuseDirect => "use_pass_diffuse_direct",
+            RenderPass::DiffuseIndirect => "use_pass_diffuse_indirect",
+            RenderPass::DiffuseColor => "use_pass_diffuse_color",
+            RenderPass::GlossyDirect => "use_pass_glossy_direct",
+            RenderPass::GlossyIndirect => "use_pass_glossy_indirect",
+            RenderPass::GlossyColor => "use_pass_glossy_color",
+            RenderPass::TransmissionDirect => "use_pass_transmission_direct",
+            RenderPass::TransmissionIndirect => "use_pass_transmission_indirect",
+            RenderPass::TransmissionColor => "use_pass_transmission_color",
+            RenderPass::Emission => "use_pass_emit",
+            RenderPass::Environment => "use_pass_environment",
+            RenderPass::AmbientOcclusion => "use_pass_ambient_occlusion",
+            RenderPass::Shadow => "use_pass_shadow",
+            RenderPass::CryptomatteObject => "use_pass_cryptomatte_object",
+            RenderPass::CryptomatteMaterial => "use_pass_cryptomatte_material",
+            RenderPass::CryptomatteAsset => "use_pass_cryptomatte_asset",
+            RenderPass::Aov(_) => return None,
+        };
+        Some(property)
+    }
+
+    pub fn aov(&self) -> Option<&Aov> {
+        match self {
+            RenderPass::Aov(aov) => Some(aov),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_aov() {
+        let aov = Aov::from_str(" Dirt : value").unwrap();
+        assert_eq!(aov.name, "Dirt");
+        assert_eq!(aov.kind, AovKind::Value);
+        assert_eq!(Aov::from_str("Tint").unwrap().kind, AovKind::Color);
+        assert_eq!(
+            Aov::from_str(":color"),
+            Err(AovError::MissingName(":color".to_owned()))
+        );
+        assert_eq!(
+            Aov::from_str("Tint:vector"),
+            Err(AovError::UnknownKind("VECTOR".to_owned()))
+        );
+
+        // render.py reads the AOV by the names blender uses.
+        assert_eq!(
+            serde_json::to_string(&aov).unwrap(),
+            r#"{"Name":"Dirt","Type":"VALUE"}"#
+        );
+        assert_eq!(RenderPass::Aov(aov).property(), None);
+        assert_eq!(RenderPass::Depth.property(), Some("use_pass_z"));
+    }
+}
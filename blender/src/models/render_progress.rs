@@ -0,0 +1,126 @@
+use crate::blender::Frame;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+
+/// What blender is currently doing with the frame.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RenderPhase {
+    /// Preparing the scene before rendering (Synchronizing objects, loading kernels, building BVH, etc.)
+    Preparing {
+        message: String,
+    },
+    /// Cycles, EEVEE and Workbench report progress in samples.
+    Sample {
+        current: u32,
+        total: u32,
+    },
+    /// Older version of Cycles (< 3.0) report progress in tiles.
+    Tile {
+        current: u32,
+        total: u32,
+    },
+    Denoising,
+    Compositing {
+        message: String,
+    },
+    /// Frame has been rendered and written to disk. Duration is the time it took to save the image.
+    Saving {
+        duration: Duration,
+    },
+    Finished,
+}
+
+/// Typed view of a single progress line from blender's stdout, e.g.
+/// `Fra:1 Mem:159.46M (Peak 159.46M) | Time:00:01.45 | Remaining:00:08.25 | Mem:10.84M, Peak:10.84M | Scene, ViewLayer | Sample 1/128`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RenderProgress {
+    pub frame: Frame,
+    /// Memory usage in megabytes
+    pub memory: Option<f32>,
+    /// Peak memory usage in megabytes
+    pub peak_memory: Option<f32>,
+    pub elapsed: Option<Duration>,
+    pub remaining: Option<Duration>,
+    pub scene: Option<String>,
+    pub view_layer: Option<String>,
+    pub phase: RenderPhase,
+}
+
+impl RenderProgress {
+    pub fn new(frame: Frame, phase: RenderPhase) -> Self {
+        Self {
+            frame,
+            memory: None,
+            peak_memory: None,
+            elapsed: None,
+            remaining: None,
+            scene: None,
+            view_layer: None,
+            phase,
+        }
+    }
+
+    /// Percentage of the current frame completed, if blender told us how far along it is.
+    pub fn percentage(&self) -> Option<f32> {
+        match self.phase {
+            RenderPhase::Sample { current, total } | RenderPhase::Tile { current, total }
+                if total > 0 =>
+            {
+                Some(current as f32 / total as f32 * 100.0)
+            }
+            RenderPhase::Saving { .. } | RenderPhase::Finished => Some(100.0),
+            _ => None,
+        }
+    }
+}
+
+// Display duration the same way blender does. E.g. 01:02.45
+fn fmt_duration(duration: &Duration) -> String {
+    let secs = duration.as_secs();
+    let centis = duration.subsec_millis() / 10;
+    match secs / 3600 {
+        0 => format!("{:02}:{:02}.{:02}", secs / 60, secs % 60, centis),
+        hours => format!(
+            "{:02}:{:02}:{:02}.{:02}",
+            hours,
+            (secs % 3600) / 60,
+            secs % 60,
+            centis
+        ),
+    }
+}
+
+impl fmt::Display for RenderPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderPhase::Preparing { message } => write!(f, "{message}"),
+            RenderPhase::Sample { current, total } => write!(f, "Sample {current}/{total}"),
+            RenderPhase::Tile { current, total } => write!(f, "Tile {current}/{total}"),
+            RenderPhase::Denoising => write!(f, "Denoising"),
+            RenderPhase::Compositing { message } if message.is_empty() => write!(f, "Compositing"),
+            RenderPhase::Compositing { message } => write!(f, "Compositing | {message}"),
+            RenderPhase::Saving { duration } => write!(f, "Saving {}", fmt_duration(duration)),
+            RenderPhase::Finished => write!(f, "Finished"),
+        }
+    }
+}
+
+impl fmt::Display for RenderProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Frame {} | {}", self.frame, self.phase)?;
+        if let Some(percentage) = self.percentage() {
+            write!(f, " ({percentage:.2}%)")?;
+        }
+        if let Some(elapsed) = &self.elapsed {
+            write!(f, " | Time {}", fmt_duration(elapsed))?;
+        }
+        if let Some(remaining) = &self.remaining {
+            write!(f, " | Remaining {}", fmt_duration(remaining))?;
+        }
+        if let Some(memory) = self.memory {
+            write!(f, " | Mem {memory:.2}M")?;
+        }
+        Ok(())
+    }
+}
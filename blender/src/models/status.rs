@@ -1,3 +1,4 @@
+use super::render_progress::RenderProgress;
 use crate::blender::BlenderError;
 use std::path::PathBuf;
 
@@ -5,10 +6,21 @@ use std::path::PathBuf;
 #[derive(Debug)]
 pub enum Status {
     Idle,
-    Running { status: String },
-    Log { status: String },
-    Warning { message: String },
+    Running {
+        progress: RenderProgress,
+    },
+    Log {
+        status: String,
+    },
+    Warning {
+        message: String,
+    },
     Error(BlenderError),
-    Completed { frame: i32, result: PathBuf },
+    /// Frame is done. Result is the image, passes are the render passes render.py wrote next to it. Multilayer EXR holds the passes in the image itself.
+    Completed {
+        frame: i32,
+        result: PathBuf,
+        passes: Vec<PathBuf>,
+    },
     Exit,
 }
@@ -0,0 +1,14 @@
+# Lists the cycles device backends this blender build can use on this machine. See Blender::devices()
+# Prints one "DEVICE: <backend>" line per backend that found at least one graphic card. CPU is always available, so it's left out.
+import bpy # type: ignore
+
+cyclesPref = bpy.context.preferences.addons["cycles"].preferences
+
+for backend in ("OPTIX", "CUDA", "HIP", "METAL", "ONEAPI", "OPENCL"):
+    try:
+        devices = cyclesPref.get_devices_for_type(backend)
+    except Exception:
+        # this build of blender doesn't know about the backend (e.g. OpenCL was removed in 3.0)
+        continue
+    if any(d.type != "CPU" for d in devices):
+        print("DEVICE: " + backend, flush=True)
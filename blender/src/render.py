@@ -4,6 +4,7 @@
 
 #Start
 import bpy # type: ignore
+import sys
 import xmlrpc.client
 from multiprocessing import cpu_count
 
@@ -62,6 +63,44 @@ def useDevices(type, allowGPU, allowCPU):
             d.use = (allowCPU and d.type == "CPU") or (allowGPU and d.type != "CPU")
             print(type + " Device:", d["name"], d["use"])
 
+# Write every enabled pass of the rendering view layers to its own EXR, for formats that can't hold the passes in the image.
+# Returns the path of the files blender will save for the frame.
+def outputPasses(output, frame):
+    scn.use_nodes = True
+    scn.render.use_compositing = True
+    tree = scn.node_tree
+
+    # remove the nodes we added for the previous frame
+    for node in [node for node in tree.nodes if node.name.startswith("BlendFarm")]:
+        tree.nodes.remove(node)
+
+    files = []
+    for layer in scn.view_layers:
+        if not layer.use:
+            continue
+        renderLayer = tree.nodes.new("CompositorNodeRLayers")
+        renderLayer.name = "BlendFarm " + layer.name
+        renderLayer.scene = scn
+        renderLayer.layer = layer.name
+
+        fileOutput = tree.nodes.new("CompositorNodeOutputFile")
+        fileOutput.name = "BlendFarm Output " + layer.name
+        fileOutput.base_path = output
+        # depth, normal and cryptomatte need full float
+        fileOutput.format.file_format = "OPEN_EXR"
+        fileOutput.format.color_depth = "32"
+        fileOutput.file_slots.clear()
+
+        for socket in renderLayer.outputs:
+            # the image itself is saved by blender as usual.
+            if not socket.enabled or socket.name in ("Image", "Alpha"):
+                continue
+            path = layer.name + "_" + socket.name + "_#####"
+            fileOutput.file_slots.new(path)
+            tree.links.new(socket, fileOutput.inputs[-1])
+            files.append(output + '/' + path.replace("#####", str(frame).zfill(5)) + ".exr")
+    return files
+
 #Renders provided settings with id to path
 def renderWithSettings(renderSettings, frame):
     global scn
@@ -70,18 +109,22 @@ def renderWithSettings(renderSettings, frame):
     scen = renderSettings["Scene"]
     if(scen is None):
         scen = ""
-    if(scen != "" + scn.name != scen):
+    if(scen != "" and scen != scn.name):
         print("Rendering specified scene " + scen + "\n")
-        scn = bpy.data.scenes[scen]
+        scn = bpy.data.scenes.get(scen)
         if(scn is None):
             raise Exception("Unknown Scene :" + scen)
 
-    # set render format 
-    renderFormat = renderSettings["RenderFormat"]
-    if (not renderFormat):
-        scn.render.image_settings.file_format = "PNG"
-    else:
-        scn.render.image_settings.file_format = renderFormat
+    # set render format and its options, missing keeps the format saved in the blend file
+    # the keys are the names of the image_settings properties, see format.rs
+    renderFormat = renderSettings.get("RenderFormat")
+    if (renderFormat):
+        imageSettings = scn.render.image_settings
+        # the format decides which options are available, so it goes first.
+        imageSettings.file_format = renderFormat["file_format"]
+        for key, value in renderFormat.items():
+            if key != "file_format":
+                setattr(imageSettings, key, value)
         
     # Set threading
     scn.render.threads_mode = 'FIXED'
@@ -106,31 +149,65 @@ def renderWithSettings(renderSettings, frame):
 
     #Set Camera
     camera = renderSettings["Camera"]
-    if(camera != None and camera != "" and bpy.data.objects[camera]):
+    if(camera != None and camera != ""):
+        if(bpy.data.objects.get(camera) is None):
+            raise Exception("Unknown Camera :" + camera)
         scn.camera = bpy.data.objects[camera]
 
+    #Set View Layers - only render the ones we were asked for, none keeps the layers the blend file uses
+    viewLayers = renderSettings["ViewLayers"]
+    if viewLayers:
+        for name in viewLayers:
+            if(scn.view_layers.get(name) is None):
+                raise Exception("Unknown View Layer :" + name)
+        for layer in scn.view_layers:
+            layer.use = layer.name in viewLayers
+
+    #Set Passes and AOVs on every layer that renders
+    passes = renderSettings["Passes"]
+    aovs = renderSettings["Aovs"]
+    for layer in scn.view_layers:
+        if not layer.use:
+            continue
+        for name in passes:
+            # some passes only exist for some engines or versions of blender, e.g. use_pass_position
+            if hasattr(layer, name):
+                setattr(layer, name, True)
+            else:
+                print("Warning: View layer " + layer.name + " has no " + name)
+        for aov in aovs:
+            if layer.aovs.get(aov["Name"]) is None:
+                added = layer.aovs.add()
+                added.name = aov["Name"]
+                added.type = aov["Type"]
+
     #Set Resolution
     scn.render.resolution_x = int(renderSettings["Width"])
     scn.render.resolution_y = int(renderSettings["Height"])
-    scn.render.resolution_percentage = 100
+    scn.render.resolution_percentage = int(renderSettings["ResolutionPercentage"])
+
+    # Set Engine, empty keeps the engine saved in the blend file
+    engine = renderSettings["Engine"]
+    if engine:
+        # blender uses the new BLENDER_EEVEE_NEXT enum for blender4.2 and above.
+        if engine == "BLENDER_EEVEE" and not isPreEeveeNext:
+            engine = "BLENDER_EEVEE_NEXT"
+        print("Using " + engine)
+        scn.render.engine = engine
 
-    #Set Samples
-    scn.cycles.samples = int(renderSettings["Samples"])
+    #Set Samples, 0 keeps the samples saved in the blend file
+    samples = int(renderSettings["Samples"])
+    if samples > 0:
+        if scn.render.engine == "CYCLES":
+            scn.cycles.samples = samples
+        elif scn.render.engine in ("BLENDER_EEVEE", "BLENDER_EEVEE_NEXT"):
+            scn.eevee.taa_render_samples = samples
     scn.render.use_persistent_data = True
 
-    #Render Device
+    #Render Device - only cycles lets us pick one
     renderType = int(renderSettings["ComputeUnit"])
-    engine = int(renderSettings["Engine"])
 
-    if(engine == 2): #Optix
-        optixGPU = renderType == 1 or renderType == 3 or renderType == 11 or renderType == 12; #CUDA or CUDA_GPU_ONLY
-        optixCPU = renderType != 3 and renderType != 12; #!CUDA_GPU_ONLY && !OPTIX_GPU_ONLY
-        if(optixCPU and not optixGPU):
-            scn.cycles.device = "CPU"
-        else:
-            scn.cycles.device = "GPU"
-        useDevices("OPTIX", optixGPU, optixCPU)
-    else: #Cycles/Eevee
+    if scn.render.engine == "CYCLES":
         if renderType == 0: #CPU
             scn.cycles.device = "CPU"
             print("Use CPU")
@@ -151,11 +228,11 @@ def renderWithSettings(renderSettings, frame):
             scn.cycles.device = 'GPU'
             print("Use OpenCL (GPU)")
         elif renderType == 5: #HIP
-            useDevices("HIP", True, False)
+            useDevices("HIP", True, True)
             scn.cycles.device = 'GPU'
             print("Use HIP")
         elif renderType == 6: #HIP (GPU Only)
-            useDevices("HIP", True, True)
+            useDevices("HIP", True, False)
             scn.cycles.device = 'GPU'
             print("Use HIP (GPU)")
         elif renderType == 7: #METAL
@@ -183,48 +260,56 @@ def renderWithSettings(renderSettings, frame):
             scn.cycles.device = "GPU"
             print("Use OptiX (GPU)")
 
-    # At the moment, we should derive to use the file settings instead of asking user to manually adjust. Remove denoiser if possible   
-    #Denoiser - Disable this until I can figure out how to fetch this info from Blend lib
-    # denoise = renderSettings["Denoiser"]
-    # if denoise is not None:
-    #     if denoise == "None":
-    #         scn.cycles.use_denoising = False
-    #     elif len(denoise) > 0:
-    #         scn.cycles.use_denoising = True
-    #         scn.cycles.denoiser = denoise
-            
+    #Denoiser, empty keeps the blend file's setting
+    denoise = renderSettings["Denoiser"]
+    if denoise and scn.render.engine == "CYCLES":
+        if denoise == "OFF":
+            scn.cycles.use_denoising = False
+        else:
+            scn.cycles.use_denoising = True
+            scn.cycles.denoiser = denoise
+
     # Set Frames Per Second
     fps = renderSettings["FPS"]
     if fps is not None and fps > 0:
         scn.render.fps = fps
 
-    # blender uses the new BLENDER_EEVEE_NEXT enum for blender4.2 and above.
-    if(engine == 1): #Eevee
-        if(isPreEeveeNext):
-            print("Using EEVEE")
-            scn.render.engine = "BLENDER_EEVEE"
-        else:
-            print("Using EEVEE_NEXT")
-            scn.render.engine = "BLENDER_EEVEE_NEXT"
-    else:
-        scn.render.engine = "CYCLES"
-    
     # Set frame
     scn.frame_set(frame)
     
     # Set Output
-    scn.render.filepath = renderSettings["Output"] + '/' + str(frame).zfill(5)
+    output = renderSettings["Output"]
+    scn.render.filepath = output + '/' + str(frame).zfill(5)
     id = str(renderSettings["TaskID"])
 
+    # Multilayer EXR saves the passes in the image, any other format gets a file per pass.
+    passFiles = []
+    if (passes or aovs) and scn.render.image_settings.file_format != "OPEN_EXR_MULTILAYER":
+        passFiles = outputPasses(output, frame)
+
     # Render
     print("RENDER_START: " + id + "\n", flush=True)
     # TODO: Research what use_viewport does?
-    bpy.ops.render.render(animation=False, write_still=True, use_viewport=False, layer="", scene = scen)
+    bpy.ops.render.render(animation=False, write_still=True, use_viewport=False, layer="", scene = scn.name)
+    # blender only reports the image it saved, BlendFarm needs to know about the passes too. Same format as blender's.
+    for path in passFiles:
+        print("Saved: '" + path + "'", flush=True)
     print("SUCCESS: " + id + "\n", flush=True)
 
+# Blender ignores any arguments after "--", we use them to receive arguments from BlendFarm. E.g. -- --port 8081
+def getArgument(name, default):
+    if "--" not in sys.argv:
+        return default
+    argv = sys.argv[sys.argv.index("--") + 1:]
+    if name in argv and argv.index(name) + 1 < len(argv):
+        return argv[argv.index(name) + 1]
+    return default
+
 def runBatch():
+    # Each render receive its own port to talk to BlendFarm
+    port = int(getArgument("--port", 8081))
     # Fatal exception was thrown [Errno 61] Connection refused - see if it's the firewall?
-    proxy = xmlrpc.client.ServerProxy("http://localhost:8081")
+    proxy = xmlrpc.client.ServerProxy("http://localhost:" + str(port))
     
     # Do i need to send in RPC like this or can it just be a value instead?
     renderSettings = None
@@ -236,12 +321,19 @@ def runBatch():
                 
     # Loop over batches
     while True:
+        # BlendFarm answers with a fault once there's no frames left, or the render was cancelled.
         try:
             frame = proxy.next_render_queue(1)
+        except xmlrpc.client.Fault as e:
+            print(e.faultString)
+            break
+
+        # anything else stops the batch, the frame we took from the queue didn't render.
+        try:
             renderWithSettings(renderSettings, frame)
         except Exception as e:
-            print(e)
-            break
+            print("EXCEPTION: Frame " + str(frame) + ": " + str(e), flush=True)
+            sys.exit(1)
     
     print("BATCH_COMPLETE\n")
 
@@ -251,4 +343,5 @@ try:
     runBatch()
 
 except Exception as e:
-    print("EXCEPTION:" + str(e))
\ No newline at end of file
+    print("EXCEPTION:" + str(e), flush=True)
+    sys.exit(1)
\ No newline at end of file
@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP TABLE failed_frames;
+ALTER TABLE jobs DROP COLUMN retry_different_worker;
+ALTER TABLE jobs DROP COLUMN max_attempts;
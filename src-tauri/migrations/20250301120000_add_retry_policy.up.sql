@@ -0,0 +1,14 @@
+-- Add up migration script here
+ALTER TABLE jobs ADD COLUMN max_attempts INTEGER NOT NULL DEFAULT 3;
+ALTER TABLE jobs ADD COLUMN retry_different_worker INTEGER NOT NULL DEFAULT 1;
+
+CREATE TABLE IF NOT EXISTS failed_frames(
+    job_id TEXT NOT NULL,
+    frame INTEGER NOT NULL,
+    attempts INTEGER NOT NULL,
+    error TEXT NOT NULL,
+    -- json list of workers that failed this frame
+    peers TEXT NOT NULL,
+    permanent INTEGER NOT NULL,
+    PRIMARY KEY (job_id, frame)
+);
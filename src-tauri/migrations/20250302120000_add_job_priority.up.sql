@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE jobs ADD COLUMN priority INTEGER NOT NULL DEFAULT 0;
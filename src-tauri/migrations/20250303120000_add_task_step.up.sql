@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE tasks ADD COLUMN step INTEGER NOT NULL DEFAULT 1;
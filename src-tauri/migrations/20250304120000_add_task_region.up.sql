@@ -0,0 +1,3 @@
+-- Add up migration script here
+-- json encoded border region, null renders the whole frame.
+ALTER TABLE tasks ADD COLUMN region TEXT;
@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE tasks DROP COLUMN blend_file_hash;
+ALTER TABLE jobs DROP COLUMN project_hash;
@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- BLAKE3 hash of the project file, files are shared on the network under it.
+ALTER TABLE jobs ADD COLUMN project_hash TEXT NOT NULL DEFAULT '';
+ALTER TABLE tasks ADD COLUMN blend_file_hash TEXT NOT NULL DEFAULT '';
@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- json encoded files the project needs next to it, see package.rs
+ALTER TABLE jobs ADD COLUMN package TEXT NOT NULL DEFAULT '{}';
+ALTER TABLE tasks ADD COLUMN dependencies TEXT NOT NULL DEFAULT '[]';
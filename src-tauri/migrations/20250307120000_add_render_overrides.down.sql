@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE tasks DROP COLUMN overrides;
+ALTER TABLE jobs DROP COLUMN overrides;
@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- json encoded RenderOverride, settings picked over the ones in the blend file
+ALTER TABLE jobs ADD COLUMN overrides TEXT NOT NULL DEFAULT '{}';
+ALTER TABLE tasks ADD COLUMN overrides TEXT NOT NULL DEFAULT '{}';
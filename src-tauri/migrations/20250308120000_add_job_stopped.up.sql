@@ -0,0 +1,3 @@
+-- Add up migration script here
+-- jobs the user stopped are not resumed when the manager restarts
+ALTER TABLE jobs ADD COLUMN stopped BOOLEAN NOT NULL DEFAULT 0;
@@ -0,0 +1,7 @@
+-- Add up migration script here
+-- every pass and AOV file a frame produced, next to the image kept in renders.
+CREATE TABLE IF NOT EXISTS render_passes(
+    render_path TEXT NOT NULL PRIMARY KEY,
+    jobs_id TEXT NOT NULL,
+    frame INTEGER NOT NULL
+);
@@ -1,5 +1,12 @@
-use crate::{domains::task_store::TaskError, models::job::Job};
+use crate::{
+    domains::task_store::TaskError,
+    models::{
+        job::{Frame, Job},
+        retry_policy::FrameFailure,
+    },
+};
 use serde::{Deserialize, Serialize};
+use std::path::Path;
 use thiserror::Error;
 use uuid::Uuid;
 
@@ -23,4 +30,27 @@ pub trait JobStore {
     async fn get_job(&self, job_id: &Uuid) -> Result<Job, JobError>;
     async fn update_job(&mut self, job: Job) -> Result<(), JobError>;
     async fn delete_job(&mut self, id: &Uuid) -> Result<(), JobError>;
+    // fetch the failure record of the frame, if this frame failed before.
+    async fn get_failure(
+        &self,
+        job_id: &Uuid,
+        frame: Frame,
+    ) -> Result<Option<FrameFailure>, JobError>;
+    // insert or update the failure record
+    async fn save_failure(&mut self, failure: FrameFailure) -> Result<(), JobError>;
+    async fn list_failures(&self, job_id: &Uuid) -> Result<Vec<FrameFailure>, JobError>;
+    // record the completed frame once the image is saved on the host.
+    async fn add_render(
+        &mut self,
+        job_id: &Uuid,
+        frame: Frame,
+        path: &Path,
+    ) -> Result<(), JobError>;
+    // record a render pass of the frame, there can be any number of them next to the image.
+    async fn add_render_pass(
+        &mut self,
+        job_id: &Uuid,
+        frame: Frame,
+        path: &Path,
+    ) -> Result<(), JobError>;
 }
@@ -17,8 +17,8 @@ pub enum TaskError {
 pub trait TaskStore {
     // append new task to queue
     async fn add_task(&mut self, task: Task) -> Result<(), TaskError>;
-    // Poll task will pop task entry from database
-    async fn poll_task(&mut self) -> Result<Task, TaskError>;
+    // Poll the oldest task from the queue. The task stays in the database until it's deleted, so we can resume after restart.
+    async fn poll_task(&mut self) -> Result<Option<Task>, TaskError>;
     // delete task by id
     async fn delete_task(&mut self, task: Task) -> Result<(), TaskError>;
     // delete all task with matching job id
@@ -1,17 +1,46 @@
+use super::job::JobEvent;
 use libp2p::{gossipsub, kad, mdns, ping, swarm::NetworkBehaviour};
 use libp2p_request_response::cbor;
 use serde::{Deserialize, Serialize};
 
+// Ask for part of a file. Files are sent a chunk at a time, see transfer.rs
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-pub struct FileRequest(pub String);
+pub struct FileRequest {
+    pub file_name: String,
+    pub offset: u64,
+    pub length: u64,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-pub struct FileResponse(pub Vec<u8>);
+pub enum FileResponse {
+    // part of the file starting at offset, along with the size of the whole file.
+    Chunk {
+        offset: u64,
+        size: u64,
+        data: Vec<u8>,
+    },
+    // we don't provide this file, or couldn't read it.
+    NotFound,
+}
+
+// Job event sent directly to one peer.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JobRequest(pub JobEvent);
+
+// Receiving peer let us know whether the job event was delivered.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JobResponse {
+    Ack,
+    Rejected(String),
+}
 
 #[derive(NetworkBehaviour)]
 pub struct BlendFarmBehaviour {
     pub ping: ping::Behaviour,
     // file transfer response protocol
     pub request_response: cbor::Behaviour<FileRequest, FileResponse>,
+    // send job event to a specific peer
+    pub job_message: cbor::Behaviour<JobRequest, JobResponse>,
     // Communication between peers to pepers
     pub gossipsub: gossipsub::Behaviour,
     // self discovery network service
@@ -0,0 +1,176 @@
+/*
+    Developer blog:
+    - Chunking decides how the frames of a job are split into tasks. Everything here works on a sorted list of frames,
+        so it doesn't matter whether the list came from the job's Mode or from the frames we're still missing after a restart.
+    - Ranges handed back are end exclusive, same as Task. With a step, the range covers start, start + step, ... up to end.
+    - Frames can be negative, blender allows it. Nothing in here assumes the frames start at zero or one.
+    - Adaptive chunks are only as good as the measurements. Work stealing picks up the slack when we guess wrong.
+*/
+use super::job::Frame;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+// how many frames a chunk gets when we have nothing else to go on.
+const DEFAULT_CHUNK_SIZE: usize = 30;
+
+/// How the frames of a job are split into tasks for the workers.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ChunkStrategy {
+    /// Every task renders at most this many frames.
+    Fixed(usize),
+    /// Split the frames into this many equal chunks for each worker.
+    PerWorker(usize),
+    /// Size each chunk to take roughly this many seconds, based on how fast the workers rendered so far.
+    Adaptive { seconds: u32 },
+}
+
+impl Default for ChunkStrategy {
+    fn default() -> Self {
+        ChunkStrategy::Fixed(DEFAULT_CHUNK_SIZE)
+    }
+}
+
+impl ChunkStrategy {
+    // Chunk sizes to hand out, cycled through until we run out of frames. Never empty, and never zero.
+    fn sizes(&self, total: usize, speeds: &[Option<f32>]) -> Vec<usize> {
+        let workers = speeds.len().max(1);
+        let sizes = match self {
+            ChunkStrategy::Fixed(size) => vec![*size],
+            ChunkStrategy::PerWorker(count) => vec![total.div_ceil(workers * (*count).max(1))],
+            ChunkStrategy::Adaptive { seconds } => {
+                let size_of =
+                    |secs: f32| (*seconds as f32 / secs.max(f32::EPSILON)).round() as usize;
+                let mut measured = speeds
+                    .iter()
+                    .flatten()
+                    .map(|secs| size_of(*secs))
+                    .collect::<Vec<_>>();
+                measured.sort_unstable();
+
+                // workers we haven't measured yet get the middle of the road.
+                // If nobody has been measured, hand out two chunks per worker and let work stealing even things out.
+                let fallback = match measured.get(measured.len() / 2) {
+                    Some(size) => *size,
+                    None => total.div_ceil(workers * 2),
+                };
+
+                let mut sizes = speeds
+                    .iter()
+                    .map(|speed| speed.map(size_of).unwrap_or(fallback))
+                    .collect::<Vec<_>>();
+                if sizes.is_empty() {
+                    sizes.push(fallback);
+                }
+                // fastest worker is usually the first one to ask for work.
+                sizes.sort_unstable_by(|a, b| b.cmp(a));
+                sizes
+            }
+        };
+        sizes.into_iter().map(|size| size.max(1)).collect()
+    }
+
+    /// Split the frames into ranges for the workers to render. Frames must be sorted.
+    /// A new range starts whenever the chunk is full, or the gap to the next frame isn't step (e.g. frames we already have).
+    /// speeds are the measured seconds per frame of each online worker, None if we haven't measured the worker yet.
+    pub fn split(
+        &self,
+        frames: &[Frame],
+        step: Frame,
+        speeds: &[Option<f32>],
+    ) -> Vec<Range<Frame>> {
+        let step = step.max(1);
+        let sizes = self.sizes(frames.len(), speeds);
+        let mut sizes = sizes.iter().cycle();
+        let mut size = 0;
+        let mut count = 0;
+        let mut chunks: Vec<Range<Frame>> = Vec::new();
+
+        for &frame in frames {
+            match chunks.last_mut() {
+                Some(range) if count < size && range.end - 1 + step == frame => {
+                    range.end = frame + 1;
+                    count += 1;
+                }
+                _ => {
+                    size = *sizes.next().unwrap_or(&1);
+                    count = 1;
+                    chunks.push(frame..frame + 1);
+                }
+            }
+        }
+        chunks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn strategy() -> impl Strategy<Value = ChunkStrategy> {
+        prop_oneof![
+            (0..50usize).prop_map(ChunkStrategy::Fixed),
+            (0..5usize).prop_map(ChunkStrategy::PerWorker),
+            (0..120u32).prop_map(|seconds| ChunkStrategy::Adaptive { seconds }),
+        ]
+    }
+
+    fn frames_of(chunks: &[Range<Frame>], step: Frame) -> Vec<Frame> {
+        chunks
+            .iter()
+            .flat_map(|range| range.clone().step_by(step as usize))
+            .collect()
+    }
+
+    proptest! {
+        #[test]
+        fn should_cover_every_frame_exactly_once(
+            start in -500..500i32,
+            len in 0..400i32,
+            step in 1..6i32,
+            strategy in strategy(),
+            speeds in prop::collection::vec(prop::option::of(0.0..60.0f32), 0..8),
+        ) {
+            let frames = (start..=start + len).step_by(step as usize).collect::<Vec<_>>();
+            let chunks = strategy.split(&frames, step, &speeds);
+
+            prop_assert!(chunks.iter().all(|range| !range.is_empty()));
+            prop_assert_eq!(frames_of(&chunks, step), frames);
+        }
+
+        #[test]
+        fn should_cover_frames_with_gaps(
+            frames in prop::collection::btree_set(-200..200i32, 0..100),
+            strategy in strategy(),
+        ) {
+            let frames = frames.into_iter().collect::<Vec<_>>();
+            let chunks = strategy.split(&frames, 1, &[None, Some(2.0)]);
+            prop_assert_eq!(frames_of(&chunks, 1), frames);
+        }
+
+        #[test]
+        fn should_not_exceed_fixed_size(size in 1..40usize, len in 0..300i32) {
+            let frames = (0..len).collect::<Vec<_>>();
+            let chunks = ChunkStrategy::Fixed(size).split(&frames, 1, &[]);
+            prop_assert!(chunks.iter().all(|range| range.len() <= size));
+        }
+    }
+
+    #[test]
+    fn should_split_equally_per_worker() {
+        let frames = (1..=100).collect::<Vec<_>>();
+        let chunks = ChunkStrategy::PerWorker(2).split(&frames, 1, &[None; 5]);
+        assert_eq!(chunks.len(), 10);
+        assert_eq!(chunks[0], 1..11);
+    }
+
+    #[test]
+    fn should_give_faster_workers_bigger_chunks() {
+        let frames = (1..=100).collect::<Vec<_>>();
+        // 2 seconds per frame and 10 seconds per frame, 60 second chunks.
+        let chunks =
+            ChunkStrategy::Adaptive { seconds: 60 }.split(&frames, 1, &[Some(10.0), Some(2.0)]);
+        assert_eq!(chunks[0].len(), 30);
+        assert_eq!(chunks[1].len(), 6);
+    }
+}
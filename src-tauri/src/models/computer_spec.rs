@@ -1,6 +1,20 @@
+use blender::models::device::Device;
 use machine_info::Machine;
 use serde::{Deserialize, Serialize};
 use std::env::consts;
+use thiserror::Error;
+
+// bincode only writes the values, in order. Old and new peers can't read each other's spec once a field is added or removed,
+// so the spec goes out with a version in front and we drop the ones we don't understand. Bump this whenever the fields change.
+pub const SPEC_VERSION: u16 = 2;
+
+#[derive(Debug, Error)]
+pub enum ComputerSpecError {
+    #[error("Peer sent spec version {0}, we only understand version {SPEC_VERSION}")]
+    UnsupportedVersion(u16),
+    #[error("Fail to parse computer spec: {0}")]
+    Decode(String),
+}
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ComputerSpec {
@@ -11,10 +25,14 @@ pub struct ComputerSpec {
     pub gpu: Option<String>,
     pub cpu: String,
     pub cores: usize,
+    /// Cycles backends blender found on this machine, see Blender::devices()
+    // default is only for workers saved in the database before we probed devices. bincode ignores it, see SPEC_VERSION.
+    #[serde(default)]
+    pub devices: Vec<Device>,
 }
 
 impl ComputerSpec {
-    pub fn new(machine: &mut Machine) -> Self {
+    pub fn new(machine: &mut Machine, devices: Vec<Device>) -> Self {
         let sys_info = machine.system_info();
         let memory = &sys_info.memory;
         let host = &sys_info.hostname;
@@ -32,6 +50,61 @@ impl ComputerSpec {
             gpu: gpu.to_owned(),
             cpu: sys_info.processor.brand.to_owned(),
             cores: cores.to_owned(),
+            devices,
         }
     }
+
+    /// Serialize the spec with SPEC_VERSION in front, to publish on the spec topic.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, bincode::Error> {
+        bincode::serialize(&(SPEC_VERSION, self))
+    }
+
+    /// Read a spec published by another peer. Fails if the peer runs a different spec version.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, ComputerSpecError> {
+        let version: u16 =
+            bincode::deserialize(data).map_err(|e| ComputerSpecError::Decode(e.to_string()))?;
+        if version != SPEC_VERSION {
+            return Err(ComputerSpecError::UnsupportedVersion(version));
+        }
+        let (_, spec): (u16, Self) =
+            bincode::deserialize(data).map_err(|e| ComputerSpecError::Decode(e.to_string()))?;
+        Ok(spec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec() -> ComputerSpec {
+        ComputerSpec {
+            host: "worker".to_owned(),
+            os: "linux".to_owned(),
+            arch: "x86_64".to_owned(),
+            memory: 16,
+            gpu: None,
+            cpu: "cpu".to_owned(),
+            cores: 8,
+            devices: vec![Device::CPU, Device::CUDA],
+        }
+    }
+
+    #[test]
+    fn should_read_back_spec() {
+        let data = spec().to_bytes().unwrap();
+        let result = ComputerSpec::from_bytes(&data).unwrap();
+        assert_eq!(result.host, "worker");
+        assert_eq!(result.devices, vec![Device::CPU, Device::CUDA]);
+    }
+
+    #[test]
+    fn should_reject_other_spec_versions() {
+        let data = bincode::serialize(&(SPEC_VERSION + 1, spec())).unwrap();
+        let result = ComputerSpec::from_bytes(&data);
+        assert!(
+            matches!(result, Err(ComputerSpecError::UnsupportedVersion(v)) if v == SPEC_VERSION + 1)
+        );
+        // garbage shouldn't panic either.
+        assert!(ComputerSpec::from_bytes(&[1]).is_err());
+    }
 }
@@ -1,2 +1,312 @@
 // use this to invoke FFMpeg to composite frame into short video animation.
 // this will be used on the manager side of application, as we want to composit incoming frame jobs into video to preview the final render image result.
+/*
+    Developer blog:
+    - Frames are handed to ffmpeg through the concat demuxer instead of an image sequence pattern.
+        Blender names frames however the output path says, and jobs can skip frames (steps, lists), so a pattern like %04d.png won't always work.
+    - With the concat list every image gets a duration. A draft from a partial set of frames holds the last frame we have until the next one shows up,
+        so the preview keeps the same length and timing as the final video.
+    - Quality is a percentage so the UI doesn't need to know about crf or prores profiles. Each codec maps it to whatever it uses.
+*/
+use super::job::Frame;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    str::FromStr,
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum FFmpegError {
+    #[error("Unable to find ffmpeg! Install it, or set the path to ffmpeg in the settings.")]
+    NotFound,
+    #[error("No frames to encode!")]
+    NoFrames,
+    #[error("Job is still missing {0} frames. Make a draft to preview what we have so far.")]
+    MissingFrames(usize),
+    #[error("Unknown codec: {0}")]
+    UnknownCodec(String),
+    #[error("Fail to run ffmpeg: {0}")]
+    Io(String),
+}
+
+/// Video formats we can encode to.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Codec {
+    /// H.264 in a mp4 container, plays pretty much everywhere.
+    H264,
+    /// Apple ProRes in a mov container, for editing.
+    ProRes,
+    /// VP9 in a webm container, for the web.
+    Vp9,
+}
+
+impl Codec {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Codec::H264 => "mp4",
+            Codec::ProRes => "mov",
+            Codec::Vp9 => "webm",
+        }
+    }
+
+    // encoder arguments for the quality, from 0 (smallest) to 100 (best).
+    fn args(&self, quality: u8) -> Vec<String> {
+        let quality = quality.min(100) as u32;
+        match self {
+            // crf 51 is the worst, 0 is lossless.
+            Codec::H264 => vec![
+                "-c:v".to_owned(),
+                "libx264".to_owned(),
+                "-crf".to_owned(),
+                (51 * (100 - quality) / 100).to_string(),
+                "-pix_fmt".to_owned(),
+                "yuv420p".to_owned(),
+                // yuv420p needs an even width and height.
+                "-vf".to_owned(),
+                "pad=ceil(iw/2)*2:ceil(ih/2)*2".to_owned(),
+            ],
+            // profiles go from proxy (0) to 4444 (4), only 4444 keeps the alpha channel.
+            Codec::ProRes => {
+                let profile = 4 * quality / 100;
+                let pix_fmt = match profile {
+                    4 => "yuva444p10le",
+                    _ => "yuv422p10le",
+                };
+                vec![
+                    "-c:v".to_owned(),
+                    "prores_ks".to_owned(),
+                    "-profile:v".to_owned(),
+                    profile.to_string(),
+                    "-pix_fmt".to_owned(),
+                    pix_fmt.to_owned(),
+                ]
+            }
+            // crf 63 is the worst, and the bitrate has to be 0 for crf to mean constant quality.
+            Codec::Vp9 => vec![
+                "-c:v".to_owned(),
+                "libvpx-vp9".to_owned(),
+                "-crf".to_owned(),
+                (63 * (100 - quality) / 100).to_string(),
+                "-b:v".to_owned(),
+                "0".to_owned(),
+                "-pix_fmt".to_owned(),
+                "yuva420p".to_owned(),
+            ],
+        }
+    }
+}
+
+impl fmt::Display for Codec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Codec::H264 => write!(f, "H264"),
+            Codec::ProRes => write!(f, "ProRes"),
+            Codec::Vp9 => write!(f, "VP9"),
+        }
+    }
+}
+
+impl FromStr for Codec {
+    type Err = FFmpegError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "h264" | "mp4" => Ok(Codec::H264),
+            "prores" | "mov" => Ok(Codec::ProRes),
+            "vp9" | "webm" => Ok(Codec::Vp9),
+            _ => Err(FFmpegError::UnknownCodec(s.to_owned())),
+        }
+    }
+}
+
+/// How the video is encoded.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VideoSetting {
+    pub codec: Codec,
+    /// Frames per second, use the fps from the blend file. See BlenderPeekResponse::fps
+    pub fps: u16,
+    /// From 0 (smallest file) to 100 (best quality).
+    pub quality: u8,
+}
+
+/// Progress of the encode, sent back the same way blender reports render progress.
+#[derive(Debug, Clone, Serialize)]
+pub enum VideoStatus {
+    Running { frame: u32, total: u32 },
+    Completed(PathBuf),
+    Error(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct FFmpeg {
+    executable: PathBuf,
+}
+
+impl FFmpeg {
+    /// Use the ffmpeg from the settings if it exists, otherwise look for it on PATH.
+    pub fn find(executable: Option<&Path>) -> Result<Self, FFmpegError> {
+        if let Some(executable) = executable.filter(|path| path.is_file()) {
+            return Ok(Self {
+                executable: executable.to_path_buf(),
+            });
+        }
+
+        let name = match cfg!(target_os = "windows") {
+            true => "ffmpeg.exe",
+            false => "ffmpeg",
+        };
+        let paths = std::env::var_os("PATH").ok_or(FFmpegError::NotFound)?;
+        std::env::split_paths(&paths)
+            .map(|dir| dir.join(name))
+            .find(|path| path.is_file())
+            .map(|executable| Self { executable })
+            .ok_or(FFmpegError::NotFound)
+    }
+
+    /// Encode the rendered frames into a video at output. frames are every frame the job renders, in order.
+    /// Unless this is a draft, every frame must be rendered. Drafts hold the last frame we have over the missing ones.
+    pub fn encode(
+        &self,
+        frames: &[Frame],
+        renders: &HashMap<Frame, PathBuf>,
+        setting: &VideoSetting,
+        output: &Path,
+        draft: bool,
+    ) -> Result<Receiver<VideoStatus>, FFmpegError> {
+        let missing = frames.iter().filter(|f| !renders.contains_key(f)).count();
+        if missing > 0 && !draft {
+            return Err(FFmpegError::MissingFrames(missing));
+        }
+
+        let (list, total) = concat_list(frames, renders, setting.fps);
+        if total == 0 {
+            return Err(FFmpegError::NoFrames);
+        }
+        let list_path = output.with_extension("ffconcat");
+        std::fs::write(&list_path, list).map_err(|e| FFmpegError::Io(e.to_string()))?;
+
+        let mut child = Command::new(&self.executable)
+            .args(["-y", "-loglevel", "error", "-nostats"])
+            .args(["-progress", "pipe:1"])
+            .args(["-f", "concat", "-safe", "0", "-i"])
+            .arg(&list_path)
+            .args(setting.codec.args(setting.quality))
+            .args(["-r", &setting.fps.max(1).to_string()])
+            .arg(output)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| FFmpegError::Io(e.to_string()))?;
+
+        let (sender, receiver) = mpsc::channel();
+        let output = output.to_path_buf();
+        thread::spawn(move || {
+            // drain stderr on its own, if ffmpeg fills the pipe while we wait on stdout neither side moves.
+            let stderr = child.stderr.take().map(|mut stderr| {
+                thread::spawn(move || {
+                    let mut error = String::new();
+                    let _ = stderr.read_to_string(&mut error);
+                    error
+                })
+            });
+
+            // ffmpeg writes key=value lines, frame= is how many frames it encoded so far.
+            if let Some(stdout) = child.stdout.take() {
+                for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                    if let Some(Ok(frame)) = line.strip_prefix("frame=").map(str::parse) {
+                        let _ = sender.send(VideoStatus::Running { frame, total });
+                    }
+                }
+            }
+
+            let error = stderr
+                .and_then(|handle| handle.join().ok())
+                .unwrap_or_default();
+            let status = match child.wait() {
+                Ok(status) if status.success() => VideoStatus::Completed(output),
+                Ok(status) => VideoStatus::Error(format!("ffmpeg {status}: {}", error.trim())),
+                Err(e) => VideoStatus::Error(e.to_string()),
+            };
+            let _ = std::fs::remove_file(list_path);
+            let _ = sender.send(status);
+        });
+
+        Ok(receiver)
+    }
+}
+
+// Build the concat list for ffmpeg, and how many frames the video ends up with.
+// Missing frames extend how long the previous image stays on screen. Frames missing at the start are left out.
+fn concat_list(frames: &[Frame], renders: &HashMap<Frame, PathBuf>, fps: u16) -> (String, u32) {
+    let mut entries: Vec<(&PathBuf, u32)> = Vec::new();
+    for frame in frames {
+        match (renders.get(frame), entries.last_mut()) {
+            (Some(path), _) => entries.push((path, 1)),
+            (None, Some((_, count))) => *count += 1,
+            (None, None) => {}
+        }
+    }
+
+    let fps = fps.max(1) as f64;
+    let mut list = "ffconcat version 1.0\n".to_owned();
+    for (path, count) in &entries {
+        // single quotes can't be escaped inside quotes, close the quote and escape it instead.
+        let path = path.to_string_lossy().replace('\'', r"'\''");
+        let duration = *count as f64 / fps;
+        list.push_str(&format!("file '{path}'\nduration {duration}\n"));
+    }
+    // ffmpeg ignores the duration of the last file unless it's listed again.
+    if let Some((path, _)) = entries.last() {
+        let path = path.to_string_lossy().replace('\'', r"'\''");
+        list.push_str(&format!("file '{path}'\n"));
+    }
+
+    (list, entries.iter().map(|(_, count)| count).sum())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_hold_last_frame_over_missing_frames() {
+        let renders = HashMap::from([
+            (2, PathBuf::from("/render/0002.png")),
+            (3, PathBuf::from("/render/it's 0003.png")),
+        ]);
+        let (list, total) = concat_list(&[1, 2, 3, 4, 5], &renders, 2);
+        // frame 1 is missing at the start, frame 3 stays on screen for 4 and 5.
+        assert_eq!(total, 4);
+        assert_eq!(
+            list,
+            "ffconcat version 1.0\n\
+             file '/render/0002.png'\nduration 0.5\n\
+             file '/render/it'\\''s 0003.png'\nduration 1.5\n\
+             file '/render/it'\\''s 0003.png'\n"
+        );
+    }
+
+    #[test]
+    fn should_refuse_missing_frames_unless_draft() {
+        let ffmpeg = FFmpeg {
+            executable: PathBuf::from("ffmpeg"),
+        };
+        let setting = VideoSetting {
+            codec: "webm".parse().unwrap(),
+            fps: 24,
+            quality: 80,
+        };
+        let renders = HashMap::from([(1, PathBuf::from("0001.png"))]);
+        let result = ffmpeg.encode(&[1, 2], &renders, &setting, Path::new("out.webm"), false);
+        assert!(matches!(result, Err(FFmpegError::MissingFrames(1))));
+        let result = ffmpeg.encode(&[1], &HashMap::new(), &setting, Path::new("out.webm"), true);
+        assert!(matches!(result, Err(FFmpegError::NoFrames)));
+    }
+}
@@ -6,12 +6,17 @@
     - I need to fetch the handles so that I can maintain and monitor all node activity.
     - TODO: See about migrating Sender code into this module?
 */
+use super::package::Package;
+use super::retry_policy::RetryPolicy;
 use super::task::Task;
 use crate::domains::job_store::JobError;
-use blender::models::mode::Mode;
+use blender::models::{
+    blender_render_setting::Window, mode::Mode, render_override::RenderOverride,
+};
 use semver::Version;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::ops::Range;
 use std::{hash::Hash, path::PathBuf};
 use uuid::Uuid;
 
@@ -19,14 +24,44 @@ use uuid::Uuid;
 pub enum JobEvent {
     Render(Task),
     Remove(Uuid),
+    /// Worker ran out of tasks and can take on more.
     RequestJob,
     ImageCompleted {
         job_id: Uuid,
         frame: Frame,
         file_name: String,
+        /// Render passes saved next to the image, shared the same way as file_name. Empty when the job has no passes or they're in a multilayer EXR.
+        #[serde(default)]
+        passes: Vec<String>,
+        /// Part of the frame this image covers, None for the whole frame.
+        region: Option<Window>,
+    },
+    /// Worker rendered every frame of the task.
+    JobComplete {
+        task_id: Uuid,
+    },
+    /// Worker fail to render the frame. Remaining are the frames from the same task that never got a chance to render.
+    FrameFailed {
+        task_id: Uuid,
+        job_id: Uuid,
+        frame: Frame,
+        error: String,
+        remaining: Option<Range<Frame>>,
+    },
+    /// Host asks the worker to give up the tail end of the task, so an idle worker can help out.
+    SplitTask {
+        task_id: Uuid,
+    },
+    /// Worker's answer to SplitTask. Frames are the ones the worker gave away, None if it had nothing to share.
+    TaskSplit {
+        task_id: Uuid,
+        frames: Option<Range<Frame>>,
+    },
+    /// Worker couldn't start the task.
+    Error {
+        task_id: Uuid,
+        error: JobError,
     },
-    JobComplete,
-    Error(JobError),
 }
 
 pub type Frame = i32;
@@ -41,12 +76,25 @@ pub struct Job {
     pub mode: Mode,
     /// Path to blender files
     pub project_file: PathBuf,
+    /// BLAKE3 hash of the project file, the file is shared on the network under this key. Filled in when the host starts providing it.
+    pub project_hash: String,
+    /// Files the project needs next to it, and where the project sits among them. Filled in along with the hash.
+    pub package: Package,
+    /// Settings to render with instead of the ones saved in the project file.
+    pub overrides: RenderOverride,
     // target blender version
     pub blender_version: Version,
     // target output destination
     pub output: PathBuf,
-    // completed render data.
-    // TODO: discuss this? Let's map this out and see how we can better utilize this structure?
+    /// What to do when a worker fail to render a frame.
+    pub retry_policy: RetryPolicy,
+    /// Jobs with higher priority are handed out to workers first.
+    pub priority: i32,
+    /// The user stopped the job. It stays around with what it rendered, but isn't resumed after a restart.
+    pub stopped: bool,
+    /// Render passes and AOVs saved next to the image of each completed frame, loaded from the render_passes table.
+    pub passes: HashMap<Frame, Vec<PathBuf>>,
+    // completed render data, loaded from the renders table.
     renders: HashMap<Frame, PathBuf>,
 }
 
@@ -58,14 +106,23 @@ impl Job {
         project_file: PathBuf,
         blender_version: Version,
         output: PathBuf,
+        retry_policy: RetryPolicy,
+        priority: i32,
         renders: HashMap<Frame, PathBuf>,
     ) -> Self {
         Self {
             id,
             mode,
             project_file,
+            project_hash: String::new(),
+            package: Package::default(),
+            overrides: RenderOverride::default(),
             blender_version,
             output,
+            retry_policy,
+            priority,
+            stopped: false,
+            passes: Default::default(),
             renders,
         }
     }
@@ -81,8 +138,15 @@ impl Job {
             id: Uuid::new_v4(),
             mode,
             project_file,
+            project_hash: String::new(),
+            package: Package::default(),
+            overrides: RenderOverride::default(),
             blender_version,
             output,
+            retry_policy: Default::default(),
+            priority: 0,
+            stopped: false,
+            passes: Default::default(),
             renders: Default::default(),
         }
     }
@@ -98,6 +162,24 @@ impl Job {
     pub fn get_version(&self) -> &Version {
         &self.blender_version
     }
+
+    /// Every frame this job renders, in order.
+    pub fn frames(&self) -> Vec<Frame> {
+        self.mode.frames()
+    }
+
+    /// Frames of this job we have yet to receive. Frames listed in skip are left out (e.g. frames that failed for good)
+    pub fn missing_frames(&self, skip: &[Frame]) -> Vec<Frame> {
+        self.frames()
+            .into_iter()
+            .filter(|frame| !self.renders.contains_key(frame) && !skip.contains(frame))
+            .collect()
+    }
+
+    /// Frames the host already received from the workers, and where the image was saved.
+    pub fn get_renders(&self) -> &HashMap<Frame, PathBuf> {
+        &self.renders
+    }
 }
 
 impl AsRef<Uuid> for Job {
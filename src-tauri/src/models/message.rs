@@ -1,6 +1,7 @@
-use super::behaviour::FileResponse;
+use super::behaviour::{FileRequest, FileResponse};
 use super::computer_spec::ComputerSpec;
 use super::job::JobEvent;
+use blender::models::device::Device;
 use futures::channel::oneshot;
 use libp2p::{Multiaddr, PeerId};
 use libp2p_request_response::ResponseChannel;
@@ -23,19 +24,30 @@ pub enum NetworkError {
     NoPeerProviderFound,
     #[error("Unable to save download file: {0}")]
     UnableToSave(String),
+    #[error("Downloaded file does not match the hash {0}")]
+    HashMismatch(String),
     #[error("Timeout, unable to connect peer")]
     Timeout,
+    #[error("Unable to deliver message to peer: {0}")]
+    DeliveryFailed(String),
+    #[error("Peer rejected the message: {0}")]
+    Rejected(String),
 }
 
 // Send commands to network.
 #[derive(Debug)]
 pub enum NetCommand {
-    IncomingWorker(PeerId),
+    // share our computer spec, along with the render devices we found.
+    IncomingWorker(PeerId, Vec<Device>),
     Status(String),
     SubscribeTopic(String),
     UnsubscribeTopic(String),
-    JobStatus(PeerId, JobEvent),
-    // use this event to send message to a specific node
+    // use this event to send message to a specific node. The sender is notified once the peer acknowledge the message.
+    JobStatus {
+        peer_id: PeerId,
+        event: JobEvent,
+        sender: oneshot::Sender<Result<(), NetworkError>>,
+    },
     StartProviding {
         file_name: String,
         sender: oneshot::Sender<()>,
@@ -46,11 +58,11 @@ pub enum NetCommand {
     },
     RequestFile {
         peer_id: PeerId,
-        file_name: String,
-        sender: oneshot::Sender<Result<Vec<u8>, Box<dyn Error + Send>>>,
+        request: FileRequest,
+        sender: oneshot::Sender<Result<FileResponse, Box<dyn Error + Send>>>,
     },
     RespondFile {
-        file: Vec<u8>,
+        response: FileResponse,
         channel: ResponseChannel<FileResponse>,
     },
     Dial {
@@ -71,7 +83,7 @@ pub enum NetEvent {
     // Heartbeat() // share hardware statistic monitor heartbeat. (CPU/GPU/RAM activity readings)
     NodeDisconnected(PeerId), // On Node disconnected
     InboundRequest {
-        request: String,
+        request: FileRequest,
         channel: ResponseChannel<FileResponse>,
     },
     JobUpdate(PeerId, JobEvent),
@@ -1,14 +1,20 @@
 pub mod app_state;
 pub mod behaviour;
+pub(crate) mod chunking;
 pub(crate) mod common;
 pub(crate) mod computer_spec;
 pub mod error;
+pub(crate) mod ffmpeg;
 pub(crate) mod job;
 pub mod message;
 pub mod network;
+pub(crate) mod package;
 pub(crate) mod project_file;
 pub(crate) mod render_info;
+pub(crate) mod retry_policy;
 pub(crate) mod task;
+pub(crate) mod tile;
+pub(crate) mod transfer;
 // pub mod render_queue;
 pub(crate) mod server_setting;
 pub mod worker;
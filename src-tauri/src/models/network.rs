@@ -1,11 +1,13 @@
-use super::behaviour::{BlendFarmBehaviour, FileRequest, FileResponse};
+use super::behaviour::{BlendFarmBehaviour, FileRequest, FileResponse, JobRequest, JobResponse};
 use super::computer_spec::ComputerSpec;
 use super::job::JobEvent;
 use super::message::{NetCommand, NetEvent, NetworkError};
 use super::server_setting::ServerSetting;
+use super::transfer::{self, Download, CHUNK_SIZE};
 use crate::models::behaviour::BlendFarmBehaviourEvent;
+use blender::models::device::Device;
 use core::str;
-use futures::{channel::oneshot, prelude::*, StreamExt};
+use futures::{channel::oneshot, stream::FuturesUnordered, StreamExt};
 use libp2p::kad::RecordKey;
 use libp2p::multiaddr::Protocol;
 use libp2p::{
@@ -18,11 +20,11 @@ use libp2p_request_response::{OutboundRequestId, ProtocolSupport, ResponseChanne
 use machine_info::Machine;
 use std::collections::{hash_map, HashMap, HashSet};
 use std::error::Error;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 use std::u64;
 use tokio::sync::mpsc::{self, Receiver, Sender};
-use tokio::{io, select};
+use tokio::{io, io::AsyncWriteExt, select};
 
 /*
 Network Service - Provides simple network interface for peer-to-peer network for BlendFarm.
@@ -34,6 +36,9 @@ pub const SPEC: &str = "blendfarm/spec";
 pub const JOB: &str = "blendfarm/job";
 pub const HEARTBEAT: &str = "blendfarm/heartbeat";
 const TRANSFER: &str = "/file-transfer/1";
+const JOB_MESSAGE: &str = "/job-message/1";
+// how long we wait for the network to tell us who has a file, whoever answered by then is all we get.
+const PROVIDER_TIMEOUT: Duration = Duration::from_secs(30);
 
 // the tuples return three objects
 // the NetworkService holds the network loop operation
@@ -87,9 +92,15 @@ pub async fn new() -> Result<(NetworkService, NetworkController, Receiver<NetEve
             let protocol = [(StreamProtocol::new(TRANSFER), ProtocolSupport::Full)];
             let request_response = libp2p_request_response::Behaviour::new(protocol, rr_config);
 
+            // job events are sent directly to the target peer instead of gossiping to everyone.
+            let job_config = libp2p_request_response::Config::default();
+            let protocol = [(StreamProtocol::new(JOB_MESSAGE), ProtocolSupport::Full)];
+            let job_message = libp2p_request_response::Behaviour::new(protocol, job_config);
+
             Ok(BlendFarmBehaviour {
                 ping,
                 request_response,
+                job_message,
                 gossipsub,
                 mdns,
                 kad,
@@ -125,6 +136,19 @@ pub async fn new() -> Result<(NetworkService, NetworkController, Receiver<NetEve
     // the event sender is used to handle incoming network message. E.g. RunJob
     let (event_sender, event_receiver) = mpsc::channel::<NetEvent>(32);
 
+    // job messages are acknowledged as soon as they get here, then handed to the application in the order they came in.
+    // The network loop can't wait on a full event channel, the application may be waiting on us to deliver its own job message.
+    // Rejecting instead would lose frames the worker already rendered, so they wait in line here.
+    let (job_sender, mut job_receiver) = mpsc::unbounded_channel::<NetEvent>();
+    let forward = event_sender.clone();
+    tokio::spawn(async move {
+        while let Some(event) = job_receiver.recv().await {
+            if forward.send(event).await.is_err() {
+                break;
+            }
+        }
+    });
+
     let local_peer_id = swarm.local_peer_id().clone();
 
     Ok((
@@ -132,12 +156,14 @@ pub async fn new() -> Result<(NetworkService, NetworkController, Receiver<NetEve
             swarm,
             command_receiver,
             event_sender,
+            job_sender,
             public_addr: None,
             machine: Machine::new(),
             pending_dial: Default::default(),
             pending_get_providers: Default::default(),
             pending_start_providing: Default::default(),
             pending_request_file: Default::default(),
+            pending_job_message: Default::default(),
             // pending_task: Default::default(),
         },
         NetworkController {
@@ -185,18 +211,30 @@ impl NetworkController {
             .expect("Command should not been dropped");
     }
 
-    // How do I get the peers info I want to communicate with?
-    pub async fn send_job_message(&mut self, target: PeerId, event: JobEvent) {
+    /// Send job event to the target peer. Returns once the peer acknowledged the message, or if we couldn't deliver it.
+    pub async fn send_job_message(
+        &mut self,
+        target: PeerId,
+        event: JobEvent,
+    ) -> Result<(), NetworkError> {
+        let (sender, receiver) = oneshot::channel();
         self.sender
-            .send(NetCommand::JobStatus(target, event))
+            .send(NetCommand::JobStatus {
+                peer_id: target,
+                event,
+                sender,
+            })
             .await
             .expect("Command should not be dropped");
+        receiver
+            .await
+            .map_err(|e| NetworkError::SendError(e.to_string()))?
     }
 
     // Share computer info to
-    pub async fn share_computer_info(&mut self, peer_id: PeerId) {
+    pub async fn share_computer_info(&mut self, peer_id: PeerId, devices: Vec<Device>) {
         self.sender
-            .send(NetCommand::IncomingWorker(peer_id))
+            .send(NetCommand::IncomingWorker(peer_id, devices))
             .await
             .expect("Command should not have been dropped");
     }
@@ -213,6 +251,7 @@ impl NetworkController {
         receiver.await.expect("Sender should not be dropped");
     }
 
+    /// Everyone providing the file. Empty if nobody answered in time.
     pub async fn get_providers(&mut self, file_name: &str) -> HashSet<PeerId> {
         let (sender, receiver) = oneshot::channel();
         self.sender
@@ -222,39 +261,138 @@ impl NetworkController {
             })
             .await
             .expect("Command receiver should not be dropped");
-        receiver.await.expect("Sender should not be dropped")
+        match tokio::time::timeout(PROVIDER_TIMEOUT, receiver).await {
+            Ok(Ok(providers)) => providers,
+            Ok(Err(e)) => {
+                eprintln!("Provider query for {file_name} was dropped! {e:?}");
+                HashSet::new()
+            }
+            Err(_) => {
+                eprintln!("Timed out looking for providers of {file_name}");
+                HashSet::new()
+            }
+        }
     }
 
+    /// Download the file from everyone providing it, a chunk at a time and straight to disk. See transfer.rs
+    /// If an earlier download of the file got cut off, we carry on from where it stopped.
     pub async fn get_file_from_peers(
         &mut self,
         file_name: &str,
         destination: &PathBuf,
     ) -> Result<PathBuf, NetworkError> {
-        let providers = self.get_providers(&file_name).await;
+        // file names are shared as "/job_id/frame.png", keep the folders but inside the destination instead of the root directory.
+        let file_path = destination.join(file_name.trim_start_matches('/'));
+        self.download_file(file_name, file_path).await
+    }
+
+    /// Download a file shared under its content hash to file_path, and make sure it's the file we asked for.
+    /// A file that doesn't match is deleted, so we don't pick it up next time.
+    pub async fn get_verified_file(
+        &mut self,
+        hash: &str,
+        file_path: &Path,
+    ) -> Result<PathBuf, NetworkError> {
+        let file_path = self.download_file(hash, file_path.to_path_buf()).await?;
+        let path = file_path.clone();
+        let expected = hash.to_owned();
+        let verified = tokio::task::spawn_blocking(move || transfer::verify_file(&path, &expected))
+            .await
+            .unwrap_or(false);
+        if !verified {
+            let _ = tokio::fs::remove_file(&file_path).await;
+            return Err(NetworkError::HashMismatch(hash.to_owned()));
+        }
+        Ok(file_path)
+    }
+
+    async fn download_file(
+        &mut self,
+        file_name: &str,
+        file_path: PathBuf,
+    ) -> Result<PathBuf, NetworkError> {
+        let mut providers = self
+            .get_providers(file_name)
+            .await
+            .into_iter()
+            .collect::<Vec<_>>();
         if providers.is_empty() {
             return Err(NetworkError::NoPeerProviderFound);
         }
 
-        let requests = providers.into_iter().map(|p| {
-            let mut client = self.clone();
-            // should I just request a file from one peer instead?
-            async move { client.request_file(p, file_name).await }.boxed()
-        });
+        let save_err = |e: io::Error| NetworkError::UnableToSave(e.to_string());
+
+        if let Some(parent) = file_path.parent() {
+            tokio::fs::create_dir_all(parent).await.map_err(save_err)?;
+        }
 
-        let content = match futures::future::select_ok(requests).await {
-            Ok(data) => data.0,
-            Err(e) => {
-                // Received a "Timeout" error? What does that mean? Should I try to reconnect?
-                eprintln!("No peer found? {e:?}");
-                return Err(NetworkError::Timeout);
+        // download into a .part file, so we never mistake a half finished download for the real file.
+        let mut part_path = file_path.clone().into_os_string();
+        part_path.push(".part");
+        let part_path = PathBuf::from(part_path);
+        let mut part = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&part_path)
+            .await
+            .map_err(save_err)?;
+        let written = part.metadata().await.map_err(save_err)?.len();
+        let mut download = Download::new(written);
+
+        let mut requests = FuturesUnordered::new();
+        let mut turn = 0;
+        loop {
+            // keep the window full, taking turns between the providers.
+            while let Some(offset) = download.next_request() {
+                let peer_id = providers[turn % providers.len()];
+                turn += 1;
+                let request = FileRequest {
+                    file_name: file_name.to_owned(),
+                    offset,
+                    length: CHUNK_SIZE,
+                };
+                let sender = self.sender.clone();
+                requests.push(async move {
+                    let response = Self::request_file(sender, peer_id, request).await;
+                    (peer_id, offset, response)
+                });
             }
-        };
 
-        let file_path = destination.join(file_name);
-        match async_std::fs::write(file_path.clone(), content).await {
-            Ok(_) => Ok(file_path),
-            Err(e) => Err(NetworkError::UnableToSave(e.to_string())),
+            let Some((peer_id, offset, response)) = requests.next().await else {
+                break;
+            };
+            match response {
+                Ok(FileResponse::Chunk { offset, size, data }) => {
+                    // what we had on disk is bigger than the file, it can't be the same file. Start over.
+                    if download.written() > size {
+                        part.set_len(0).await.map_err(save_err)?;
+                        download = Download::new(0);
+                        continue;
+                    }
+                    for chunk in download.received(offset, data, size) {
+                        part.write_all(&chunk).await.map_err(save_err)?;
+                    }
+                }
+                response => {
+                    eprintln!("{peer_id} couldn't send {file_name} at {offset}, asking someone else. {response:?}");
+                    download.failed(offset);
+                    providers.retain(|p| *p != peer_id);
+                    if providers.is_empty() {
+                        return Err(NetworkError::Timeout);
+                    }
+                }
+            }
+        }
+
+        if !download.is_done() {
+            return Err(NetworkError::Timeout);
         }
+        part.flush().await.map_err(save_err)?;
+        drop(part);
+        tokio::fs::rename(&part_path, &file_path)
+            .await
+            .map_err(save_err)?;
+        Ok(file_path)
     }
 
     pub async fn dial(
@@ -276,16 +414,17 @@ impl NetworkController {
             .expect("Command receiver should not be dropped")
     }
 
+    // takes the command sender instead of self, so we don't clone the whole controller for every chunk.
     async fn request_file(
-        &mut self,
+        command: mpsc::Sender<NetCommand>,
         peer_id: PeerId,
-        file_name: &str,
-    ) -> Result<Vec<u8>, Box<dyn Error + Send>> {
+        request: FileRequest,
+    ) -> Result<FileResponse, Box<dyn Error + Send>> {
         let (sender, receiver) = oneshot::channel();
-        self.sender
+        command
             .send(NetCommand::RequestFile {
                 peer_id,
-                file_name: file_name.into(),
+                request,
                 sender,
             })
             .await
@@ -293,13 +432,33 @@ impl NetworkController {
         receiver.await.expect("Sender should not be dropped")
     }
 
+    /// Answer a peer asking for part of a file we provide. Only the requested chunk is read from disk.
     pub(crate) async fn respond_file(
         &mut self,
-        file: Vec<u8>,
+        request: FileRequest,
         channel: ResponseChannel<FileResponse>,
     ) {
+        let response = match self.providing_files.get(&request.file_name).cloned() {
+            Some(path) => {
+                let FileRequest { offset, length, .. } = request;
+                let read = move || transfer::read_chunk(&path, offset, length);
+                match tokio::task::spawn_blocking(read).await {
+                    Ok(Ok((data, size))) => FileResponse::Chunk { offset, size, data },
+                    Ok(Err(e)) => {
+                        eprintln!("Fail to read {} for peer! {e:?}", request.file_name);
+                        FileResponse::NotFound
+                    }
+                    Err(e) => {
+                        eprintln!("Fail to read {} for peer! {e:?}", request.file_name);
+                        FileResponse::NotFound
+                    }
+                }
+            }
+            None => FileResponse::NotFound,
+        };
+
         self.sender
-            .send(NetCommand::RespondFile { file, channel })
+            .send(NetCommand::RespondFile { response, channel })
             .await
             .expect("Command should not be dropped");
     }
@@ -319,14 +478,20 @@ pub struct NetworkService {
     // Send Network event to subscribers.
     event_sender: Sender<NetEvent>,
 
+    // Job messages for the application, never full. See new()
+    job_sender: mpsc::UnboundedSender<NetEvent>,
+
     public_addr: Option<Multiaddr>,
 
     // empheral key used to stored and communicate with.
-    pending_get_providers: HashMap<kad::QueryId, oneshot::Sender<HashSet<PeerId>>>,
+    // providers are collected as the query goes, and sent back once it's finished.
+    pending_get_providers:
+        HashMap<kad::QueryId, (HashSet<PeerId>, oneshot::Sender<HashSet<PeerId>>)>,
     pending_start_providing: HashMap<kad::QueryId, oneshot::Sender<()>>,
     pending_request_file:
-        HashMap<OutboundRequestId, oneshot::Sender<Result<Vec<u8>, Box<dyn Error + Send>>>>,
+        HashMap<OutboundRequestId, oneshot::Sender<Result<FileResponse, Box<dyn Error + Send>>>>,
     pending_dial: HashMap<PeerId, oneshot::Sender<Result<(), Box<dyn Error + Send>>>>,
+    pending_job_message: HashMap<OutboundRequestId, oneshot::Sender<Result<(), NetworkError>>>,
     // pending_task: HashMap<PeerId, oneshot::Sender<Result<Task, Box<dyn Error + Send>>>>,
 }
 
@@ -343,26 +508,30 @@ impl NetworkService {
             }
             NetCommand::RequestFile {
                 peer_id,
-                file_name,
+                request,
                 sender,
             } => {
                 let request_id = self
                     .swarm
                     .behaviour_mut()
                     .request_response
-                    .send_request(&peer_id, FileRequest(file_name.into()));
+                    .send_request(&peer_id, request);
                 self.pending_request_file.insert(request_id, sender);
             }
-            NetCommand::RespondFile { file, channel } => {
-                self.swarm
+            NetCommand::RespondFile { response, channel } => {
+                // the peer may have given up on the chunk already.
+                if let Err(e) = self
+                    .swarm
                     .behaviour_mut()
                     .request_response
-                    .send_response(channel, FileResponse(file))
-                    .expect("Connection to peer may still be open?");
+                    .send_response(channel, response)
+                {
+                    eprintln!("Fail to send file chunk to peer! {e:?}");
+                }
             }
-            NetCommand::IncomingWorker(peer_id) => {
-                let spec = ComputerSpec::new(&mut self.machine);
-                let data = bincode::serialize(&spec).unwrap();
+            NetCommand::IncomingWorker(peer_id, devices) => {
+                let spec = ComputerSpec::new(&mut self.machine, devices);
+                let data = spec.to_bytes().unwrap();
                 let topic = IdentTopic::new(SPEC);
                 let _ = self.swarm.dial(peer_id);
                 if let Err(e) = self.swarm.behaviour_mut().gossipsub.publish(topic, data) {
@@ -372,7 +541,8 @@ impl NetworkService {
             NetCommand::GetProviders { file_name, sender } => {
                 let key = RecordKey::new(&file_name.as_bytes());
                 let query_id = self.swarm.behaviour_mut().kad.get_providers(key.into());
-                self.pending_get_providers.insert(query_id, sender);
+                self.pending_get_providers
+                    .insert(query_id, (HashSet::new(), sender));
             }
             NetCommand::StartProviding { file_name, sender } => {
                 let provider_key = RecordKey::new(&file_name.as_bytes());
@@ -400,29 +570,17 @@ impl NetworkService {
                     .gossipsub
                     .unsubscribe(&ident_topic);
             }
-            // what was I'm suppose to do here?
-            NetCommand::JobStatus(_peer_id, _event) => {
-                /*
-                // convert data into json format.
-                // let data = bincode::serialize(&status).unwrap();
-
-                // TODO: Read more about libp2p and how I can just connect to one machine and send that machine job status information.
-                // let _ = self.swarm.dial(target);
-                // once we have a tcp/udp/quik connection, we should only send one task over and end the pipe.
-
-                // TODO: Find a way to send JobEvent to specific target machine?
-                // let topic = IdentTopic::new(JOB);
-                // if let Err(e) = self.swarm.behaviour_mut().gossipsub.publish(topic, data) {
-                //     eprintln!("Fail to send job! {e:?}");
-                // }
-                */
-
-                /*
-                   Let's break this down, we receive a worker with peer_id and peer_addr, both of which will be used to establish communication
-                   Once we establish a communication, that target peer will need to receive the pending task we have assigned for them.
-                   For now, we will try to dial the target peer, and append the task to our network service pool of pending task.
-                */
-                // self.pending_task.insert(peer_id, );
+            NetCommand::JobStatus {
+                peer_id,
+                event,
+                sender,
+            } => {
+                let request_id = self
+                    .swarm
+                    .behaviour_mut()
+                    .job_message
+                    .send_request(&peer_id, JobRequest(event));
+                self.pending_job_message.insert(request_id, sender);
             }
             NetCommand::Dial {
                 peer_id,
@@ -461,6 +619,9 @@ impl NetworkService {
             SwarmEvent::Behaviour(BlendFarmBehaviourEvent::RequestResponse(rr)) => {
                 self.handle_response(rr).await
             }
+            SwarmEvent::Behaviour(BlendFarmBehaviourEvent::JobMessage(job)) => {
+                self.handle_job_message(job).await
+            }
             // Once the swarm establish connection, we then send the peer_id we connected to.
             SwarmEvent::ConnectionEstablished { peer_id, .. } => {
                 self.event_sender
@@ -478,7 +639,7 @@ impl NetworkService {
                 // hmm.. I need to capture the address here?
                 // how do I save the address?
                 if address.protocol_stack().any(|f| f.contains("tcp")) {
-                    dbg!(&address);
+                    println!("Listening on {address}");
                     self.public_addr = Some(address);
                 }
             }
@@ -498,10 +659,7 @@ impl NetworkService {
                     request, channel, ..
                 } => {
                     self.event_sender
-                        .send(NetEvent::InboundRequest {
-                            request: request.0,
-                            channel,
-                        })
+                        .send(NetEvent::InboundRequest { request, channel })
                         .await
                         .expect("Event receiver should not be dropped!");
                 }
@@ -509,12 +667,11 @@ impl NetworkService {
                     request_id,
                     response,
                 } => {
-                    if let Err(e) = self
-                        .pending_request_file
-                        .remove(&request_id)
-                        .expect("Request is still pending?")
-                        .send(Ok(response.0))
-                    {
+                    let Some(sender) = self.pending_request_file.remove(&request_id) else {
+                        eprintln!("Received a file chunk nobody asked for! {request_id:?}");
+                        return;
+                    };
+                    if let Err(e) = sender.send(Ok(response)) {
                         eprintln!("libp2p Response Error: {e:?}");
                     }
                 }
@@ -522,12 +679,11 @@ impl NetworkService {
             libp2p_request_response::Event::OutboundFailure {
                 request_id, error, ..
             } => {
-                if let Err(e) = self
-                    .pending_request_file
-                    .remove(&request_id)
-                    .expect("Request is still pending")
-                    .send(Err(Box::new(error)))
-                {
+                let Some(sender) = self.pending_request_file.remove(&request_id) else {
+                    eprintln!("File request {request_id:?} failed, but nobody is waiting on it! {error:?}");
+                    return;
+                };
+                if let Err(e) = sender.send(Err(Box::new(error))) {
                     eprintln!("libp2p outbound fail: {e:?}");
                 }
             }
@@ -536,6 +692,60 @@ impl NetworkService {
         }
     }
 
+    // Inbound job event is passed on to the application, and we let the sender know it got here.
+    async fn handle_job_message(
+        &mut self,
+        event: libp2p_request_response::Event<JobRequest, JobResponse>,
+    ) {
+        match event {
+            libp2p_request_response::Event::Message { peer, message, .. } => match message {
+                libp2p_request_response::Message::Request {
+                    request, channel, ..
+                } => {
+                    // don't wait on the application here, it may be waiting on us to deliver its own job message.
+                    // Only fails once the application is gone.
+                    let response = match self.job_sender.send(NetEvent::JobUpdate(peer, request.0))
+                    {
+                        Ok(_) => JobResponse::Ack,
+                        Err(e) => JobResponse::Rejected(e.to_string()),
+                    };
+
+                    if let Err(e) = self
+                        .swarm
+                        .behaviour_mut()
+                        .job_message
+                        .send_response(channel, response)
+                    {
+                        eprintln!("Fail to acknowledge job message from {peer}! {e:?}");
+                    }
+                }
+                libp2p_request_response::Message::Response {
+                    request_id,
+                    response,
+                } => {
+                    if let Some(sender) = self.pending_job_message.remove(&request_id) {
+                        let result = match response {
+                            JobResponse::Ack => Ok(()),
+                            JobResponse::Rejected(reason) => Err(NetworkError::Rejected(reason)),
+                        };
+                        let _ = sender.send(result);
+                    }
+                }
+            },
+            libp2p_request_response::Event::OutboundFailure {
+                request_id, error, ..
+            } => {
+                if let Some(sender) = self.pending_job_message.remove(&request_id) {
+                    let _ = sender.send(Err(NetworkError::DeliveryFailed(error.to_string())));
+                }
+            }
+            libp2p_request_response::Event::InboundFailure { peer, error, .. } => {
+                eprintln!("Fail to receive job message from {peer}! {error:?}");
+            }
+            libp2p_request_response::Event::ResponseSent { .. } => {}
+        }
+    }
+
     async fn handle_mdns(&mut self, event: mdns::Event) {
         match event {
             mdns::Event::Discovered(peers) => {
@@ -569,8 +779,14 @@ impl NetworkService {
             gossipsub::Event::Message { message, .. } => match message.topic.as_str() {
                 SPEC => {
                     let source = message.source.expect("Source cannot be empty!");
-                    let specs =
-                        bincode::deserialize(&message.data).expect("Fail to parse Computer Specs!");
+                    // a peer running another version of BlendFarm, ignore it rather than bring the network down.
+                    let specs = match ComputerSpec::from_bytes(&message.data) {
+                        Ok(specs) => specs,
+                        Err(e) => {
+                            eprintln!("Ignoring computer spec from {source:?}: {e}");
+                            return;
+                        }
+                    };
                     if let Err(e) = self
                         .event_sender
                         .send(NetEvent::NodeDiscovered(source, specs))
@@ -623,32 +839,31 @@ impl NetworkService {
                     .expect("Completed query to be previously pending.");
                 let _ = sender.send(());
             }
+            // each step may only hold a few of the providers, keep collecting until the query is done.
             kad::Event::OutboundQueryProgressed {
                 id,
-                result:
-                    kad::QueryResult::GetProviders(Ok(kad::GetProvidersOk::FoundProviders {
-                        providers,
-                        ..
-                    })),
+                result: kad::QueryResult::GetProviders(result),
+                step,
                 ..
             } => {
-                if let Some(sender) = self.pending_get_providers.remove(&id) {
-                    sender.send(providers).expect("Receiver not to be dropped");
-                    self.swarm
-                        .behaviour_mut()
-                        .kad
-                        .query_mut(&id)
-                        .unwrap()
-                        .finish();
+                match result {
+                    Ok(kad::GetProvidersOk::FoundProviders { providers, .. }) => {
+                        if let Some((found, _)) = self.pending_get_providers.get_mut(&id) {
+                            found.extend(providers);
+                        }
+                    }
+                    Ok(kad::GetProvidersOk::FinishedWithNoAdditionalRecord { .. }) => {}
+                    // kademlia gave up, whoever we found so far will have to do.
+                    Err(e) => eprintln!("Provider query did not finish! {e:?}"),
+                }
+
+                if step.last {
+                    if let Some((found, sender)) = self.pending_get_providers.remove(&id) {
+                        // the download may have timed out waiting already.
+                        let _ = sender.send(found);
+                    }
                 }
             }
-            kad::Event::OutboundQueryProgressed {
-                result:
-                    kad::QueryResult::GetProviders(Ok(
-                        kad::GetProvidersOk::FinishedWithNoAdditionalRecord { .. },
-                    )),
-                ..
-            } => {}
             _ => {}
         }
     }
@@ -0,0 +1,140 @@
+/*
+    Developer blog:
+    - The blend file alone isn't enough, textures, linked libraries and caches need to go along. See Blender::dependencies()
+    - Files keep their place relative to the blend file, so the // paths blender saved still work on the worker.
+        The root is the folder that holds the blend file and everything it points to, and the worker rebuilds it inside blend_dir/job_id.
+    - Every file is shared under its own hash, same as the blend file. A texture used by many jobs is only downloaded once per job, and only when it changed.
+    - Absolute paths outside of the project folder are left behind. They would pull in the whole disk as the root, and blender wouldn't look for them there anyway.
+*/
+use super::transfer;
+use blender::models::dependency::Dependency;
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path, PathBuf};
+
+/// A file the blend file needs, shared on the network under its hash.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PackageFile {
+    /// Where the file goes, relative to the root of the package.
+    pub path: PathBuf,
+    /// BLAKE3 hash of the file
+    pub hash: String,
+}
+
+/// Everything the worker needs to render the project, laid out the way it is on the host.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Package {
+    /// The blend file, relative to the root of the package.
+    pub project: PathBuf,
+    /// Every other file, without the blend file.
+    pub files: Vec<PackageFile>,
+}
+
+impl Package {
+    /// Put the project and its dependencies into a package, and hash every file.
+    /// Returns the package and its root on this machine. Each file is at root.join(file.path)
+    pub fn pack(
+        project_file: &Path,
+        dependencies: &[Dependency],
+    ) -> std::io::Result<(Self, PathBuf)> {
+        let project_dir = project_file.parent().unwrap_or(Path::new(""));
+        let included = dependencies
+            .iter()
+            .filter(|dependency| {
+                let inside = dependency.relative || dependency.path.starts_with(project_dir);
+                if !inside {
+                    eprintln!(
+                        "{:?} uses an absolute path, workers won't be able to find it. Make the paths relative in blender.",
+                        dependency.path
+                    );
+                }
+                inside
+            })
+            .collect::<Vec<_>>();
+
+        let root = included
+            .iter()
+            .filter_map(|dependency| dependency.path.parent())
+            .fold(project_dir.to_path_buf(), |root, dir| {
+                common_ancestor(&root, dir)
+            });
+
+        let mut files = Vec::new();
+        for dependency in included {
+            // relative paths can't cross drives on windows, so they always share the root.
+            let Ok(path) = dependency.path.strip_prefix(&root) else {
+                continue;
+            };
+            files.push(PackageFile {
+                path: path.to_path_buf(),
+                hash: transfer::hash_file(&dependency.path)?,
+            });
+        }
+
+        let project = project_file
+            .strip_prefix(&root)
+            .unwrap_or(project_file)
+            .to_path_buf();
+        Ok((Self { project, files }, root))
+    }
+}
+
+/// The path stays inside the folder it's joined onto. The host decides where files go, don't let it write anywhere else on the worker.
+pub fn is_safe(path: &Path) -> bool {
+    path.components().all(|c| matches!(c, Component::Normal(_)))
+}
+
+fn common_ancestor(a: &Path, b: &Path) -> PathBuf {
+    a.components()
+        .zip(b.components())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blender::models::dependency::DependencyKind;
+
+    #[test]
+    fn should_keep_files_where_they_are_next_to_the_project() {
+        let root = std::env::temp_dir().join(format!("package_{}", uuid::Uuid::new_v4()));
+        let project = root.join("scenes").join("shot.blend");
+        let texture = root.join("textures").join("wood.png");
+        std::fs::create_dir_all(texture.parent().unwrap()).unwrap();
+        std::fs::write(&texture, b"wood").unwrap();
+
+        let dependencies = vec![
+            Dependency {
+                kind: DependencyKind::Image,
+                path: texture.clone(),
+                relative: true,
+            },
+            // absolute and somewhere else, left behind.
+            Dependency {
+                kind: DependencyKind::Image,
+                path: PathBuf::from("/library/rock.png"),
+                relative: false,
+            },
+        ];
+        let (package, package_root) = Package::pack(&project, &dependencies).unwrap();
+        assert_eq!(package_root, root);
+        assert_eq!(package.project, Path::new("scenes").join("shot.blend"));
+        assert_eq!(
+            package.files,
+            vec![PackageFile {
+                path: Path::new("textures").join("wood.png"),
+                hash: transfer::hash_file(&texture).unwrap(),
+            }]
+        );
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn should_refuse_paths_outside_of_the_package() {
+        assert!(is_safe(Path::new("textures/wood.png")));
+        assert!(!is_safe(Path::new("../wood.png")));
+        assert!(!is_safe(Path::new("/etc/wood.png")));
+    }
+}
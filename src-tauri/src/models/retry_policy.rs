@@ -0,0 +1,73 @@
+use super::job::Frame;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// How the host should react when a worker fails to render a frame.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RetryPolicy {
+    /// Number of times a frame can be attempted before it's marked as permanently failed.
+    pub max_attempts: u32,
+    /// Prefer handing the frame to a worker that hasn't failed it before.
+    pub retry_on_different_worker: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            retry_on_different_worker: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(max_attempts: u32, retry_on_different_worker: bool) -> Self {
+        Self {
+            // we always get at least one attempt.
+            max_attempts: max_attempts.max(1),
+            retry_on_different_worker,
+        }
+    }
+
+    /// Check whether the frame should be handed out again.
+    pub fn should_retry(&self, failure: &FrameFailure) -> bool {
+        !failure.permanent && failure.attempts < self.max_attempts
+    }
+}
+
+/// Record of a frame that failed to render. Kept in the database so we can show it on the job detail page.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FrameFailure {
+    pub job_id: Uuid,
+    pub frame: Frame,
+    /// Number of attempts that failed so far.
+    pub attempts: u32,
+    /// Last error reported by the worker
+    pub error: String,
+    /// Workers (base58 peer id) that failed to render this frame.
+    pub peers: Vec<String>,
+    /// We gave up on this frame.
+    pub permanent: bool,
+}
+
+impl FrameFailure {
+    pub fn new(job_id: Uuid, frame: Frame) -> Self {
+        Self {
+            job_id,
+            frame,
+            attempts: 0,
+            error: String::new(),
+            peers: Vec::new(),
+            permanent: false,
+        }
+    }
+
+    /// Count another failed attempt from the worker.
+    pub fn record(&mut self, peer_id: String, error: String) {
+        self.attempts += 1;
+        self.error = error;
+        if !self.peers.contains(&peer_id) {
+            self.peers.push(peer_id);
+        }
+    }
+}
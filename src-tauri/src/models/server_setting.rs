@@ -1,3 +1,4 @@
+use super::chunking::ChunkStrategy;
 use serde::{Deserialize, Serialize};
 use std::{fs, path::PathBuf};
 
@@ -26,6 +27,13 @@ pub struct ServerSetting {
     pub render_dir: PathBuf,
     /// Public directory of blender working copy of files.
     pub blend_dir: PathBuf,
+    /// How the frames of a job are split into tasks for the workers.
+    // settings saved before this existed don't have it.
+    #[serde(default)]
+    pub chunking: ChunkStrategy,
+    /// Path to ffmpeg, used to put the frames together into a video. Looks on PATH when it's not set.
+    #[serde(default)]
+    pub ffmpeg: Option<PathBuf>,
 }
 
 impl Default for ServerSetting {
@@ -47,6 +55,8 @@ impl Default for ServerSetting {
         Self {
             render_dir: render_data,
             blend_dir: blend_file,
+            chunking: Default::default(),
+            ffmpeg: None,
         }
     }
 }
@@ -1,8 +1,11 @@
-use super::job::Job;
+use super::{job::Job, package::PackageFile};
 use crate::domains::task_store::TaskError;
 use blender::{
     blender::{Args, Blender},
-    models::status::Status,
+    models::{
+        blender_render_setting::Window, render_handle::RenderHandle,
+        render_override::RenderOverride,
+    },
 };
 use libp2p::PeerId;
 use semver::Version;
@@ -33,11 +36,26 @@ pub struct Task {
     /// target blender version to use
     pub blender_version: Version,
 
-    /// generic blender file name from job's reference.
+    /// blender file name from job's reference, relative to the job's folder on the worker.
     pub blend_file_name: PathBuf,
 
-    /// Render range frame to perform the task
+    /// BLAKE3 hash of the blend file. Used to fetch the file from the network, and to check the copy we have is the right one.
+    pub blend_file_hash: String,
+
+    /// Other files the blend file needs. Paths are relative to the folder the blend file name is relative to.
+    pub dependencies: Vec<PackageFile>,
+
+    /// Render range frame to perform the task. End exclusive.
     pub range: Range<i32>,
+
+    /// Render every step-th frame of the range.
+    pub step: i32,
+
+    /// Only render this part of the frame. None renders the whole frame.
+    pub region: Option<Window>,
+
+    /// Settings the job picked over the ones in the blend file.
+    pub overrides: RenderOverride,
 }
 
 // To better understand Task, this is something that will be save to the database and maintain a record copy for data recovery
@@ -49,47 +67,66 @@ impl Task {
         blend_file_name: PathBuf,
         blender_version: Version,
         range: Range<i32>,
+        step: i32,
     ) -> Self {
         Self {
             id: Uuid::new_v4(),
             peer_id: peer_id.to_bytes(),
             job_id,
             blend_file_name,
+            blend_file_hash: String::new(),
+            dependencies: Vec::new(),
             blender_version,
             range,
+            step: step.max(1),
+            region: None,
+            overrides: RenderOverride::default(),
         }
     }
 
-    pub fn from(peer_id: PeerId, job: Job, range: Range<i32>) -> Self {
+    pub fn from(peer_id: PeerId, job: Job, range: Range<i32>, step: i32) -> Self {
         Self {
             id: Uuid::new_v4(),
             peer_id: peer_id.to_bytes(),
             job_id: job.id,
-            blend_file_name: PathBuf::from(job.project_file.file_name().unwrap()),
+            // the project keeps its place among the files it needs, see package.rs
+            blend_file_name: match job.package.project.as_os_str().is_empty() {
+                true => PathBuf::from(job.project_file.file_name().unwrap()),
+                false => job.package.project,
+            },
+            blend_file_hash: job.project_hash,
+            dependencies: job.package.files,
             blender_version: job.blender_version,
             range,
+            step: step.max(1),
+            region: None,
+            overrides: job.overrides,
         }
     }
 
-    // this could be async? we'll see.
+    /// Number of frames left to render in this task.
+    pub fn frames_left(&self) -> usize {
+        self.range.len().div_ceil(self.step.max(1) as usize)
+    }
 
-    /// The behaviour of this function returns the percentage of the remaining jobs in poll.
-    /// E.g. 102 (80%) of 120 remaining would return 96 end frames.
-    /// TODO: Allow other node or host to fetch end frames from this task and distribute to other requesting workers.
+    /// The behaviour of this function returns the percentage of the remaining frames in poll.
+    /// E.g. 50% of 20..40 remaining would return 30..40, and this task continues with 20..30.
+    /// With a step, the split lands on a frame we would have rendered, so both halves keep the same step.
+    /// Used to let idle nodes take the tail end of a busy node's task. Returns None if there's too little left to bother.
     pub fn fetch_end_frames(&mut self, percentage: i8) -> Option<Range<i32>> {
-        // Here we'll determine how many franes left, and then pass out percentage of that frames back.
-        let perc = percentage as f32 / i8::MAX as f32;
+        // Here we'll determine how many frames left, and then pass out percentage of that frames back.
+        let percentage = percentage.clamp(0, 100) as i32;
         let end = self.range.end;
-        let delta = (end - self.range.start) as f32;
-        let trunc = (perc * (delta.powf(2.0)).sqrt()).floor() as usize;
+        let delta = self.frames_left() as i32;
+        let trunc = delta * percentage / 100;
 
         if trunc.le(&2) {
             return None;
         }
 
-        let start = end - trunc as i32;
+        let start = self.range.start + (delta - trunc) * self.step;
         let range = Range { start, end };
-        self.range.end = start - 1; // Update end value accordingly.
+        self.range.end = start; // Range is end exclusive, the stolen frames start where ours end.
         Some(range)
     }
 
@@ -101,7 +138,8 @@ impl Task {
         // we will use this to generate a temporary frame record on database for now.
         if self.range.start < self.range.end {
             let value = Some(self.range.start);
-            self.range.start = self.range.start + 1;
+            // don't go past the end, otherwise fetch_end_frames() would think there's frames left.
+            self.range.start = (self.range.start + self.step).min(self.range.end);
             value
         } else {
             None
@@ -109,29 +147,100 @@ impl Task {
     }
 
     // Invoke blender to run the job
-    // how do I stop this? Will this be another async container?
+    // use the returned handle to stop the render.
+    // The task is shared so that fetch_end_frames() can hand our remaining frames to another node while we render.
     pub async fn run(
-        self,
+        task: Arc<RwLock<Task>>,
         blend_file: PathBuf,
         // output is used to create local path storage to save frame path to
         output: PathBuf,
         // reference to the blender executable path to run this task.
         blender: &Blender,
-    ) -> Result<std::sync::mpsc::Receiver<Status>, TaskError> {
-        let args = Args::new(blend_file, output);
-        let arc_task = Arc::new(RwLock::new(self)).clone();
+    ) -> Result<RenderHandle, TaskError> {
+        let mut args = Args::new(blend_file, output);
+        if let Ok(task) = task.read() {
+            args.overrides = task.overrides.clone();
+        }
+        // crop the image down to the region, the host puts the pieces back together.
+        if let Some(region) = task.read().ok().and_then(|task| task.region.clone()) {
+            args.border = region;
+            args.crop = true;
+        }
 
-        // TODO: How can I adjust blender jobs?
         // this always puzzle me. Is this still awaited after application closed?
-        let receiver = blender
+        blender
             .render(args, move || -> Option<i32> {
-                let mut task = match arc_task.write() {
+                let mut task = match task.write() {
                     Ok(task) => task,
                     Err(_) => return None,
                 };
                 task.get_next_frame()
             })
-            .await;
-        Ok(receiver)
+            .await
+            .map_err(|e| TaskError::BlenderError(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(range: Range<i32>, step: i32) -> Task {
+        Task::new(
+            PeerId::random(),
+            Uuid::new_v4(),
+            PathBuf::from("test.blend"),
+            Version::new(4, 2, 0),
+            range,
+            step,
+        )
+    }
+
+    #[test]
+    fn should_split_remaining_frames() {
+        let mut task = task(20..40, 1);
+        assert_eq!(task.fetch_end_frames(50), Some(30..40));
+        assert_eq!(task.range, 20..30);
+    }
+
+    #[test]
+    fn should_split_on_a_frame_of_the_step() {
+        // 0, 2, 4 .. 18
+        let mut task = task(0..20, 2);
+        assert_eq!(task.fetch_end_frames(50), Some(10..20));
+        assert_eq!(task.range, 0..10);
+        assert_eq!(task.frames_left(), 5);
+    }
+
+    #[test]
+    fn should_keep_last_frame_when_end_is_not_a_multiple_of_step() {
+        // 0, 4, 8 .. 24, the end isn't rendered but 24 is.
+        let mut task = task(0..25, 4);
+        assert_eq!(task.frames_left(), 7);
+        let range = task.fetch_end_frames(50).unwrap();
+        assert_eq!(range, 16..25);
+        assert_eq!(range.step_by(4).collect::<Vec<i32>>(), vec![16, 20, 24]);
+        assert_eq!(task.range, 0..16);
+        assert_eq!(task.frames_left(), 4);
+    }
+
+    #[test]
+    fn should_clamp_percentage() {
+        // nothing to hand out below 0%.
+        let mut task = task(20..40, 1);
+        assert_eq!(task.fetch_end_frames(-50), None);
+        assert_eq!(task.range, 20..40);
+
+        // anything over 100% hands out everything we have left.
+        assert_eq!(task.fetch_end_frames(i8::MAX), Some(20..40));
+        assert_eq!(task.range, 20..20);
+        assert_eq!(task.frames_left(), 0);
+    }
+
+    #[test]
+    fn should_not_split_single_frame() {
+        let mut task = task(5..6, 1);
+        assert_eq!(task.fetch_end_frames(100), None);
+        assert_eq!(task.range, 5..6);
     }
 }
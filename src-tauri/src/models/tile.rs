@@ -0,0 +1,420 @@
+/*
+    Developer blog:
+    - A Mode::Section job splits one frame into a grid of border regions. Each worker renders a region, and the host puts the pieces back together.
+    - Tiles come in two flavours. Cropped tiles are only as big as the border, so we lay them out next to each other.
+        Blender rounds the border to whole pixels, so instead of trusting the float edges we measure the size from the images themselves.
+        Uncropped tiles are the size of the whole frame with nothing outside the border, so we cut the border back out of each one.
+    - Blender counts y from the bottom of the frame, images count y from the top. The row with the highest y goes first.
+    - Everything works on files, nothing here needs blender or the network. Render a few tiles, point stitch() at them.
+    - Pixels keep their depth. EXR stays in float, 16 bit PNG/TIFF stay 16 bit, and alpha comes along in all of them.
+*/
+use blender::models::blender_render_setting::Window;
+use image::{ColorType, DynamicImage, GenericImage, GenericImageView, ImageBuffer, Pixel};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum TileError {
+    #[error("No tiles to stitch!")]
+    Empty,
+    #[error("Unable to open tile {0:?}: {1}")]
+    Open(PathBuf, String),
+    #[error("Tiles leave part of the frame empty: {0}")]
+    Gap(String),
+    #[error("Tiles overlap: {0}")]
+    Overlap(String),
+    #[error("Tiles do not line up: {0}")]
+    Mismatch(String),
+    #[error("Unable to save stitched image: {0}")]
+    Save(String),
+}
+
+/// A rendered region of a frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tile {
+    pub region: Window,
+    pub path: PathBuf,
+    /// Image only contains the region. Otherwise it's the size of the whole frame.
+    pub cropped: bool,
+}
+
+// Where the pixels of a tile end up in the frame.
+struct Placement {
+    // which tile, and the part of it we copy over. (x, y, width, height)
+    index: usize,
+    source: (u32, u32, u32, u32),
+    // top left corner in the frame.
+    x: u32,
+    y: u32,
+}
+
+// Every edge of the regions, sorted and without duplicates.
+fn edges(ranges: impl Iterator<Item = (f32, f32)>) -> Vec<f32> {
+    let mut edges = ranges
+        .flat_map(|(start, end)| [start, end])
+        .collect::<Vec<_>>();
+    edges.sort_by(f32::total_cmp);
+    edges.dedup();
+    edges
+}
+
+/// Make sure the regions cover the whole frame, and that no part of the frame is rendered twice.
+pub fn check_coverage(regions: &[Window]) -> Result<(), TileError> {
+    if regions.is_empty() {
+        return Err(TileError::Empty);
+    }
+
+    let xs = edges(regions.iter().map(|r| (r.x.start, r.x.end)));
+    let ys = edges(regions.iter().map(|r| (r.y.start, r.y.end)));
+    if xs.first() != Some(&0.0) || xs.last() != Some(&1.0) {
+        return Err(TileError::Gap(format!("x only covers {xs:?}")));
+    }
+    if ys.first() != Some(&0.0) || ys.last() != Some(&1.0) {
+        return Err(TileError::Gap(format!("y only covers {ys:?}")));
+    }
+
+    // the edges cut the frame into cells, every cell needs exactly one region.
+    for x in xs.windows(2) {
+        for y in ys.windows(2) {
+            let (cx, cy) = ((x[0] + x[1]) / 2.0, (y[0] + y[1]) / 2.0);
+            let count = regions
+                .iter()
+                .filter(|r| r.x.contains(&cx) && r.y.contains(&cy))
+                .count();
+            let cell = format!("x {}..{}, y {}..{}", x[0], x[1], y[0], y[1]);
+            match count {
+                0 => return Err(TileError::Gap(cell)),
+                1 => {}
+                _ => return Err(TileError::Overlap(cell)),
+            }
+        }
+    }
+    Ok(())
+}
+
+// Cropped tiles need to line up in rows, so we can add up the sizes. Returns the frame size and where each tile goes.
+fn layout_cropped(
+    tiles: &[Tile],
+    sizes: &[(u32, u32)],
+) -> Result<((u32, u32), Vec<Placement>), TileError> {
+    // top row first.
+    let mut bands = edges(tiles.iter().map(|t| (t.region.y.start, t.region.y.end)));
+    bands.reverse();
+
+    let mut placements = Vec::with_capacity(tiles.len());
+    let mut width = None;
+    let mut y = 0;
+    for band in bands.windows(2) {
+        let mut row = (0..tiles.len())
+            .filter(|i| tiles[*i].region.y.start < band[0] && tiles[*i].region.y.end > band[1])
+            .collect::<Vec<_>>();
+        if let Some(i) = row
+            .iter()
+            .find(|i| tiles[**i].region.y != (band[1]..band[0]))
+        {
+            return Err(TileError::Mismatch(format!(
+                "{:?} spans more than one row",
+                tiles[*i].path
+            )));
+        }
+        row.sort_by(|a, b| {
+            tiles[*a]
+                .region
+                .x
+                .start
+                .total_cmp(&tiles[*b].region.x.start)
+        });
+
+        let height = sizes[row[0]].1;
+        let mut x = 0;
+        for index in row {
+            let (w, h) = sizes[index];
+            if h != height {
+                return Err(TileError::Mismatch(format!(
+                    "{:?} is {h} pixels high, the rest of the row is {height}",
+                    tiles[index].path
+                )));
+            }
+            placements.push(Placement {
+                index,
+                source: (0, 0, w, h),
+                x,
+                y,
+            });
+            x += w;
+        }
+
+        match width {
+            Some(width) if width != x => {
+                return Err(TileError::Mismatch(format!(
+                    "row is {x} pixels wide, expected {width}"
+                )))
+            }
+            _ => width = Some(x),
+        }
+        y += height;
+    }
+
+    Ok(((width.unwrap_or_default(), y), placements))
+}
+
+// Uncropped tiles are the whole frame, cut out the border the same way blender rounds it.
+fn layout_uncropped(
+    tiles: &[Tile],
+    sizes: &[(u32, u32)],
+) -> Result<((u32, u32), Vec<Placement>), TileError> {
+    let (width, height) = sizes[0];
+    if let Some(i) = sizes.iter().position(|size| *size != (width, height)) {
+        return Err(TileError::Mismatch(format!(
+            "{:?} is not the same size as the other tiles",
+            tiles[i].path
+        )));
+    }
+
+    let pixel = |edge: f32, size: u32| (edge * size as f32).round() as u32;
+    let placements = tiles
+        .iter()
+        .enumerate()
+        .map(|(index, tile)| {
+            let (x0, x1) = (
+                pixel(tile.region.x.start, width),
+                pixel(tile.region.x.end, width),
+            );
+            // flip y, images start at the top.
+            let y0 = height - pixel(tile.region.y.end, height);
+            let y1 = height - pixel(tile.region.y.start, height);
+            Placement {
+                index,
+                source: (x0, y0, x1 - x0, y1 - y0),
+                x: x0,
+                y: y0,
+            }
+        })
+        .collect();
+    Ok(((width, height), placements))
+}
+
+// Copy the tiles into a new frame, all tiles converted to the same pixel type first.
+fn compose<P: Pixel>(
+    images: Vec<ImageBuffer<P, Vec<P::Subpixel>>>,
+    (width, height): (u32, u32),
+    placements: &[Placement],
+) -> Result<ImageBuffer<P, Vec<P::Subpixel>>, TileError> {
+    let mut frame = ImageBuffer::new(width, height);
+    for placement in placements {
+        let (x, y, w, h) = placement.source;
+        let source = images[placement.index].view(x, y, w, h);
+        frame
+            .copy_from(&*source, placement.x, placement.y)
+            .map_err(|e| TileError::Mismatch(e.to_string()))?;
+    }
+    Ok(frame)
+}
+
+/// Put the tiles of a frame back together and save it in the destination folder, under the same file name as the tiles.
+/// The file extension of the tiles decides the format, PNG, EXR and TIFF are supported.
+pub fn stitch(tiles: &[Tile], destination: &Path) -> Result<PathBuf, TileError> {
+    let file_name = tiles
+        .first()
+        .and_then(|tile| tile.path.file_name())
+        .ok_or(TileError::Empty)?;
+    let regions = tiles.iter().map(|t| t.region.clone()).collect::<Vec<_>>();
+    check_coverage(&regions)?;
+
+    let images = tiles
+        .iter()
+        .map(|tile| {
+            image::open(&tile.path).map_err(|e| TileError::Open(tile.path.clone(), e.to_string()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let sizes = images.iter().map(|i| i.dimensions()).collect::<Vec<_>>();
+
+    let (size, placements) = match tiles.iter().all(|t| t.cropped) {
+        true => layout_cropped(tiles, &sizes)?,
+        false if tiles.iter().all(|t| !t.cropped) => layout_uncropped(tiles, &sizes)?,
+        false => {
+            return Err(TileError::Mismatch(
+                "can't mix cropped and uncropped tiles".to_owned(),
+            ))
+        }
+    };
+
+    // keep the depth of the deepest tile, and always keep alpha.
+    let frame = match images.iter().map(depth).max().unwrap_or(Depth::Eight) {
+        Depth::Float => DynamicImage::from(compose(
+            images.iter().map(DynamicImage::to_rgba32f).collect(),
+            size,
+            &placements,
+        )?),
+        Depth::Sixteen => DynamicImage::from(compose(
+            images.iter().map(DynamicImage::to_rgba16).collect(),
+            size,
+            &placements,
+        )?),
+        Depth::Eight => DynamicImage::from(compose(
+            images.iter().map(DynamicImage::to_rgba8).collect(),
+            size,
+            &placements,
+        )?),
+    };
+
+    let path = destination.join(file_name);
+    frame
+        .save(&path)
+        .map_err(|e| TileError::Save(e.to_string()))?;
+    Ok(path)
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum Depth {
+    Eight,
+    Sixteen,
+    Float,
+}
+
+fn depth(image: &DynamicImage) -> Depth {
+    match image.color() {
+        ColorType::Rgb32F | ColorType::Rgba32F => Depth::Float,
+        color if color.bytes_per_pixel() / color.channel_count() == 2 => Depth::Sixteen,
+        _ => Depth::Eight,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, Rgba32FImage, RgbaImage};
+
+    // somewhere to put the synthetic tiles, removed once the test is done.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("tile_{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(dir.join("output")).unwrap();
+            Self(dir)
+        }
+
+        // each tile goes in its own folder, same as the tasks do.
+        fn tile(&self, index: usize, file_name: &str) -> PathBuf {
+            let path = self.0.join(index.to_string()).join(file_name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            path
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn should_stitch_cropped_png_tiles_in_place() {
+        let dir = TempDir::new();
+        // a 4x2 frame split into 2x2 regions, each region painted a different shade with a different alpha.
+        let tiles = Window::grid(2, 2)
+            .into_iter()
+            .enumerate()
+            .map(|(index, region)| {
+                let path = dir.tile(index, "0001.png");
+                let alpha = 255 - index as u8 * 50;
+                RgbaImage::from_pixel(2, 1, Rgba([index as u8, 0, 0, alpha]))
+                    .save(&path)
+                    .unwrap();
+                Tile {
+                    region,
+                    path,
+                    cropped: true,
+                }
+            })
+            .rev()
+            .collect::<Vec<_>>();
+
+        let path = stitch(&tiles, &dir.0.join("output")).unwrap();
+        let frame = image::open(&path).unwrap().to_rgba8();
+        assert_eq!(frame.dimensions(), (4, 2));
+        assert_eq!(frame.get_pixel(0, 0), &Rgba([0, 0, 0, 255]));
+        assert_eq!(frame.get_pixel(3, 0), &Rgba([1, 0, 0, 205]));
+        assert_eq!(frame.get_pixel(0, 1), &Rgba([2, 0, 0, 155]));
+        assert_eq!(frame.get_pixel(3, 1), &Rgba([3, 0, 0, 105]));
+    }
+
+    #[test]
+    fn should_keep_float_pixels_of_uncropped_exr_tiles() {
+        let dir = TempDir::new();
+        // blender leaves everything outside the border empty, so only the region should make it into the frame.
+        let tiles = Window::grid(1, 3)
+            .into_iter()
+            .enumerate()
+            .map(|(index, region)| {
+                let path = dir.tile(index, "0001.exr");
+                let value = 1.5 + index as f32;
+                Rgba32FImage::from_fn(5, 6, |_, y| match y / 2 == index as u32 {
+                    true => Rgba([value, 0.0, 0.0, 0.5]),
+                    false => Rgba([0.0; 4]),
+                })
+                .save(&path)
+                .unwrap();
+                Tile {
+                    region,
+                    path,
+                    cropped: false,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let path = stitch(&tiles, &dir.0.join("output")).unwrap();
+        let frame = image::open(&path).unwrap().to_rgba32f();
+        assert_eq!(frame.dimensions(), (5, 6));
+        for y in 0..6 {
+            assert_eq!(
+                frame.get_pixel(4, y),
+                &Rgba([1.5 + (y / 2) as f32, 0.0, 0.0, 0.5])
+            );
+        }
+    }
+
+    #[test]
+    fn should_keep_16_bit_tiff_tiles() {
+        let dir = TempDir::new();
+        let tiles = Window::grid(2, 1)
+            .into_iter()
+            .enumerate()
+            .map(|(index, region)| {
+                let path = dir.tile(index, "0001.tif");
+                let image: ImageBuffer<Rgba<u16>, Vec<u16>> =
+                    ImageBuffer::from_pixel(3, 2, Rgba([1000 + index as u16, 0, 0, 40000]));
+                image.save(&path).unwrap();
+                Tile {
+                    region,
+                    path,
+                    cropped: true,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let path = stitch(&tiles, &dir.0.join("output")).unwrap();
+        let frame = image::open(&path).unwrap().to_rgba16();
+        assert_eq!(frame.dimensions(), (6, 2));
+        assert_eq!(frame.get_pixel(0, 1), &Rgba([1000, 0, 0, 40000]));
+        assert_eq!(frame.get_pixel(5, 1), &Rgba([1001, 0, 0, 40000]));
+    }
+
+    #[test]
+    fn should_reject_tiles_that_miss_or_overlap() {
+        let mut regions = Window::grid(2, 2);
+        assert!(check_coverage(&regions).is_ok());
+
+        let missing = regions.pop().unwrap();
+        assert!(matches!(check_coverage(&regions), Err(TileError::Gap(_))));
+
+        regions.push(missing);
+        regions.push(Window::default());
+        assert!(matches!(
+            check_coverage(&regions),
+            Err(TileError::Overlap(_))
+        ));
+        assert!(matches!(check_coverage(&[]), Err(TileError::Empty)));
+    }
+}
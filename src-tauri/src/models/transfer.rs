@@ -0,0 +1,185 @@
+/*
+    Developer blog:
+    - Files used to go across in one message, which meant reading the whole .blend into memory on one side and holding all of it on the other.
+        Now the receiver asks for one chunk at a time, and the sender only reads that chunk from disk.
+    - Several chunks are in flight at once, spread over every peer providing the file. The window caps how many chunks we wait on or hold in memory,
+        so a slow disk or a fast network can't pile up more than WINDOW * CHUNK_SIZE bytes.
+    - Chunks can arrive in any order, but we only append them to the .part file in order. That way the size of the .part file is how far we got,
+        and a download that got cut off picks up from there next time.
+    - Note: resuming assumes the file didn't change in between. Project files are shared under their content hash, so that holds for them.
+        Renders are still shared by name, keep those unique (job id, task id).
+    - Project files used to be shared by name, so two scene.blend from different projects would collide, and a changed file got reused from the cache.
+        Now the key is the BLAKE3 hash of the file. The worker checks what it downloaded (or already had) against the hash before rendering with it.
+*/
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// BLAKE3 hash of the file, as hex. Reads the file in pieces, so it's fine for big projects.
+pub fn hash_file(path: &Path) -> std::io::Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update_reader(std::fs::File::open(path)?)?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Check the file against the hash we expect. Returns false if the file is missing or different.
+pub fn verify_file(path: &Path, hash: &str) -> bool {
+    hash_file(path).is_ok_and(|actual| actual.eq_ignore_ascii_case(hash))
+}
+
+/// Size of each chunk. CBOR sends bytes as an array of numbers, so a chunk can take up to twice this on the wire. Keep it well under the 10MB response limit.
+pub const CHUNK_SIZE: u64 = 1024 * 1024;
+// most chunks we wait on, or hold in memory, at once.
+const WINDOW: usize = 8;
+
+/// Read part of the file to send to the peer. Never reads more than CHUNK_SIZE, no matter what was asked for.
+/// Returns the bytes and the size of the whole file.
+pub fn read_chunk(path: &Path, offset: u64, length: u64) -> std::io::Result<(Vec<u8>, u64)> {
+    let mut file = std::fs::File::open(path)?;
+    let size = file.metadata()?.len();
+    file.seek(SeekFrom::Start(offset.min(size)))?;
+
+    let mut data = Vec::new();
+    file.take(length.min(CHUNK_SIZE)).read_to_end(&mut data)?;
+    Ok((data, size))
+}
+
+/// Keep track of which chunks of a file we asked for, and which ones are ready to be written to disk.
+#[derive(Debug)]
+pub struct Download {
+    // size of the whole file, we find out from the first chunk.
+    size: Option<u64>,
+    // bytes written to disk so far, always from the start of the file.
+    written: u64,
+    // next chunk we haven't asked for yet.
+    next: u64,
+    // chunks that failed, ask someone else for them.
+    retry: VecDeque<u64>,
+    // chunks that arrived ahead of the ones before them.
+    ready: BTreeMap<u64, Vec<u8>>,
+    in_flight: usize,
+}
+
+impl Download {
+    /// Start a download, skipping what's already on disk.
+    pub fn new(written: u64) -> Self {
+        Self {
+            size: None,
+            written,
+            next: written,
+            retry: VecDeque::new(),
+            ready: BTreeMap::new(),
+            in_flight: 0,
+        }
+    }
+
+    /// Offset of the next chunk to ask for. None if the window is full, or there's nothing left to ask.
+    pub fn next_request(&mut self) -> Option<u64> {
+        let limit = match self.size {
+            Some(_) => WINDOW,
+            // we don't know how big the file is yet, wait for the first chunk.
+            None => 1,
+        };
+        if self.in_flight + self.ready.len() >= limit {
+            return None;
+        }
+
+        let offset = match self.retry.pop_front() {
+            Some(offset) => offset,
+            None if self.size.is_some_and(|size| self.next >= size) => return None,
+            None => {
+                let offset = self.next;
+                self.next += CHUNK_SIZE;
+                offset
+            }
+        };
+        self.in_flight += 1;
+        Some(offset)
+    }
+
+    /// A chunk arrived. Returns the chunks that can be appended to the file now, in order.
+    pub fn received(&mut self, offset: u64, data: Vec<u8>, size: u64) -> Vec<Vec<u8>> {
+        self.in_flight = self.in_flight.saturating_sub(1);
+        self.size = Some(size);
+        if offset >= self.written && !data.is_empty() {
+            self.ready.insert(offset, data);
+        }
+
+        let mut chunks = Vec::new();
+        while let Some(data) = self.ready.remove(&self.written) {
+            self.written += data.len() as u64;
+            chunks.push(data);
+        }
+        chunks
+    }
+
+    /// Peer couldn't give us the chunk, ask again later.
+    pub fn failed(&mut self, offset: u64) {
+        self.in_flight = self.in_flight.saturating_sub(1);
+        self.retry.push_back(offset);
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.size == Some(self.written)
+    }
+
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_write_chunks_in_order() {
+        let size = CHUNK_SIZE * 3 + 10;
+        // the first few bytes are already on disk from last time.
+        let mut download = Download::new(10);
+        let first = download.next_request().unwrap();
+        assert_eq!(first, 10);
+        // nothing else goes out until we know how big the file is.
+        assert_eq!(download.next_request(), None);
+
+        let chunk = vec![0; CHUNK_SIZE as usize];
+        assert_eq!(download.received(first, chunk.clone(), size).len(), 1);
+        let offsets = std::iter::from_fn(|| download.next_request()).collect::<Vec<_>>();
+        assert_eq!(offsets, vec![CHUNK_SIZE + 10, CHUNK_SIZE * 2 + 10]);
+
+        // the last chunk shows up first, and has to wait for the one before it.
+        assert!(download
+            .received(offsets[1], chunk.clone(), size)
+            .is_empty());
+        download.failed(offsets[0]);
+        assert_eq!(download.next_request(), Some(offsets[0]));
+        assert_eq!(download.received(offsets[0], chunk, size).len(), 2);
+        assert!(download.is_done());
+        assert_eq!(download.written(), size);
+    }
+
+    #[test]
+    fn should_read_chunk_at_offset() {
+        let path = std::env::temp_dir().join(format!("chunk_{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, b"0123456789").unwrap();
+        assert_eq!(read_chunk(&path, 4, 3).unwrap(), (b"456".to_vec(), 10));
+        assert_eq!(read_chunk(&path, 8, 5).unwrap(), (b"89".to_vec(), 10));
+        assert_eq!(read_chunk(&path, 20, 5).unwrap(), (Vec::new(), 10));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn should_verify_file_against_hash() {
+        let path = std::env::temp_dir().join(format!("hash_{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, b"scene").unwrap();
+        let hash = hash_file(&path).unwrap();
+        assert_eq!(hash, blake3::hash(b"scene").to_hex().to_string());
+        assert!(verify_file(&path, &hash));
+
+        // same name, different content.
+        std::fs::write(&path, b"other scene").unwrap();
+        assert!(!verify_file(&path, &hash));
+        std::fs::remove_file(&path).unwrap();
+        assert!(!verify_file(&path, &hash));
+    }
+}
@@ -1,15 +1,29 @@
-use blender::models::mode::Mode;
+use blender::{
+    blender::Blender,
+    models::{
+        format::{ColorDepth, Format},
+        mode::Mode,
+        render_override::RenderOverride,
+        render_pass::{Aov, RenderPass},
+    },
+};
 use maud::html;
 use semver::Version;
+use serde::{de::DeserializeOwned, Deserialize};
 use serde_json::json;
 use std::path::PathBuf;
 use std::{ops::Range, str::FromStr};
-use tauri::{command, State};
+use tauri::{command, AppHandle, Emitter, State};
 use tokio::sync::Mutex;
 use uuid::Uuid;
 
 use crate::{
-    models::{app_state::AppState, job::Job},
+    models::{
+        app_state::AppState,
+        ffmpeg::{Codec, FFmpeg, VideoSetting},
+        job::Job,
+        retry_policy::RetryPolicy,
+    },
     services::tauri_app::UiCommand,
 };
 
@@ -25,14 +39,77 @@ pub async fn create_job(
     version: Version,
     path: PathBuf,
     output: PathBuf,
+    // frame expression, overrides start and end when provided.
+    frames: Option<String>,
+    max_attempts: String,
+    // checkbox only sends a value when it's checked.
+    retry_elsewhere: Option<String>,
+    priority: String,
+    // split a single frame into a grid of regions, 1x1 renders the frame as a whole.
+    columns: String,
+    rows: String,
+    // overrides, left empty to keep what's in the blend file.
+    scene: Option<String>,
+    camera: Option<String>,
+    view_layers: Option<Selected>,
+    engine: Option<String>,
+    device: Option<String>,
+    samples: Option<String>,
+    denoiser: Option<String>,
+    width: Option<String>,
+    height: Option<String>,
+    percentage: Option<String>,
+    format: Option<String>,
+    // options of the format, left empty to keep blender's defaults.
+    color_depth: Option<String>,
+    quality: Option<String>,
+    codec: Option<String>,
+    // checked passes, and AOV names separated by comma.
+    passes: Option<Selected>,
+    aovs: Option<String>,
 ) -> Result<String, String> {
     // first thing first, parse the string into number
     let start = start.parse::<i32>().map_err(|e| e.to_string())?;
     let end = end.parse::<i32>().map_err(|e| e.to_string())?;
+    let max_attempts = max_attempts.parse::<u32>().map_err(|e| e.to_string())?;
+    let priority = priority.parse::<i32>().map_err(|e| e.to_string())?;
+    let columns = columns.parse::<u16>().map_err(|e| e.to_string())?;
+    let rows = rows.parse::<u16>().map_err(|e| e.to_string())?;
+    let overrides = RenderOverride {
+        scene: filled(scene),
+        camera: filled(camera),
+        view_layers: selected(view_layers),
+        engine: choice(engine)?,
+        device: choice(device)?,
+        samples: number(samples)?,
+        denoiser: choice(denoiser)?,
+        width: number(width)?,
+        height: number(height)?,
+        resolution_percentage: number(percentage)?,
+        format: image_format(format, color_depth, quality, codec)?,
+        passes: render_passes(passes, aovs)?,
+    };
     // stop if the parse fail to parse.
 
-    let mode = Mode::Animation(Range { start, end });
-    let job = Job::from(path, output, version, mode);
+    let mode = match frames.as_deref().map(str::trim) {
+        Some(expression) if !expression.is_empty() => {
+            Mode::from_str(expression).map_err(|e| e.to_string())?
+        }
+        _ => Mode::Animation(Range { start, end }),
+    };
+    let mode = match (mode, columns as u32 * rows as u32) {
+        (mode, 0 | 1) => mode,
+        (Mode::Frame(frame), _) => Mode::Section {
+            frame,
+            columns,
+            rows,
+        },
+        _ => return Err("Regions can only be used to render a single frame!".to_owned()),
+    };
+    let mut job = Job::from(path, output, version, mode);
+    job.retry_policy = RetryPolicy::new(max_attempts, retry_elsewhere.is_some());
+    job.priority = priority;
+    job.overrides = overrides;
     let app_state = state.lock().await;
     let mut jobs = app_state.job_db.write().await;
 
@@ -54,6 +131,86 @@ pub async fn create_job(
     remote_render_page().await
 }
 
+// Form sends an empty string for the fields the user left alone.
+fn filled(value: Option<String>) -> Option<String> {
+    value
+        .map(|value| value.trim().to_owned())
+        .filter(|value| !value.is_empty())
+}
+
+fn number<T: FromStr>(value: Option<String>) -> Result<Option<T>, String>
+where
+    T::Err: ToString,
+{
+    filled(value)
+        .map(|value| value.parse::<T>().map_err(|e| e.to_string()))
+        .transpose()
+}
+
+// a single checked box or selected option comes through as a string, more than one as a list.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Selected {
+    One(String),
+    Many(Vec<String>),
+}
+
+fn selected(value: Option<Selected>) -> Vec<String> {
+    let values = match value {
+        None => Vec::new(),
+        Some(Selected::One(value)) => vec![value],
+        Some(Selected::Many(values)) => values,
+    };
+    values.into_iter().filter_map(|v| filled(Some(v))).collect()
+}
+
+// select values are the enum variant names.
+fn choice<T: DeserializeOwned>(value: Option<String>) -> Result<Option<T>, String> {
+    filled(value)
+        .map(|value| serde_json::from_value(json!(value)).map_err(|e| e.to_string()))
+        .transpose()
+}
+
+// Format picked in the form, with whichever options the user filled in.
+fn image_format(
+    format: Option<String>,
+    color_depth: Option<String>,
+    quality: Option<String>,
+    codec: Option<String>,
+) -> Result<Option<Format>, String> {
+    let Some(name) = filled(format) else {
+        return Ok(None);
+    };
+    let mut format = Format::from_str(&name).map_err(|e| e.to_string())?;
+    if let Some(depth) = choice::<ColorDepth>(color_depth)? {
+        format.set_color_depth(depth).map_err(|e| e.to_string())?;
+    }
+    if let Some(quality) = number::<u8>(quality)? {
+        format.set_quality(quality).map_err(|e| e.to_string())?;
+    }
+    if let Some(codec) = filled(codec) {
+        format.set_codec(&codec).map_err(|e| e.to_string())?;
+    }
+    Ok(Some(format))
+}
+
+fn render_passes(
+    passes: Option<Selected>,
+    aovs: Option<String>,
+) -> Result<Vec<RenderPass>, String> {
+    let mut passes = selected(passes)
+        .into_iter()
+        .map(|pass| serde_json::from_value(json!(pass)).map_err(|e| e.to_string()))
+        .collect::<Result<Vec<RenderPass>, String>>()?;
+    for aov in filled(aovs).iter().flat_map(|aovs| aovs.split(',')) {
+        if !aov.trim().is_empty() {
+            let aov = Aov::from_str(aov).map_err(|e| e.to_string())?;
+            passes.push(RenderPass::Aov(aov));
+        }
+    }
+    Ok(passes)
+}
+
 #[command(async)]
 pub async fn list_jobs(state: State<'_, Mutex<AppState>>) -> Result<String, ()> {
     let server = state.lock().await;
@@ -89,16 +246,101 @@ pub async fn get_job(state: State<'_, Mutex<AppState>>, job_id: &str) -> Result<
     let jobs = app_state.job_db.read().await;
 
     match jobs.get_job(&job_id).await {
-        Ok(job) => Ok(html!(
-        div {
-                p { "Job Detail" };
-                div { ( job.project_file.to_str().unwrap() ) };
-                div { ( job.output.to_str().unwrap() ) };
-                div { ( job.blender_version.to_string() ) };
-                button tauri-invoke="delete_job" hx-vals=(json!({"jobId":job_id})) hx-target="#workplace" { "Delete Job" };
-            };
-        )
-        .0),
+        Ok(job) => {
+            let failures = jobs.list_failures(&job_id).await.unwrap_or_default();
+            // frames we gave up on, so the user can render them again in a new job.
+            let failed = failures
+                .iter()
+                .filter(|f| f.permanent && !job.get_renders().contains_key(&f.frame))
+                .map(|f| f.frame)
+                .collect::<Vec<_>>();
+            let renders = job.get_renders();
+            let mut frames = renders.keys().collect::<Vec<_>>();
+            frames.sort();
+            Ok(html!(
+            div {
+                    p { "Job Detail" };
+                    div { ( job.project_file.to_str().unwrap() ) };
+                    div { ( job.output.to_str().unwrap() ) };
+                    div { ( job.blender_version.to_string() ) };
+                    div { "Frames: " ( job.mode ) };
+                    div { "Max attempts per frame: " ( job.retry_policy.max_attempts ) };
+                    div { "Priority: " ( job.priority ) };
+                    @if job.stopped {
+                        div { "Stopped" };
+                    }
+                    @if !failures.is_empty() {
+                        p { "Failed Frames" };
+                        table {
+                            tr {
+                                th { "Frame" };
+                                th { "Attempts" };
+                                th { "Status" };
+                                th { "Last Error" };
+                            };
+                            @for failure in failures {
+                                tr {
+                                    td { ( failure.frame ) };
+                                    td { ( failure.attempts ) };
+                                    td {
+                                        @if renders.contains_key(&failure.frame) { "Recovered" }
+                                        @else if failure.permanent { "Failed" }
+                                        @else { "Retrying" }
+                                    };
+                                    td { ( failure.error ) };
+                                };
+                            };
+                        };
+                        @if !failed.is_empty() {
+                            div { "Render failed frames again with: " ( Mode::List(failed) ) };
+                        };
+                    };
+                    @if !frames.is_empty() {
+                        p { "Completed Frames (" ( frames.len() ) ")" };
+                        table {
+                            tr {
+                                th { "Frame" };
+                                th { "Path" };
+                                th { "Passes" };
+                            };
+                            @for frame in frames {
+                                tr {
+                                    td { ( frame ) };
+                                    td { ( renders[frame].to_string_lossy() ) };
+                                    td {
+                                        @for pass in job.passes.get(frame).into_iter().flatten() {
+                                            div { ( pass.to_string_lossy() ) };
+                                        }
+                                    };
+                                };
+                            };
+                        };
+                    };
+                    @if !frames.is_empty() {
+                        p { "Video" };
+                        form tauri-invoke="encode_video" hx-vals=(json!({"jobId":job_id})) hx-target="#video" {
+                            select name="codec" {
+                                option value="H264" { "MP4 (H.264)" };
+                                option value="ProRes" { "MOV (ProRes)" };
+                                option value="VP9" { "WebM (VP9)" };
+                            };
+                            label { "Quality:" };
+                            input class="form-input" name="quality" type="number" min="0" max="100" value="80";
+                            label { "FPS:" };
+                            input class="form-input" name="fps" type="number" min="1" placeholder="From blend file";
+                            // preview the frames we have so far.
+                            label { "Draft" };
+                            input name="draft" type="checkbox";
+                            button type="submit" { "Make Video" };
+                        };
+                        div id="video";
+                    };
+                    button tauri-invoke="stop_job" hx-vals=(json!({"jobId":job_id})) hx-target="#workplace" { "Stop Job" };
+                    button tauri-invoke="delete_job" hx-vals=(json!({"jobId":job_id})) hx-target="#workplace" { "Delete Job" };
+                };
+            )
+            .0)
+        }
         Err(e) => Ok(html!(
         div {
                 p { "Job do not exist.. How did you get here?" };
@@ -109,6 +351,109 @@ pub async fn get_job(state: State<'_, Mutex<AppState>>, job_id: &str) -> Result<
     }
 }
 
+/// Put the rendered frames of the job together into a video, next to the job's output.
+/// Progress is published to the front end as "video_update" events while ffmpeg runs.
+#[command(async)]
+pub async fn encode_video(
+    state: State<'_, Mutex<AppState>>,
+    app: AppHandle,
+    job_id: &str,
+    codec: String,
+    quality: String,
+    // leave empty to use the fps from the blend file.
+    fps: Option<String>,
+    // checkbox only sends a value when it's checked.
+    draft: Option<String>,
+) -> Result<String, String> {
+    let id = Uuid::from_str(job_id).map_err(|e| e.to_string())?;
+    let codec = Codec::from_str(&codec).map_err(|e| e.to_string())?;
+    let quality = quality.parse::<u8>().map_err(|e| e.to_string())?;
+    let draft = draft.is_some();
+
+    let (job, ffmpeg) = {
+        let app_state = state.lock().await;
+        let jobs = app_state.job_db.read().await;
+        let job = jobs.get_job(&id).await.map_err(|e| e.to_string())?;
+        let ffmpeg = app_state.setting.read().await.ffmpeg.clone();
+        (job, ffmpeg)
+    };
+    let ffmpeg = FFmpeg::find(ffmpeg.as_deref()).map_err(|e| e.to_string())?;
+
+    let fps = match fps.as_deref().map(str::trim) {
+        Some(fps) if !fps.is_empty() => fps.parse::<u16>().map_err(|e| e.to_string())?,
+        _ => Blender::peek(job.get_project_path())
+            .await
+            .map_err(|e| e.to_string())?
+            .selected()
+            .map(|scene| scene.fps)
+            .unwrap_or(24),
+    };
+    let setting = VideoSetting {
+        codec,
+        fps,
+        quality,
+    };
+
+    let name = job
+        .project_file
+        .file_stem()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| id.to_string());
+    let suffix = if draft { "_draft" } else { "" };
+    let output = job
+        .output
+        .join(format!("{name}{suffix}.{}", codec.extension()));
+    std::fs::create_dir_all(&job.output).map_err(|e| e.to_string())?;
+
+    let receiver = ffmpeg
+        .encode(&job.frames(), job.get_renders(), &setting, &output, draft)
+        .map_err(|e| e.to_string())?;
+
+    // ffmpeg reports on a blocking channel, forward it to the front end the same way we do with render progress.
+    tokio::task::spawn_blocking(move || {
+        while let Ok(status) = receiver.recv() {
+            if let Err(e) = app.emit("video_update", (id, status)) {
+                eprintln!("Fail to publish video progress to front end! {e:?}");
+            }
+        }
+    });
+
+    Ok(html!(
+        div { "Encoding " (codec) " video to " (output.to_string_lossy()) };
+    )
+    .0)
+}
+
+/// Tell all workers to stop rendering this job. The job remains in the database.
+#[command(async)]
+pub async fn stop_job(state: State<'_, Mutex<AppState>>, job_id: &str) -> Result<String, String> {
+    {
+        let id = Uuid::from_str(job_id).map_err(|e| e.to_string())?;
+        let server = state.lock().await;
+
+        // remember the job was stopped, otherwise it starts rendering again next time the app starts.
+        {
+            let mut jobs = server.job_db.write().await;
+            match jobs.get_job(&id).await {
+                Ok(mut job) => {
+                    job.stopped = true;
+                    if let Err(e) = jobs.update_job(job).await {
+                        eprintln!("Fail to save stopped job! {e:?}");
+                    }
+                }
+                Err(e) => eprintln!("Unable to find job to stop! {e:?}"),
+            }
+        }
+
+        let msg = UiCommand::StopJob(id);
+        if let Err(e) = server.to_network.send(msg).await {
+            eprintln!("Fail to send stop job command! {e:?}");
+        }
+    }
+
+    remote_render_page().await
+}
+
 // we'll need to figure out more about this? How exactly are we going to update the job?
 // #[command(async)]
 // pub fn update_job()
@@ -121,11 +466,10 @@ pub async fn delete_job(state: State<'_, Mutex<AppState>>, job_id: &str) -> Resu
         let server = state.lock().await;
         let mut jobs = server.job_db.write().await;
         let _ = jobs.delete_job(&id).await;
-        // TODO: Figure out what suppose to be done and handle here?
-        // let msg = UiCommand::StopJob(id);
-        // if let Err(e) = server.to_network.send(msg).await {
-        //     eprintln!("Fail to send stop job command! {e:?}");
-        // }
+        let msg = UiCommand::StopJob(id);
+        if let Err(e) = server.to_network.send(msg).await {
+            eprintln!("Fail to send stop job command! {e:?}");
+        }
     }
 
     remote_render_page().await
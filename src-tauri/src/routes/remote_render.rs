@@ -19,6 +19,31 @@ use tauri_plugin_dialog::DialogExt;
 use tauri_plugin_fs::FilePath;
 use tokio::sync::Mutex;
 
+// value is the RenderPass variant, see create_job()
+const RENDER_PASSES: [(&str, &str); 21] = [
+    ("Depth", "Depth"),
+    ("Mist", "Mist"),
+    ("Normal", "Normal"),
+    ("Vector", "Vector"),
+    ("Position", "Position"),
+    ("DiffuseDirect", "Diffuse Direct"),
+    ("DiffuseIndirect", "Diffuse Indirect"),
+    ("DiffuseColor", "Diffuse Color"),
+    ("GlossyDirect", "Glossy Direct"),
+    ("GlossyIndirect", "Glossy Indirect"),
+    ("GlossyColor", "Glossy Color"),
+    ("TransmissionDirect", "Transmission Direct"),
+    ("TransmissionIndirect", "Transmission Indirect"),
+    ("TransmissionColor", "Transmission Color"),
+    ("Emission", "Emission"),
+    ("Environment", "Environment"),
+    ("AmbientOcclusion", "Ambient Occlusion"),
+    ("Shadow", "Shadow"),
+    ("CryptomatteObject", "Cryptomatte Object"),
+    ("CryptomatteMaterial", "Cryptomatte Material"),
+    ("CryptomatteAsset", "Cryptomatte Asset"),
+];
+
 // todo break commands apart, find a way to get the list of versions
 async fn list_versions(app_state: &AppState) -> Vec<Version> {
     let manager = app_state.manager.read().await;
@@ -100,6 +125,18 @@ pub async fn import_blend(
         Ok(data) => data,
         Err(e) => return Err(e.to_string()),
     };
+    // fill in the form from the scene blender would render.
+    let Some(scene) = data.selected() else {
+        return Err("Blend file has no scene to render!".to_owned());
+    };
+    // the frame expression keeps the scene's frame step, see Mode::from_str
+    let frames = match scene.frame_step > 1 {
+        true => format!(
+            "{}-{}x{}",
+            scene.frame_start, scene.frame_end, scene.frame_step
+        ),
+        false => String::new(),
+    };
 
     let content = html! {
         div id="modal" _="on closeModal add .closing then wait for animationend then remove me" {
@@ -113,7 +150,7 @@ pub async fn import_blend(
                     br;
 
                     label { "Output destination:" };
-                    input type="text" tauri-invoke="select_directory" hx-target="this" class="form-input" placeholder="Output Path" name="output" value=(data.output.to_str().unwrap()) readonly={true};
+                    input type="text" tauri-invoke="select_directory" hx-target="this" class="form-input" placeholder="Output Path" name="output" value=(scene.output.to_str().unwrap()) readonly={true};
                     br;
 
                     div name="mode" {
@@ -138,14 +175,226 @@ pub async fn import_blend(
                                     };
                                 }
                                 td style="width:33%" {
-                                    input class="form-input" name="start" type="number" value=(data.frame_start);
+                                    input class="form-input" name="start" type="number" value=(scene.frame_start);
                                 };
                                 td style="width:33%" {
-                                    input class="form-input" name="end" type="number" value=(data.frame_end);
+                                    input class="form-input" name="end" type="number" value=(scene.frame_end);
+                                };
+                            };
+                        };
+                    };
+
+                    // what's in the file, so the user knows what the workers will render.
+                    table {
+                        tr {
+                            th { "Scene" };
+                            th { "Engine" };
+                            th { "Samples" };
+                            th { "Resolution" };
+                            th { "Camera" };
+                            th { "View Layers" };
+                            th { "Format" };
+                        };
+                        @for info in &data.scenes {
+                            tr {
+                                td { (info.name) @if info.name == scene.name { " (active)" } };
+                                td { (info.engine) };
+                                td {
+                                    (info.samples)
+                                    @if let Some(denoiser) = &info.denoiser { " (" (denoiser) ")" }
+                                };
+                                td { (info.render_width) "x" (info.render_height) " " (info.resolution_percentage) "%" };
+                                td { (info.active_camera) " of " (info.cameras.len()) };
+                                td { (info.view_layers.join(", ")) };
+                                td { (info.format) };
+                            };
+                        };
+                    };
+                    br;
+
+                    // left empty, the workers render what's saved in the blend file.
+                    h2 { "Overrides" };
+                    table {
+                        tr {
+                            th { label htmlfor="scene" { "Scene" } };
+                            th { label htmlfor="camera" { "Camera" } };
+                            th { label htmlfor="viewLayers" { "View Layers" } };
+                        };
+                        tr {
+                            td {
+                                select name="scene" {
+                                    option value="" selected { "Keep file (" (scene.name) ")" };
+                                    @for info in &data.scenes {
+                                        option value=(info.name) { (info.name) };
+                                    }
+                                };
+                            };
+                            td {
+                                select name="camera" {
+                                    option value="" selected { "Keep file (" (scene.active_camera) ")" };
+                                    @for info in &data.scenes {
+                                        optgroup label=(info.name) {
+                                            @for camera in &info.cameras {
+                                                option value=(camera) { (camera) };
+                                            }
+                                        };
+                                    }
+                                };
+                            };
+                            td {
+                                // nothing selected renders the layers the file uses.
+                                select name="viewLayers" multiple {
+                                    @for info in &data.scenes {
+                                        optgroup label=(info.name) {
+                                            @for layer in &info.view_layers {
+                                                option value=(layer) { (layer) };
+                                            }
+                                        };
+                                    }
+                                };
+                            };
+                        };
+                        tr {
+                            th { label htmlfor="engine" { "Engine" } };
+                            th { label htmlfor="device" { "Device" } };
+                            th { label htmlfor="samples" { "Samples" } };
+                        };
+                        tr {
+                            td {
+                                select name="engine" {
+                                    option value="" selected { "Keep file (" (scene.engine) ")" };
+                                    option value="Cycles" { "Cycles" };
+                                    option value="Eevee" { "Eevee" };
+                                    option value="Workbench" { "Workbench" };
+                                };
+                            };
+                            td {
+                                // workers without the device won't get the job.
+                                select name="device" {
+                                    option value="" selected { "Best on each worker" };
+                                    option value="CPU" { "CPU" };
+                                    @for (device, name) in [("OPTIX", "OptiX"), ("CUDA", "CUDA"), ("HIP", "HIP"), ("METAL", "Metal"), ("ONEAPI", "oneAPI")] {
+                                        option value=(format!("{device}_GPUONLY")) { (name) " only" };
+                                        option value=(device) { (name) " + CPU" };
+                                    }
+                                };
+                            };
+                            td {
+                                input class="form-input" name="samples" type="number" min="1" placeholder=(scene.samples);
+                            };
+                        };
+                        tr {
+                            th { label htmlfor="width" { "Width" } };
+                            th { label htmlfor="height" { "Height" } };
+                            th { label htmlfor="percentage" { "Resolution %" } };
+                        };
+                        tr {
+                            td {
+                                input class="form-input" name="width" type="number" min="1" placeholder=(scene.render_width);
+                            };
+                            td {
+                                input class="form-input" name="height" type="number" min="1" placeholder=(scene.render_height);
+                            };
+                            td {
+                                input class="form-input" name="percentage" type="number" min="1" max="100" placeholder=(scene.resolution_percentage);
+                            };
+                        };
+                        tr {
+                            th { label htmlfor="denoiser" { "Denoiser (Cycles)" } };
+                            th { label htmlfor="format" { "Format" } };
+                        };
+                        tr {
+                            td {
+                                select name="denoiser" {
+                                    option value="" selected { "Keep file (" (scene.denoiser.as_deref().unwrap_or("Off")) ")" };
+                                    option value="Off" { "Off" };
+                                    option value="OpenImageDenoise" { "OpenImageDenoise" };
+                                    option value="OptiX" { "OptiX" };
+                                };
+                            };
+                            td {
+                                select name="format" {
+                                    option value="" selected { "Keep file (" (scene.format) ")" };
+                                    @for format in ["PNG", "JPEG", "OPEN_EXR", "OPEN_EXR_MULTILAYER", "TIFF", "WEBP", "JPEG2000", "DPX", "CINEON", "HDR", "BMP", "TARGA", "TARGA_RAW", "IRIS"] {
+                                        option value=(format) { (format) };
+                                    }
                                 };
                             };
                         };
+                        // only apply when a format is picked above, blender's defaults otherwise.
+                        tr {
+                            th { label htmlfor="colorDepth" { "Color Depth" } };
+                            th { label htmlfor="quality" { "Quality / Compression %" } };
+                            th { label htmlfor="codec" { "Codec" } };
+                        };
+                        tr {
+                            td {
+                                select name="colorDepth" {
+                                    option value="" selected { "Default" };
+                                    @for depth in ["8", "10", "12", "16", "32"] {
+                                        option value=(depth) { (depth) " bit" };
+                                    }
+                                };
+                            };
+                            td {
+                                input class="form-input" name="quality" type="number" min="0" max="100";
+                            };
+                            td {
+                                select name="codec" {
+                                    option value="" selected { "Default" };
+                                    optgroup label="OpenEXR" {
+                                        @for codec in ["ZIP", "PIZ", "DWAA", "DWAB", "PXR24", "ZIPS", "RLE", "B44", "B44A", "NONE"] {
+                                            option value=(codec) { (codec) };
+                                        }
+                                    };
+                                    optgroup label="TIFF" {
+                                        @for codec in ["DEFLATE", "LZW", "PACKBITS"] {
+                                            option value=(codec) { (codec) };
+                                        }
+                                    };
+                                };
+                            };
+                        };
+                    };
+                    br;
+
+                    // on every view layer that renders. Multilayer EXR keeps them in the image, other formats get an EXR per pass.
+                    h2 { "Render Passes" };
+                    div {
+                        @for (pass, name) in RENDER_PASSES {
+                            label {
+                                input name="passes" type="checkbox" value=(pass);
+                                (name)
+                            };
+                        }
                     };
+                    label { "AOVs (e.g. Dirt, Mask:value):" };
+                    input class="form-input" name="aovs" type="text";
+                    br;
+
+                    // leave empty to render everything from start to end.
+                    label { "Frames (e.g. 1,5,10-20 or 1-250x10):" };
+                    input class="form-input" name="frames" type="text" placeholder="Start to End" value=(frames);
+                    br;
+
+                    // more than one region splits a single frame across the workers.
+                    label { "Regions (columns x rows), single frame only:" };
+                    input class="form-input" name="columns" type="number" min="1" value="1";
+                    input class="form-input" name="rows" type="number" min="1" value="1";
+                    br;
+
+                    label { "Max attempts per frame:" };
+                    input class="form-input" name="maxAttempts" type="number" min="1" value="3";
+                    br;
+
+                    label { "Retry failed frames on a different worker" };
+                    input name="retryElsewhere" type="checkbox" checked;
+                    br;
+
+                    // higher number goes first.
+                    label { "Priority:" };
+                    input class="form-input" name="priority" type="number" value="0";
+                    br;
 
                     menu {
                         button type="button" value="cancel" _="on click trigger closeModal" { "Cancel" };
@@ -126,16 +126,25 @@ pub async fn update_settings(
     install_path: String,
     cache_path: String,
     render_path: String,
+    ffmpeg_path: Option<String>,
 ) -> Result<String, String> {
     let install_path = PathBuf::from(install_path);
     let blend_dir = PathBuf::from(cache_path);
     let render_dir = PathBuf::from(render_path);
+    // leave it empty to use the ffmpeg on PATH.
+    let ffmpeg = ffmpeg_path
+        .filter(|path| !path.trim().is_empty())
+        .map(PathBuf::from);
 
     {
         let mut server = state.lock().await;
+        // not editable on this page yet, keep what we had.
+        let chunking = server.setting.read().await.chunking.clone();
         server.setting = Arc::new(RwLock::new(ServerSetting {
             blend_dir,
             render_dir,
+            chunking,
+            ffmpeg,
         }));
         let mut manager = server.manager.write().await;
         manager.set_install_path(&install_path);
@@ -152,6 +161,10 @@ pub async fn edit_settings(state: State<'_, Mutex<AppState>>) -> Result<String,
     let install_path = manager.get_install_path();
     let cache_path = &settings.blend_dir;
     let render_path = &settings.render_dir;
+    let ffmpeg_path = settings
+        .ffmpeg
+        .as_ref()
+        .map(|p| p.to_string_lossy().to_string());
 
     Ok(html!(
         form tauri-invoke="update_settings" hx-target="this" hx-swap="outerHTML" {
@@ -163,6 +176,9 @@ pub async fn edit_settings(state: State<'_, Mutex<AppState>>) -> Result<String,
 
             h3 { "Render cache directory:" };
             input name="renderPath" class="form-input" readonly="true" tauri-invoke="select_directory" hx-trigger="click" hx-target="this" value=(render_path.to_str().unwrap());
+
+            h3 { "FFmpeg executable:" };
+            input name="ffmpegPath" class="form-input" placeholder="Use ffmpeg from PATH" value=(ffmpeg_path.unwrap_or_default());
             
             br;
             
@@ -180,6 +196,10 @@ pub async fn get_settings(state: State<'_, Mutex<AppState>>) -> Result<String, S
     let install_path = manager.get_install_path().to_str().unwrap();
     let cache_path = &settings.blend_dir.to_str().unwrap();
     let render_path = &settings.render_dir.to_str().unwrap();
+    let ffmpeg_path = match &settings.ffmpeg {
+        Some(path) => path.to_string_lossy().to_string(),
+        None => "ffmpeg from PATH".to_owned(),
+    };
 
     Ok(html!(
         div tauri-invoke="open_path" hx-target="this" hx-swap="outerHTML" {
@@ -191,6 +211,9 @@ pub async fn get_settings(state: State<'_, Mutex<AppState>>) -> Result<String, S
             
             h3 { "Render cache directory:" };
             label hx-info=(json!( { "path": render_path } )) { (render_path) };
+
+            h3 { "FFmpeg executable:" };
+            label { (ffmpeg_path) };
             br;
             
             button tauri-invoke="edit_settings" { "Edit" };
@@ -71,6 +71,8 @@ pub async fn get_worker(state: State<'_, Mutex<AppState>>, machine_id: &str) ->
                 } @else {
                     p { "GPU: N/A" };
                 };
+                @let devices = worker.spec.devices.iter().map(ToString::to_string).collect::<Vec<String>>();
+                p { (format!("Render devices: {}", devices.join(", "))) };
             };
         }
         .0),
@@ -1,4 +1,8 @@
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, Mutex as SyncMutex, RwLock as SyncRwLock},
+};
 
 /*
 Have a look into TUI for CLI status display window to show user entertainment on screen
@@ -10,53 +14,188 @@ Feature request:
 */
 use super::blend_farm::BlendFarm;
 use crate::{
-    domains::{job_store::JobError, task_store::TaskStore},
+    domains::{
+        job_store::JobError,
+        task_store::{TaskError, TaskStore},
+    },
     models::{
-        job::JobEvent,
+        job::{Frame, JobEvent},
         message::{NetEvent, NetworkError},
         network::{NetworkController, JOB},
+        package,
         task::Task,
+        transfer,
     },
 };
 use blender::blender::Manager as BlenderManager;
-use blender::models::status::Status;
+use blender::models::{device::Device, render_handle::RenderHandle, status::Status};
 use libp2p::PeerId;
+use std::time::Duration;
 use tokio::{
-    select,
-    sync::{mpsc::Receiver, RwLock},
-    // task::JoinHandle,
+    select, spawn,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        RwLock,
+    },
+    time::interval,
 };
+use uuid::Uuid;
+
+// how often we check the queue for the next task when we're not rendering.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+// how much of our remaining frames we give away when the host asks us to share.
+const SPLIT_PERCENTAGE: i8 = 50;
+
+// blender tells us it started rendering the task through this channel, so we can hold on to the handle.
+type RenderStarted = (Uuid, Arc<RenderHandle>);
 
 pub struct CliApp {
-    manager: BlenderManager,
+    // shared with the render task, it may need to download blender.
+    manager: Arc<SyncMutex<BlenderManager>>,
     task_store: Arc<RwLock<(dyn TaskStore + Send + Sync + 'static)>>,
-    // Hmm not sure if I need this but we'll see!
-    // task_handle: Option<JoinHandle<()>>, // isntead of this, we should hold task_handler. That way, we can abort it when we receive the invocation to do so.
+    // hold on to the active render handles by job id. That way, we can cancel the render when we receive the invocation to do so.
+    render_handles: HashMap<Uuid, Arc<RenderHandle>>,
+    // task we're currently working on, None if we're free to pick up the next one from the queue.
+    // Blender pulls frames from this task as it goes, so shrinking the range here means we render less of it.
+    active_task: Option<Arc<SyncRwLock<Task>>>,
+    // the host removed the job of the active task before blender started, stop blender as soon as it does.
+    active_removed: bool,
+    // render devices blender found on this machine, shared with the host so it can pick one for us.
+    devices: Vec<Device>,
 }
 
 impl CliApp {
     pub fn new(task_store: Arc<RwLock<(dyn TaskStore + Send + Sync + 'static)>>) -> Self {
-        let manager = BlenderManager::load();
+        let manager = Arc::new(SyncMutex::new(BlenderManager::load()));
         Self {
             manager,
             task_store,
-            // task_handle: None,
+            render_handles: Default::default(),
+            active_task: None,
+            active_removed: false,
+            devices: vec![Device::CPU],
         }
     }
 }
 
 impl CliApp {
-    // TODO: May have to refactor this to take consideration of Job Storage
-    // How do I abort the job?
-    // Invokes the render job. The task needs to be mutable for frame deque.
-    async fn render_task(
+    // Save the task to the queue. The select! loop in run() will pick it up once we're free.
+    // That way we don't block network event while we download files or render.
+    async fn queue_task(&mut self, client: &mut NetworkController, task: Task) {
+        let status = format!("Receive task from peer [{:?}]", task);
+        client.send_status(status).await;
+
+        let mut db = self.task_store.write().await;
+        if let Err(e) = db.add_task(task).await {
+            eprintln!("Fail to save task to queue! {e:?}");
+        }
+    }
+
+    // Pull the next task from the queue and start rendering it.
+    async fn next_task(
         &mut self,
-        request_id: PeerId,
         client: &mut NetworkController,
-        task: &mut Task,
+        started: Sender<RenderStarted>,
+        finished: Sender<Task>,
     ) {
-        let status = format!("Receive task from peer [{:?}]", task);
-        client.send_status(status).await;
+        let task = match self.task_store.write().await.poll_task().await {
+            Ok(Some(task)) => task,
+            Ok(None) => return,
+            Err(e) => {
+                eprintln!("Fail to poll task from queue! {e:?}");
+                return;
+            }
+        };
+
+        let task = Arc::new(SyncRwLock::new(task));
+        self.active_task = Some(task.clone());
+        self.active_removed = false;
+
+        // downloading the project and blender can take a long while. Do it in the background so we keep up with the host, like a stop command.
+        let client = client.clone();
+        let manager = self.manager.clone();
+        spawn(Self::render_task(client, manager, task, started, finished));
+    }
+
+    // Blender started on the active task. Keep the handle so stop_render() can abort it,
+    // unless the job was removed while we were still fetching the files.
+    fn render_started(&mut self, job_id: Uuid, handle: Arc<RenderHandle>) {
+        if self.active_removed {
+            if let Err(e) = handle.cancel() {
+                eprintln!("Fail to stop render for job {job_id}! {e:?}");
+            }
+            return;
+        }
+
+        // forget about renders that have already finished.
+        self.render_handles.retain(|_, h| h.is_running());
+        self.render_handles.insert(job_id, handle);
+    }
+
+    fn is_active(&self, task_id: &Uuid) -> bool {
+        self.active_task
+            .as_ref()
+            .and_then(|task| task.read().ok().map(|task| task.id == *task_id))
+            .unwrap_or_default()
+    }
+
+    // Task is done (or failed, or cancelled). Remove it from the queue so we can move on to the next one.
+    // If there's nothing else in the queue, let the host know we can take on more work.
+    async fn finish_task(&mut self, client: &mut NetworkController, task: Task) {
+        if self.is_active(&task.id) {
+            self.active_task = None;
+        }
+
+        let host = task.get_peer_id();
+        let mut db = self.task_store.write().await;
+        if let Err(e) = db.delete_task(task).await {
+            eprintln!("Fail to remove task from queue! {e:?}");
+        }
+
+        if let Ok(None) = db.poll_task().await {
+            if let Err(e) = client.send_job_message(host, JobEvent::RequestJob).await {
+                eprintln!("Fail to request more work from host! {e:?}");
+            }
+        }
+    }
+
+    // Host would like us to share the rest of our task with an idle worker. Give away the tail end of the frames we haven't started yet.
+    async fn split_task(&mut self, client: &mut NetworkController, host: PeerId, task_id: Uuid) {
+        let frames = match &self.active_task {
+            Some(task) if self.is_active(&task_id) => match task.write() {
+                Ok(mut task) => task.fetch_end_frames(SPLIT_PERCENTAGE),
+                Err(_) => None,
+            },
+            _ => None,
+        };
+
+        let event = JobEvent::TaskSplit { task_id, frames };
+        if let Err(e) = client.send_job_message(host, event).await {
+            eprintln!("Fail to answer split request for task {task_id}! {e:?}");
+        }
+    }
+
+    // Invokes the render job. Runs on its own task, see next_task(). Once blender is done with the task, it's sent back through finished.
+    // The render handle is sent back through started, so that we can abort the job with stop_render()
+    async fn render_task(
+        mut client: NetworkController,
+        manager: Arc<SyncMutex<BlenderManager>>,
+        shared: Arc<SyncRwLock<Task>>,
+        started: Sender<RenderStarted>,
+        finished: Sender<Task>,
+    ) {
+        // we can't hold on to the lock while we render, blender needs it to fetch the next frame.
+        let task = match shared.read() {
+            Ok(task) => task.clone(),
+            Err(e) => {
+                eprintln!("Task lock was poisoned! {e:?}");
+                return;
+            }
+        };
+        let request_id = task.get_peer_id();
+        client
+            .send_status(format!("Start rendering task {}", task.id))
+            .await;
         let id = task.job_id;
 
         // create a path link where we think the file should be
@@ -72,47 +211,82 @@ impl CliApp {
             .send_status(format!("Checking for project file {:?}", &project_file))
             .await;
 
-        // Fetch the project from peer if we don't have it.
-        if !project_file.exists() {
-            println!(
-                "Project file do not exist, asking to download from host: {:?}",
-                &task.blend_file_name
-            );
+        // the project and everything it needs, laid out inside the job folder the same way they are on the host. See package.rs
+        let files = std::iter::once((&task.blend_file_name, &task.blend_file_hash)).chain(
+            task.dependencies
+                .iter()
+                .map(|file| (&file.path, &file.hash)),
+        );
+        // a file we couldn't get, there's no point starting blender without it.
+        let mut missing: Option<JobError> = None;
+        for (file_name, hash) in files {
+            if !package::is_safe(file_name) {
+                client
+                    .send_status(format!(
+                        "Refusing to save {file_name:?} outside of the job folder!"
+                    ))
+                    .await;
+                continue;
+            }
+            let file_path = blend_dir.join(file_name);
 
-            let file_name = task.blend_file_name.to_str().unwrap();
-            // TODO: To receive the path or not to modify existing project_file value? I expect both would have the same value?
-            match client.get_file_from_peers(&file_name, &blend_dir).await {
-                Ok(path) => println!("File successfully download from peers! path: {path:?}"),
-                Err(e) => match e {
-                    NetworkError::UnableToListen(_) => todo!(),
-                    NetworkError::NotConnected => todo!(),
-                    NetworkError::SendError(_) => {}
-                    NetworkError::NoPeerProviderFound => {
-                        // I was timed out here?
-                        client
-                            .send_status("No peer provider found on the network?".to_owned())
-                            .await
-                    }
-                    NetworkError::UnableToSave(e) => {
-                        client
-                            .send_status(format!("Fail to save file to disk: {e}"))
-                            .await
-                    }
-                    NetworkError::Timeout => {
-                        // somehow we lost connection, try to establish connection again?
-                        // client.dial(request_id, client.public_addr).await;
-                        dbg!("Timed out?");
+            // A copy from an earlier task may be out of date, the host could have changed the file since. Throw it away if the hash doesn't match.
+            if file_path.exists() {
+                let path = file_path.clone();
+                let expected = hash.clone();
+                let verified =
+                    tokio::task::spawn_blocking(move || transfer::verify_file(&path, &expected))
+                        .await
+                        .unwrap_or(false);
+                if !verified {
+                    client
+                        .send_status(format!("{file_name:?} changed, downloading it again."))
+                        .await;
+                    if let Err(e) = async_std::fs::remove_file(&file_path).await {
+                        eprintln!("Unable to remove outdated file! {e:?}");
                     }
-                    _ => println!("Unhandle error received {e:?}"), // shouldn't be covered?
-                },
+                }
+            }
+
+            // Fetch the file from peer if we don't have it.
+            if file_path.exists() {
+                continue;
+            }
+            println!("File do not exist, asking to download from host: {file_name:?}");
+
+            // the file is shared under its hash, and checked against it once it's here.
+            match client.get_verified_file(hash, &file_path).await {
+                Ok(path) => println!("File successfully download from peers! path: {path:?}"),
+                Err(e) => {
+                    let reason = format!("Unable to download {file_name:?}: {e}");
+                    client.send_status(reason.clone()).await;
+                    missing = Some(JobError::FailedToRun(reason));
+                    break;
+                }
             }
         }
 
-        // here we'll ask if we have blender installed before usage
-        let blender = self
-            .manager
-            .fetch_blender(&task.blender_version)
-            .expect("Fail to download blender");
+        if let Some(error) = missing {
+            Self::abandon_task(&mut client, task, error, &finished).await;
+            return;
+        }
+
+        // here we'll ask if we have blender installed before usage. Downloading it blocks, keep it off the runtime.
+        let version = task.blender_version.clone();
+        let blender = tokio::task::spawn_blocking(move || match manager.lock() {
+            Ok(mut manager) => manager.fetch_blender(&version).map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        })
+        .await
+        .unwrap_or_else(|e| Err(e.to_string()));
+        let blender = match blender {
+            Ok(blender) => blender,
+            Err(e) => {
+                let error = JobError::TaskError(TaskError::BlenderError(e));
+                Self::abandon_task(&mut client, task, error, &finished).await;
+                return;
+            }
+        };
 
         // TODO: Call other network on specific topics to see if there's a version available.
         // match manager.have_blender(job.as_ref()) {
@@ -124,19 +298,57 @@ impl CliApp {
         // }
 
         // create a output destination for the render image
-        let output = client.settings.render_dir.join(id.to_string());
+        // regions of the same frame share the file name, so each region task gets its own folder.
+        let region = task.region.clone();
+        let output_dir = match region {
+            Some(_) => format!("{}/{}", id, task.id),
+            None => id.to_string(),
+        };
+        let output = client.settings.render_dir.join(&output_dir);
         if let Err(e) = async_std::fs::create_dir_all(&output).await {
             eprintln!("Error creating render directory: {e:?}");
         }
 
+        // keep the first frame, so we can tell the host which frames didn't get rendered if blender fails.
+        let first_frame = task.range.start;
+
         // run the job!
-        match task.clone().run(project_file, output, &blender).await {
-            Ok(rx) => loop {
-                if let Ok(status) = rx.recv() {
+        match Task::run(shared.clone(), project_file, output, &blender).await {
+            Ok(handle) => {
+                let handle = Arc::new(handle);
+                if let Err(e) = started.send((id, handle.clone())).await {
+                    eprintln!("Fail to keep render handle for job {id}! {e:?}");
+                }
+
+                // handle.recv() blocks, so read it on a thread of its own. Otherwise it ties up a runtime thread for the whole render,
+                // and on a small machine the select! loop in run() never gets to the stop command.
+                let (status_sender, mut statuses) = mpsc::unbounded_channel::<Status>();
+                let reader = handle.clone();
+                std::thread::spawn(move || {
+                    // the handle keeps a sender of its own, so recv() won't end by itself after the last status.
+                    while let Ok(status) = reader.recv() {
+                        let exit = matches!(status, Status::Exit);
+                        if status_sender.send(status).is_err() || exit {
+                            break;
+                        }
+                    }
+                });
+
+                // forward blender status to the network. We already run apart from the select! loop, so stop command from the host still get through.
+                // the frame blender is working on, and the last error we saw while rendering it.
+                let mut rendering: Option<Frame> = None;
+                let mut last_error: Option<String> = None;
+                let mut last_completed: Option<Frame> = None;
+
+                while let Some(status) = statuses.recv().await {
                     match status {
                         Status::Idle => client.send_status("[Idle]".to_owned()).await,
-                        Status::Running { status } => {
-                            client.send_status(format!("[Running] {status}")).await
+                        Status::Running { progress } => {
+                            // blender still report the save time after the frame completed.
+                            if last_completed != Some(progress.frame) {
+                                rendering = Some(progress.frame);
+                            }
+                            client.send_status(format!("[Running] {progress}")).await
                         }
                         Status::Log { status } => {
                             client.send_status(format!("[Log] {status}")).await
@@ -145,51 +357,180 @@ impl CliApp {
                             client.send_status(format!("[Warning] {message}")).await
                         }
                         Status::Error(blender_error) => {
+                            last_error = Some(blender_error.to_string());
                             client.send_status(format!("[ERR] {blender_error:?}")).await
                         }
-                        Status::Completed { frame, result, .. } => {
+                        Status::Completed {
+                            frame,
+                            result,
+                            passes,
+                        } => {
+                            // the frame made it through, whatever error blender printed wasn't fatal.
+                            rendering = None;
+                            last_error = None;
+                            last_completed = Some(frame);
+
                             // Use PathBuf as this helps enforce type intention of using OsString
                             // Why don't I create it like a directory instead? =
-                            let file_name = result.file_name().unwrap().to_string_lossy();
-                            let file_name = format!("/{}/{}", id, file_name);
+                            let shared_name = |path: &Path| {
+                                let file_name = path.file_name().unwrap().to_string_lossy();
+                                format!("/{}/{}", output_dir, file_name)
+                            };
+                            let file_name = shared_name(&result);
+
+                            // the passes sit next to the image, so the host keeps them together.
+                            let mut pass_names = Vec::with_capacity(passes.len());
+                            for pass in passes {
+                                let pass_name = shared_name(&pass);
+                                client.start_providing(pass_name.clone(), pass).await;
+                                pass_names.push(pass_name);
+                            }
+
                             let event = JobEvent::ImageCompleted {
                                 job_id: id,
                                 frame,
                                 file_name: file_name.clone(),
+                                passes: pass_names,
+                                region: region.clone(),
                             };
                             client.start_providing(file_name, result).await;
-                            client.send_job_message(request_id, event).await;
+                            if let Err(e) = client.send_job_message(request_id, event).await {
+                                eprintln!("Fail to notify host about frame {frame}! {e:?}");
+                            }
                         }
                         Status::Exit => {
-                            client
-                                .send_job_message(request_id, JobEvent::JobComplete)
-                                .await;
+                            // a cancelled render did not complete the job.
+                            if handle.is_cancelled() {
+                                client.send_status(format!("[Cancelled] Job {id}")).await;
+                                break;
+                            }
+
+                            // we may have given away the tail end of the task since we started.
+                            let (end, step) = shared
+                                .read()
+                                .map(|t| (t.range.end, t.step))
+                                .unwrap_or((first_frame, 1));
+
+                            // the frame blender was on when it stopped. Without any progress, it's the one after the last frame it finished.
+                            // if blender died before rendering anything, blame the first frame of the task.
+                            let failed = rendering
+                                .or(match last_completed {
+                                    Some(frame) => Some(frame + step),
+                                    None => Some(first_frame),
+                                })
+                                .filter(|frame| *frame < end);
+
+                            // blender stopping with frames left is a failure, even if it didn't say why.
+                            let event = match failed {
+                                Some(frame) => {
+                                    let error = last_error.unwrap_or_else(|| {
+                                        "Blender stopped before rendering every frame of the task!".to_owned()
+                                    });
+                                    let remaining = frame + step..end;
+                                    JobEvent::FrameFailed {
+                                        task_id: task.id,
+                                        job_id: id,
+                                        frame,
+                                        error,
+                                        remaining: (!remaining.is_empty()).then_some(remaining),
+                                    }
+                                }
+                                None => JobEvent::JobComplete { task_id: task.id },
+                            };
+                            if let Err(e) = client.send_job_message(request_id, event).await {
+                                eprintln!("Fail to notify host about job {id}! {e:?}");
+                            }
                             break;
                         }
                     };
                 }
-            },
+
+                if let Err(e) = finished.send(task).await {
+                    eprintln!("Fail to mark task as finished! {e:?}");
+                }
+            }
             Err(e) => {
-                let err = JobError::TaskError(e);
-                client
-                    .send_job_message(request_id, JobEvent::Error(err))
-                    .await;
+                Self::abandon_task(&mut client, task, JobError::TaskError(e), &finished).await
             }
         };
     }
 
+    // We couldn't start the task. Tell the host why, so it can hand the task out again, and move on to the next one.
+    async fn abandon_task(
+        client: &mut NetworkController,
+        task: Task,
+        error: JobError,
+        finished: &Sender<Task>,
+    ) {
+        let event = JobEvent::Error {
+            task_id: task.id,
+            error,
+        };
+        if let Err(e) = client.send_job_message(task.get_peer_id(), event).await {
+            eprintln!("Fail to notify host about job {}! {e:?}", task.job_id);
+        }
+        if let Err(e) = finished.send(task).await {
+            eprintln!("Fail to mark task as finished! {e:?}");
+        }
+    }
+
+    // Ask the latest blender we have which devices it can render with.
+    // Older builds may lack a backend the newer ones have, but it's the hardware that matters most.
+    // TODO: probe again once the first blender is downloaded, until then we only offer the CPU.
+    async fn probe_devices(&mut self) -> Vec<Device> {
+        let blender = self
+            .manager
+            .lock()
+            .ok()
+            .and_then(|mut manager| manager.latest_local_avail());
+        let Some(blender) = blender else {
+            return vec![Device::CPU];
+        };
+        match tokio::task::spawn_blocking(move || blender.devices()).await {
+            Ok(Ok(devices)) => devices,
+            result => {
+                eprintln!("Fail to probe render devices! {result:?}");
+                vec![Device::CPU]
+            }
+        }
+    }
+
+    // Kill blender if we're currently rendering anything for this job.
+    fn stop_render(&mut self, job_id: &Uuid) {
+        if let Some(handle) = self.render_handles.remove(job_id) {
+            if let Err(e) = handle.cancel() {
+                eprintln!("Fail to stop render for job {job_id}! {e:?}");
+            }
+        }
+    }
+
     async fn handle_message(&mut self, client: &mut NetworkController, event: NetEvent) {
         match event {
-            NetEvent::OnConnected(peer_id) => client.share_computer_info(peer_id).await,
+            NetEvent::OnConnected(peer_id) => {
+                client
+                    .share_computer_info(peer_id, self.devices.clone())
+                    .await
+            }
             NetEvent::NodeDiscovered(..) => {}  // Ignored
             NetEvent::NodeDisconnected(_) => {} // ignored
             NetEvent::JobUpdate(peer_id, job_event) => match job_event {
                 // on render task received, we should store this in the database.
-                JobEvent::Render(mut task) => self.render_task(peer_id, client, &mut task).await,
+                JobEvent::Render(task) => self.queue_task(client, task).await,
+                JobEvent::SplitTask { task_id } => self.split_task(client, peer_id, task_id).await,
                 JobEvent::ImageCompleted { .. } => {} // ignored since we do not want to capture image?
-                // For future impl. we can take advantage about how we can allieve existing job load. E.g. if I'm still rendering 50%, try to send this node the remaining parts?
-                JobEvent::JobComplete => {} // Ignored, we're treated as a client node, waiting for new job request.
+                JobEvent::JobComplete { .. } => {} // Ignored, we're treated as a client node, waiting for new job request.
                 JobEvent::Remove(id) => {
+                    // blender may not have started yet, we're still fetching files for it.
+                    if let Some(task) = &self.active_task {
+                        if task
+                            .read()
+                            .map(|task| task.job_id == id)
+                            .unwrap_or_default()
+                        {
+                            self.active_removed = true;
+                        }
+                    }
+                    self.stop_render(&id);
                     let mut db = self.task_store.write().await;
                     let _ = db.delete_job_task(id).await;
                     // let mut db = self.job_store.write().await;
@@ -201,14 +542,9 @@ impl CliApp {
                 }
                 _ => println!("Unhandle Job Event: {job_event:?}"),
             },
-            // maybe move this inside Network code? Seems repeative in both cli and Tauri side of application here.
+            // peer wants part of a file we provide, the network code reads it from disk.
             NetEvent::InboundRequest { request, channel } => {
-                if let Some(path) = client.providing_files.get(&request) {
-                    println!("Sending file {path:?}");
-                    client
-                        .respond_file(std::fs::read(path).unwrap(), channel)
-                        .await;
-                }
+                client.respond_file(request, channel).await
             }
             _ => println!("[CLI] Unhandled event from network: {event:?}"),
         }
@@ -228,14 +564,25 @@ impl BlendFarm for CliApp {
         // let system_info = format!("blendfarm/{}{}", consts::OS, &system.processor.brand);
         // client.subscribe_to_topic(system_info).await;
         client.subscribe_to_topic(JOB.to_string()).await;
+        self.devices = self.probe_devices().await;
+        let devices = self.devices.iter().map(ToString::to_string);
+        let devices = devices.collect::<Vec<String>>().join(", ");
+        let status = format!("Render devices: {devices}");
+        client.send_status(status).await;
+
+        // render task let us know when blender started, and when they're done through these channels.
+        let (started, mut on_started) = mpsc::channel::<RenderStarted>(8);
+        let (finished, mut on_finished) = mpsc::channel::<Task>(8);
+        let mut poll = interval(POLL_INTERVAL);
 
-        // let current_job: Option<Job> = None;
         loop {
             select! {
                 // here we can insert job_db here to receive event invocation from Tauri_app
                 Some(event) = event_receiver.recv() => self.handle_message(&mut client, event).await,
-                // how do I poll database here?
-                // Some(task) = db.poll_task().await => self.handle_poll(&)
+                Some((job_id, handle)) = on_started.recv() => self.render_started(job_id, handle),
+                Some(task) = on_finished.recv() => self.finish_task(&mut client, task).await,
+                // only pick up the next task once we're done with the current one.
+                _ = poll.tick(), if self.active_task.is_none() => self.next_task(&mut client, started.clone(), finished.clone()).await,
 
                 // how do I poll the machine specs in certain intervals?
             }
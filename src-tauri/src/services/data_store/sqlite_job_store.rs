@@ -1,8 +1,15 @@
-use std::{path::PathBuf, str::FromStr};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 use crate::{
     domains::job_store::{JobError, JobStore},
-    models::job::Job,
+    models::{
+        job::{Frame, Job},
+        retry_policy::{FrameFailure, RetryPolicy},
+    },
 };
 use blender::models::mode::Mode;
 use semver::Version;
@@ -17,6 +24,39 @@ impl SqliteJobStore {
     pub fn new(conn: SqlitePool) -> Self {
         Self { conn }
     }
+
+    async fn fetch_renders(&self, job_id: &Uuid) -> Result<HashMap<Frame, PathBuf>, JobError> {
+        let sql = "SELECT frame, render_path FROM renders WHERE jobs_id=$1";
+        sqlx::query_as::<_, RenderDb>(sql)
+            .bind(job_id.to_string())
+            .fetch_all(&self.conn)
+            .await
+            .map(|records| {
+                records
+                    .into_iter()
+                    .map(|r| (r.frame as Frame, PathBuf::from(r.render_path)))
+                    .collect()
+            })
+            .map_err(|e| JobError::DatabaseError(e.to_string()))
+    }
+
+    async fn fetch_passes(&self, job_id: &Uuid) -> Result<HashMap<Frame, Vec<PathBuf>>, JobError> {
+        let sql = "SELECT frame, render_path FROM render_passes WHERE jobs_id=$1 ORDER BY frame, render_path";
+        let records = sqlx::query_as::<_, RenderDb>(sql)
+            .bind(job_id.to_string())
+            .fetch_all(&self.conn)
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        let mut passes: HashMap<Frame, Vec<PathBuf>> = HashMap::new();
+        for r in records {
+            passes
+                .entry(r.frame as Frame)
+                .or_default()
+                .push(PathBuf::from(r.render_path));
+        }
+        Ok(passes)
+    }
 }
 
 #[derive(FromRow)]
@@ -24,8 +64,72 @@ struct JobDb {
     id: String,
     mode: String,
     project_file: String,
+    project_hash: String,
+    // json encoded Package
+    package: String,
+    // json encoded RenderOverride
+    overrides: String,
     blender_version: String,
     output_path: String,
+    max_attempts: i64,
+    retry_different_worker: bool,
+    priority: i64,
+    stopped: bool,
+}
+
+impl JobDb {
+    fn into_job(self, renders: HashMap<Frame, PathBuf>) -> Job {
+        let id = Uuid::parse_str(&self.id).unwrap();
+        let mode: Mode = serde_json::from_str(&self.mode).unwrap();
+        let project = PathBuf::from(self.project_file);
+        let version = Version::from_str(&self.blender_version).unwrap();
+        let output = PathBuf::from(self.output_path);
+        let policy = RetryPolicy::new(self.max_attempts as u32, self.retry_different_worker);
+        let mut job = Job::new(
+            id,
+            mode,
+            project,
+            version,
+            output,
+            policy,
+            self.priority as i32,
+            renders,
+        );
+        job.project_hash = self.project_hash;
+        job.package = serde_json::from_str(&self.package).unwrap_or_default();
+        job.overrides = serde_json::from_str(&self.overrides).unwrap_or_default();
+        job.stopped = self.stopped;
+        job
+    }
+}
+
+#[derive(FromRow)]
+struct RenderDb {
+    frame: i64,
+    render_path: String,
+}
+
+#[derive(FromRow)]
+struct FailureDb {
+    job_id: String,
+    frame: i64,
+    attempts: i64,
+    error: String,
+    peers: String,
+    permanent: bool,
+}
+
+impl FailureDb {
+    fn into_failure(self) -> FrameFailure {
+        FrameFailure {
+            job_id: Uuid::parse_str(&self.job_id).unwrap(),
+            frame: self.frame as Frame,
+            attempts: self.attempts as u32,
+            error: self.error,
+            peers: serde_json::from_str(&self.peers).unwrap_or_default(),
+            permanent: self.permanent,
+        }
+    }
 }
 
 #[async_trait::async_trait]
@@ -36,18 +140,30 @@ impl JobStore for SqliteJobStore {
         let project_file = job.project_file.to_str().unwrap().to_owned();
         let blender_version = job.blender_version.to_string();
         let output = job.output.to_str().unwrap().to_owned();
+        let max_attempts = job.retry_policy.max_attempts as i64;
+        let retry_different_worker = job.retry_policy.retry_on_different_worker;
+        let priority = job.priority as i64;
+        let package = serde_json::to_string(&job.package).unwrap();
+        let overrides = serde_json::to_string(&job.overrides).unwrap();
 
         sqlx::query(
             r"
-                INSERT INTO jobs (id, mode, project_file, blender_version, output_path)
-                VALUES($1, $2, $3, $4, $5);
+                INSERT INTO jobs (id, mode, project_file, project_hash, package, overrides, blender_version, output_path, max_attempts, retry_different_worker, priority, stopped)
+                VALUES($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12);
             ",
         )
         .bind(id)
         .bind(mode)
         .bind(project_file)
+        .bind(job.project_hash)
+        .bind(package)
+        .bind(overrides)
         .bind(blender_version)
         .bind(output)
+        .bind(max_attempts)
+        .bind(retry_different_worker)
+        .bind(priority)
+        .bind(job.stopped)
         .execute(&self.conn)
         .await
         .map_err(|e| JobError::DatabaseError(e.to_string()))?;
@@ -55,43 +171,67 @@ impl JobStore for SqliteJobStore {
     }
 
     async fn get_job(&self, job_id: &Uuid) -> Result<Job, JobError> {
-        let sql =
-            "SELECT id, mode, project_file, blender_version, output_path FROM Jobs WHERE id=$1";
+        let sql = r"SELECT id, mode, project_file, project_hash, package, overrides, blender_version, output_path, max_attempts, retry_different_worker, priority, stopped
+            FROM Jobs WHERE id=$1";
         match sqlx::query_as::<_, JobDb>(sql)
             .bind(job_id.to_string())
             .fetch_one(&self.conn)
             .await
         {
             Ok(r) => {
-                let id = Uuid::parse_str(&r.id).unwrap();
-                let mode: Mode = serde_json::from_str(&r.mode).unwrap();
-                let project = PathBuf::from(r.project_file);
-                let version = Version::from_str(&r.blender_version).unwrap();
-                let output = PathBuf::from(r.output_path);
-                let job = Job::new(id, mode, project, version, output, Default::default());
+                let renders = self.fetch_renders(job_id).await?;
+                let mut job = r.into_job(renders);
+                job.passes = self.fetch_passes(job_id).await?;
                 Ok(job)
             }
             Err(e) => Err(JobError::DatabaseError(e.to_string())),
         }
     }
 
-    async fn update_job(&mut self, _job: Job) -> Result<(), JobError> {
-        todo!("Update job to database");
+    // renders and failures have their own tables, only the job record itself is updated here.
+    async fn update_job(&mut self, job: Job) -> Result<(), JobError> {
+        let mode = serde_json::to_string(&job.mode).unwrap();
+        let project_file = job.project_file.to_str().unwrap().to_owned();
+        let output = job.output.to_str().unwrap().to_owned();
+        let package = serde_json::to_string(&job.package).unwrap();
+        let overrides = serde_json::to_string(&job.overrides).unwrap();
+
+        sqlx::query(
+            r"
+                UPDATE jobs SET mode=$2, project_file=$3, project_hash=$4, package=$5, overrides=$6, blender_version=$7,
+                    output_path=$8, max_attempts=$9, retry_different_worker=$10, priority=$11, stopped=$12
+                WHERE id=$1;
+            ",
+        )
+        .bind(job.id.to_string())
+        .bind(mode)
+        .bind(project_file)
+        .bind(job.project_hash)
+        .bind(package)
+        .bind(overrides)
+        .bind(job.blender_version.to_string())
+        .bind(output)
+        .bind(job.retry_policy.max_attempts as i64)
+        .bind(job.retry_policy.retry_on_different_worker)
+        .bind(job.priority as i64)
+        .bind(job.stopped)
+        .execute(&self.conn)
+        .await
+        .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+        Ok(())
     }
 
     async fn list_all(&self) -> Result<Vec<Job>, JobError> {
-        let sql = r"SELECT id, mode, project_file, blender_version, output_path FROM jobs";
+        let sql = r"SELECT id, mode, project_file, project_hash, package, overrides, blender_version, output_path, max_attempts, retry_different_worker, priority, stopped FROM jobs";
         let mut data: Vec<Job> = Vec::new();
         let results = sqlx::query_as::<_, JobDb>(sql).fetch_all(&self.conn).await;
         match results {
             Ok(records) => {
                 for r in records {
                     let id = Uuid::parse_str(&r.id).unwrap();
-                    let mode: Mode = serde_json::from_str(&r.mode).unwrap();
-                    let project = PathBuf::from(r.project_file);
-                    let version = Version::from_str(&r.blender_version).unwrap();
-                    let output = PathBuf::from(r.output_path);
-                    let job = Job::new(id, mode, project, version, output, Default::default());
+                    let renders = self.fetch_renders(&id).await?;
+                    let mut job = r.into_job(renders);
+                    job.passes = self.fetch_passes(&id).await?;
                     data.push(job);
                 }
             }
@@ -108,6 +248,126 @@ impl JobStore for SqliteJobStore {
         {
             eprintln!("Fail to delete job! {e:?}");
         }
+
+        if let Err(e) = sqlx::query("DELETE FROM failed_frames WHERE job_id = $1")
+            .bind(id.to_string())
+            .execute(&self.conn)
+            .await
+        {
+            eprintln!("Fail to delete failed frames! {e:?}");
+        }
+
+        if let Err(e) = sqlx::query("DELETE FROM renders WHERE jobs_id = $1")
+            .bind(id.to_string())
+            .execute(&self.conn)
+            .await
+        {
+            eprintln!("Fail to delete render records! {e:?}");
+        }
+
+        if let Err(e) = sqlx::query("DELETE FROM render_passes WHERE jobs_id = $1")
+            .bind(id.to_string())
+            .execute(&self.conn)
+            .await
+        {
+            eprintln!("Fail to delete render pass records! {e:?}");
+        }
+        Ok(())
+    }
+
+    async fn get_failure(
+        &self,
+        job_id: &Uuid,
+        frame: Frame,
+    ) -> Result<Option<FrameFailure>, JobError> {
+        let sql = r"SELECT job_id, frame, attempts, error, peers, permanent FROM failed_frames WHERE job_id=$1 AND frame=$2";
+        sqlx::query_as::<_, FailureDb>(sql)
+            .bind(job_id.to_string())
+            .bind(frame)
+            .fetch_optional(&self.conn)
+            .await
+            .map(|r| r.map(FailureDb::into_failure))
+            .map_err(|e| JobError::DatabaseError(e.to_string()))
+    }
+
+    async fn save_failure(&mut self, failure: FrameFailure) -> Result<(), JobError> {
+        let peers = serde_json::to_string(&failure.peers).unwrap();
+        sqlx::query(
+            r"
+                INSERT INTO failed_frames (job_id, frame, attempts, error, peers, permanent)
+                VALUES($1, $2, $3, $4, $5, $6)
+                ON CONFLICT(job_id, frame) DO UPDATE SET
+                    attempts = excluded.attempts,
+                    error = excluded.error,
+                    peers = excluded.peers,
+                    permanent = excluded.permanent;
+            ",
+        )
+        .bind(failure.job_id.to_string())
+        .bind(failure.frame)
+        .bind(failure.attempts as i64)
+        .bind(failure.error)
+        .bind(peers)
+        .bind(failure.permanent)
+        .execute(&self.conn)
+        .await
+        .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn list_failures(&self, job_id: &Uuid) -> Result<Vec<FrameFailure>, JobError> {
+        let sql = r"SELECT job_id, frame, attempts, error, peers, permanent FROM failed_frames WHERE job_id=$1 ORDER BY frame";
+        sqlx::query_as::<_, FailureDb>(sql)
+            .bind(job_id.to_string())
+            .fetch_all(&self.conn)
+            .await
+            .map(|records| records.into_iter().map(FailureDb::into_failure).collect())
+            .map_err(|e| JobError::DatabaseError(e.to_string()))
+    }
+
+    async fn add_render(
+        &mut self,
+        job_id: &Uuid,
+        frame: Frame,
+        path: &Path,
+    ) -> Result<(), JobError> {
+        let id = format!("{job_id}_{frame}");
+        // a frame may be rendered again (e.g. retried), keep the latest copy.
+        sqlx::query(
+            r"
+                INSERT OR REPLACE INTO renders (id, jobs_id, frame, render_path)
+                VALUES($1, $2, $3, $4);
+            ",
+        )
+        .bind(id)
+        .bind(job_id.to_string())
+        .bind(frame)
+        .bind(path.to_str().unwrap().to_owned())
+        .execute(&self.conn)
+        .await
+        .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn add_render_pass(
+        &mut self,
+        job_id: &Uuid,
+        frame: Frame,
+        path: &Path,
+    ) -> Result<(), JobError> {
+        // the same file comes back when the frame is rendered again, keep a single record of it.
+        sqlx::query(
+            r"
+                INSERT OR REPLACE INTO render_passes (render_path, jobs_id, frame)
+                VALUES($1, $2, $3);
+            ",
+        )
+        .bind(path.to_str().unwrap().to_owned())
+        .bind(job_id.to_string())
+        .bind(frame)
+        .execute(&self.conn)
+        .await
+        .map_err(|e| JobError::DatabaseError(e.to_string()))?;
         Ok(())
     }
 }
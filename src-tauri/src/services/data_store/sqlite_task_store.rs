@@ -1,11 +1,17 @@
-use sqlx::SqlitePool;
-use uuid::Uuid;
+use std::{path::PathBuf, str::FromStr};
 
-use crate::{domains::task_store::{TaskError, TaskStore}, models::task::Task};
+use libp2p::PeerId;
+use semver::Version;
+use sqlx::{FromRow, SqlitePool};
+use uuid::Uuid;
 
+use crate::{
+    domains::task_store::{TaskError, TaskStore},
+    models::task::Task,
+};
 
 pub struct SqliteTaskStore {
-    conn: SqlitePool
+    conn: SqlitePool,
 }
 
 impl SqliteTaskStore {
@@ -14,6 +20,59 @@ impl SqliteTaskStore {
     }
 }
 
+#[derive(FromRow)]
+struct TaskDb {
+    id: String,
+    peer_id: String,
+    job_id: String,
+    blender_version: String,
+    blend_file_name: String,
+    blend_file_hash: String,
+    // json encoded list of PackageFile
+    dependencies: String,
+    // json encoded RenderOverride
+    overrides: String,
+    start_frame: i64,
+    end_frame: i64,
+    step: i64,
+    // json encoded Window, null renders the whole frame.
+    region: Option<String>,
+}
+
+impl TaskDb {
+    fn into_task(self) -> Result<Task, TaskError> {
+        let parse_err = |e: String| TaskError::DatabaseError(format!("Malformed task record! {e}"));
+        let peer_id = PeerId::from_str(&self.peer_id).map_err(|e| parse_err(e.to_string()))?;
+        let job_id = Uuid::parse_str(&self.job_id).map_err(|e| parse_err(e.to_string()))?;
+        let version =
+            Version::from_str(&self.blender_version).map_err(|e| parse_err(e.to_string()))?;
+        let range = self.start_frame as i32..self.end_frame as i32;
+
+        let mut task = Task::new(
+            peer_id,
+            job_id,
+            PathBuf::from(self.blend_file_name),
+            version,
+            range,
+            self.step as i32,
+        );
+        // keep the same id so we can delete the record once we're done.
+        task.id = Uuid::parse_str(&self.id).map_err(|e| parse_err(e.to_string()))?;
+        task.blend_file_hash = self.blend_file_hash;
+        task.dependencies =
+            serde_json::from_str(&self.dependencies).map_err(|e| parse_err(e.to_string()))?;
+        task.overrides =
+            serde_json::from_str(&self.overrides).map_err(|e| parse_err(e.to_string()))?;
+        task.region = match self.region {
+            Some(region) => {
+                Some(serde_json::from_str(&region).map_err(|e| parse_err(e.to_string()))?)
+            }
+            None => None,
+        };
+        Ok(task)
+    }
+}
+
 #[async_trait::async_trait]
 impl TaskStore for SqliteTaskStore {
     async fn add_task(&mut self, task: Task) -> Result<(), TaskError> {
@@ -22,35 +81,132 @@ impl TaskStore for SqliteTaskStore {
         let job_id = task.job_id.to_string();
         let blend_file_name = task.blend_file_name.to_str().unwrap().to_string();
         let blender_version = task.blender_version.to_string();
-        let range = serde_json::to_string(&task.range).unwrap();
-        let _ = sqlx::query(r"INSERT INTO tasks(id, peer_id, job_id, blend_file_name, blender_version, range) 
-            VALUES($1, $2, $3, $4, $5, $6)")
+        let region = task
+            .region
+            .as_ref()
+            .map(|region| serde_json::to_string(region).unwrap());
+        let dependencies = serde_json::to_string(&task.dependencies).unwrap();
+        let overrides = serde_json::to_string(&task.overrides).unwrap();
+        sqlx::query(
+            r"
+                INSERT INTO tasks(id, peer_id, job_id, blend_file_name, blend_file_hash, dependencies, overrides, blender_version, start_frame, end_frame, step, region)
+                VALUES($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+            ",
+        )
         .bind(id)
         .bind(peer_id)
         .bind(job_id)
         .bind(blend_file_name)
+        .bind(task.blend_file_hash)
+        .bind(dependencies)
+        .bind(overrides)
         .bind(blender_version)
-        .bind(range)
-        .execute(&self.conn);
+        .bind(task.range.start)
+        .bind(task.range.end)
+        .bind(task.step)
+        .bind(region)
+        .execute(&self.conn)
+        .await
+        .map_err(|e| TaskError::DatabaseError(e.to_string()))?;
         Ok(())
     }
 
-    async fn poll_task(&mut self) -> Result<Task, TaskError> {
-        todo!("poll pending task?");
+    async fn poll_task(&mut self) -> Result<Option<Task>, TaskError> {
+        // rowid follows insertion order, so this gives us the oldest task first.
+        let sql = r"SELECT id, peer_id, job_id, blender_version, blend_file_name, blend_file_hash, dependencies, overrides, start_frame, end_frame, step, region
+            FROM tasks ORDER BY rowid LIMIT 1";
+        let record = sqlx::query_as::<_, TaskDb>(sql)
+            .fetch_optional(&self.conn)
+            .await
+            .map_err(|e| TaskError::DatabaseError(e.to_string()))?;
+
+        record.map(TaskDb::into_task).transpose()
     }
-    
+
     async fn delete_task(&mut self, task: Task) -> Result<(), TaskError> {
         let id = task.id.to_string();
-        let _ = sqlx::query(r"DELETE * FROM tasks WHERE id = $1")
+        sqlx::query(r"DELETE FROM tasks WHERE id = $1")
             .bind(id)
-            .execute(&self.conn).await;
+            .execute(&self.conn)
+            .await
+            .map_err(|e| TaskError::DatabaseError(e.to_string()))?;
         Ok(())
     }
-    
+
     async fn delete_job_task(&mut self, job_id: Uuid) -> Result<(), TaskError> {
-        let _ = sqlx::query(r"DELETE * FROM tasks WHERE job_id = $1")
+        sqlx::query(r"DELETE FROM tasks WHERE job_id = $1")
             .bind(job_id.to_string())
-            .execute(&self.conn).await;
+            .execute(&self.conn)
+            .await
+            .map_err(|e| TaskError::DatabaseError(e.to_string()))?;
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blender::models::blender_render_setting::Window;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    // every connection to sqlite::memory: opens a database of its own, so keep it to one.
+    async fn store() -> SqliteTaskStore {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!().run(&pool).await.unwrap();
+        SqliteTaskStore::new(pool)
+    }
+
+    fn task(job_id: Uuid, range: std::ops::Range<i32>, step: i32) -> Task {
+        Task::new(
+            PeerId::random(),
+            job_id,
+            PathBuf::from("test.blend"),
+            Version::new(4, 2, 0),
+            range,
+            step,
+        )
+    }
+
+    #[tokio::test]
+    async fn should_poll_tasks_in_insertion_order() {
+        let mut store = store().await;
+        assert!(store.poll_task().await.unwrap().is_none());
+
+        let job = Uuid::new_v4();
+        let (first, second) = (task(job, 1..10, 1), task(job, 10..20, 1));
+        store.add_task(first.clone()).await.unwrap();
+        store.add_task(second.clone()).await.unwrap();
+
+        // polling leaves the task in the queue until it's deleted.
+        assert_eq!(store.poll_task().await.unwrap().unwrap().id, first.id);
+        assert_eq!(store.poll_task().await.unwrap().unwrap().id, first.id);
+
+        store.delete_task(first).await.unwrap();
+        assert_eq!(store.poll_task().await.unwrap().unwrap().id, second.id);
+        store.delete_task(second).await.unwrap();
+        assert!(store.poll_task().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn should_keep_frames_and_region() {
+        let mut store = store().await;
+        let mut region_task = task(Uuid::new_v4(), 5..25, 5);
+        region_task.region = Some(Window {
+            x: 0.25..0.5,
+            y: 0.0..0.5,
+        });
+        store.add_task(region_task.clone()).await.unwrap();
+
+        let polled = store.poll_task().await.unwrap().unwrap();
+        assert_eq!(polled.id, region_task.id);
+        assert_eq!(polled.job_id, region_task.job_id);
+        assert_eq!(polled.get_peer_id(), region_task.get_peer_id());
+        assert_eq!(polled.range, 5..25);
+        assert_eq!(polled.step, 5);
+        assert_eq!(polled.region, region_task.region);
+    }
+}
@@ -1,4 +1,5 @@
 pub mod blend_farm;
 pub mod cli_app;
 pub mod data_store;
+pub mod scheduler;
 pub mod tauri_app;
@@ -0,0 +1,662 @@
+/*
+    Developer blog:
+    - The scheduler only makes decisions, it does not talk to the network. TauriApp sends out whatever assignments we hand back,
+        and tells us when the delivery failed. That way we can verify the decisions without spinning up libp2p.
+    - Each worker renders one task at a time. A worker is busy once we hand it a task, and idle again when it reports back (complete, failed or error).
+    - Higher priority goes first. Tasks with the same priority are handed out in the order they came in.
+    - When a worker goes offline, whatever it was working on goes back to the front of its priority line.
+    - Work stealing: when a worker runs out of work and nothing is queued, we ask the worker with the most frames left to give up the tail end of its task.
+        The busy worker has the final say on what it gives away, since it knows exactly where it's at. We only keep a rough idea from the completed frames.
+    - Workers tell us which render devices they have. A job that asks for a device only goes to workers that have it, otherwise we pick the best one each worker has.
+*/
+use crate::models::task::Task;
+use blender::models::device::Device;
+use libp2p::PeerId;
+use std::{collections::HashMap, ops::Range, time::Instant};
+use uuid::Uuid;
+
+// Don't bother asking a worker to share unless it has at least this many frames left.
+// Workers hand over half of what's left and refuse to split off 2 frames or less, see Task::fetch_end_frames()
+const MIN_SPLIT_FRAMES: usize = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkerState {
+    Idle,
+    /// Rendering the task with this id
+    Busy(Uuid),
+    Offline,
+}
+
+#[derive(Debug)]
+struct WorkerEntry {
+    state: WorkerState,
+    // when this worker last received a task, used to spread work around evenly.
+    last_assigned: u64,
+    // when we last heard the worker got anywhere with its task. Used to measure how fast it renders.
+    last_progress: Option<Instant>,
+    // average seconds it takes this worker to render a frame.
+    secs_per_frame: Option<f32>,
+    // render devices the worker has. Empty until it tells us, which leaves it with the CPU.
+    devices: Vec<Device>,
+}
+
+impl WorkerEntry {
+    fn can_render(&self, task: &Task) -> bool {
+        match task.overrides.device {
+            Some(device) => device.is_available(&self.devices),
+            None => true,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct QueuedTask {
+    task: Task,
+    priority: i32,
+    // insertion order, keeps tasks with the same priority first in first out.
+    order: u64,
+    // workers we'd rather not give this task to (e.g. they failed this frame before)
+    avoid: Vec<PeerId>,
+    // we asked the worker to share this task, either waiting for an answer or it had nothing left to give.
+    split_requested: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    workers: HashMap<PeerId, WorkerEntry>,
+    queue: Vec<QueuedTask>,
+    // tasks handed out to workers, by task id.
+    assigned: HashMap<Uuid, QueuedTask>,
+    counter: u64,
+}
+
+impl Scheduler {
+    fn next_counter(&mut self) -> u64 {
+        self.counter += 1;
+        self.counter
+    }
+
+    /// Worker joined the network, or came back online.
+    pub fn add_worker(&mut self, peer: PeerId) {
+        let entry = self.workers.entry(peer).or_insert(WorkerEntry {
+            state: WorkerState::Offline,
+            last_assigned: 0,
+            last_progress: None,
+            secs_per_frame: None,
+            devices: Vec::new(),
+        });
+        if entry.state == WorkerState::Offline {
+            entry.state = WorkerState::Idle;
+        }
+    }
+
+    /// Worker told us which render devices it has.
+    pub fn set_devices(&mut self, peer: &PeerId, devices: Vec<Device>) {
+        if let Some(entry) = self.workers.get_mut(peer) {
+            entry.devices = devices;
+        }
+    }
+
+    /// Worker left the network. The task it was working on goes back into the queue.
+    pub fn remove_worker(&mut self, peer: &PeerId) {
+        self.release(peer);
+        if let Some(entry) = self.workers.get_mut(peer) {
+            entry.state = WorkerState::Offline;
+        }
+    }
+
+    /// Worker is still around, but couldn't take the task right now. The task goes back into the queue and the worker stays available.
+    pub fn release(&mut self, peer: &PeerId) {
+        if let Some(entry) = self.workers.get_mut(peer) {
+            if let WorkerState::Busy(task_id) = entry.state {
+                if let Some(queued) = self.assigned.remove(&task_id) {
+                    self.queue.push(queued);
+                }
+                entry.state = WorkerState::Idle;
+            }
+        }
+    }
+
+    pub fn worker_state(&self, peer: &PeerId) -> Option<WorkerState> {
+        self.workers.get(peer).map(|entry| entry.state)
+    }
+
+    /// Add task to the queue.
+    pub fn enqueue(&mut self, task: Task, priority: i32) {
+        self.enqueue_avoiding(task, priority, Vec::new());
+    }
+
+    /// Add task to the queue, preferring workers not listed in avoid.
+    /// If every online worker is on the list, the task goes to any of them. Better to try again than never render it.
+    pub fn enqueue_avoiding(&mut self, task: Task, priority: i32, avoid: Vec<PeerId>) {
+        let order = self.next_counter();
+        self.queue.push(QueuedTask {
+            task,
+            priority,
+            order,
+            avoid,
+            split_requested: false,
+        });
+    }
+
+    /// Worker reported back on the task, whoever rendered it is free to take the next one.
+    /// Returns the task, None if it's no longer handed out. A late report from a worker that already moved on leaves its current task alone.
+    pub fn finish(&mut self, task_id: &Uuid) -> Option<Task> {
+        let queued = self.assigned.remove(task_id)?;
+        for entry in self.workers.values_mut() {
+            if entry.state == WorkerState::Busy(*task_id) {
+                entry.state = WorkerState::Idle;
+            }
+        }
+        Some(queued.task)
+    }
+
+    /// We couldn't deliver the task to the worker. Put the task back and treat the worker as offline until we hear from it again.
+    pub fn reject(&mut self, peer: &PeerId) {
+        self.remove_worker(peer);
+    }
+
+    /// Forget about the job. Queued tasks are dropped and workers rendering it are free again.
+    pub fn remove_job(&mut self, job_id: &Uuid) {
+        self.queue.retain(|queued| queued.task.job_id != *job_id);
+        self.assigned
+            .retain(|_, queued| queued.task.job_id != *job_id);
+        for entry in self.workers.values_mut() {
+            if let WorkerState::Busy(task_id) = entry.state {
+                if !self.assigned.contains_key(&task_id) {
+                    entry.state = WorkerState::Idle;
+                }
+            }
+        }
+    }
+
+    /// Worker finished rendering the frame. Keep track of how far along the task is.
+    pub fn progress(&mut self, peer: &PeerId, job_id: &Uuid, frame: i32) {
+        let Some(entry) = self.workers.get_mut(peer) else {
+            return;
+        };
+        let WorkerState::Busy(task_id) = entry.state else {
+            return;
+        };
+
+        // the first frame includes fetching the project and starting blender, so it's a bit pessimistic. It evens out over time.
+        let now = Instant::now();
+        if let Some(last) = entry.last_progress {
+            let secs = now.duration_since(last).as_secs_f32();
+            entry.secs_per_frame = Some(match entry.secs_per_frame {
+                Some(average) => average * 0.7 + secs * 0.3,
+                None => secs,
+            });
+        }
+        entry.last_progress = Some(now);
+
+        if let Some(queued) = self.assigned.get_mut(&task_id) {
+            let step = queued.task.step;
+            let range = &mut queued.task.range;
+            if queued.task.job_id == *job_id && range.start <= frame {
+                range.start = (frame + step).min(range.end);
+            }
+        }
+    }
+
+    /// Measured seconds per frame of each online worker, None if the worker hasn't rendered anything yet.
+    pub fn speeds(&self) -> Vec<Option<f32>> {
+        self.workers
+            .values()
+            .filter(|entry| entry.state != WorkerState::Offline)
+            .map(|entry| entry.secs_per_frame)
+            .collect()
+    }
+
+    /// Pick the busy worker with the most frames left, so it can share with an idle worker.
+    /// Returns the worker and the task id to split. Nothing is returned if nobody is idle or there's not enough left to split.
+    pub fn split_candidate(&mut self) -> Option<(PeerId, Uuid)> {
+        if !self
+            .workers
+            .values()
+            .any(|entry| entry.state == WorkerState::Idle)
+        {
+            return None;
+        }
+
+        let (peer, task_id) = self
+            .workers
+            .iter()
+            .filter_map(|(peer, entry)| match entry.state {
+                WorkerState::Busy(task_id) => Some((*peer, task_id)),
+                _ => None,
+            })
+            .filter_map(|(peer, task_id)| {
+                let queued = self.assigned.get(&task_id)?;
+                let left = queued.task.frames_left();
+                (!queued.split_requested && left >= MIN_SPLIT_FRAMES)
+                    .then_some((left, peer, task_id))
+            })
+            .max()
+            .map(|(_, peer, task_id)| (peer, task_id))?;
+
+        if let Some(queued) = self.assigned.get_mut(&task_id) {
+            queued.split_requested = true;
+        }
+        Some((peer, task_id))
+    }
+
+    /// The split request never reached the worker. Let us ask again later.
+    pub fn split_failed(&mut self, task_id: &Uuid) {
+        if let Some(queued) = self.assigned.get_mut(task_id) {
+            queued.split_requested = false;
+        }
+    }
+
+    /// Worker answered our split request. The frames it gave away are queued up as a new task with the same priority.
+    pub fn split(&mut self, task_id: &Uuid, stolen: Option<Range<i32>>) {
+        let Some(queued) = self.assigned.get_mut(task_id) else {
+            return;
+        };
+        // None means the worker had nothing to share, leave split_requested on so we don't ask again.
+        let Some(stolen) = stolen else {
+            return;
+        };
+
+        queued.split_requested = false;
+        queued.task.range.end = queued.task.range.end.min(stolen.start);
+        let mut task = Task::new(
+            queued.task.get_peer_id(),
+            queued.task.job_id,
+            queued.task.blend_file_name.clone(),
+            queued.task.blender_version.clone(),
+            stolen,
+            queued.task.step,
+        );
+        task.blend_file_hash = queued.task.blend_file_hash.clone();
+        task.dependencies = queued.task.dependencies.clone();
+        task.overrides = queued.task.overrides.clone();
+        let priority = queued.priority;
+        self.enqueue(task, priority);
+    }
+
+    /// Number of tasks waiting for a worker.
+    pub fn pending(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Hand out queued tasks to idle workers. The returned assignments must be sent to the workers.
+    pub fn schedule(&mut self) -> Vec<(PeerId, Task)> {
+        // highest priority first, then oldest first.
+        self.queue
+            .sort_by(|a, b| b.priority.cmp(&a.priority).then(a.order.cmp(&b.order)));
+
+        let mut assignments = Vec::new();
+        let mut index = 0;
+        while index < self.queue.len() {
+            if !self
+                .workers
+                .values()
+                .any(|entry| entry.state == WorkerState::Idle)
+            {
+                break;
+            }
+
+            // only workers that have the device the task asks for.
+            let queued = &self.queue[index];
+            let mut idle = self
+                .workers
+                .iter()
+                .filter(|(_, entry)| {
+                    entry.state == WorkerState::Idle && entry.can_render(&queued.task)
+                })
+                .map(|(peer, entry)| (entry.last_assigned, *peer))
+                .collect::<Vec<(u64, PeerId)>>();
+            // worker that waited the longest goes first.
+            idle.sort();
+
+            let avoid = &queued.avoid;
+            let has_alternative = self.workers.iter().any(|(peer, entry)| {
+                entry.state != WorkerState::Offline
+                    && entry.can_render(&queued.task)
+                    && !avoid.contains(peer)
+            });
+
+            let peer = match idle.iter().find(|(_, peer)| !avoid.contains(peer)) {
+                Some((_, peer)) => Some(*peer),
+                // everyone online failed this task before, so take whoever is free.
+                None if !has_alternative => idle.first().map(|(_, peer)| *peer),
+                // wait for the worker we'd prefer to free up.
+                None => None,
+            };
+
+            let Some(peer) = peer else {
+                index += 1;
+                continue;
+            };
+
+            let queued = self.queue.remove(index);
+            let order = self.next_counter();
+            let mut task = queued.task.clone();
+            if let Some(entry) = self.workers.get_mut(&peer) {
+                entry.state = WorkerState::Busy(queued.task.id);
+                entry.last_assigned = order;
+                entry.last_progress = Some(Instant::now());
+                // the job left the device up to us. Only the copy we send out, the task may end up on another worker.
+                task.overrides
+                    .device
+                    .get_or_insert(Device::best(&entry.devices));
+            }
+            assignments.push((peer, task));
+            self.assigned.insert(queued.task.id, queued);
+        }
+        assignments
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use semver::Version;
+    use std::collections::HashSet;
+    use std::path::PathBuf;
+
+    // Pretend network: delivers everything the scheduler hands out, unless the peer is unreachable or too busy to take it.
+    #[derive(Default)]
+    struct FakeNetwork {
+        unreachable: HashSet<PeerId>,
+        busy: HashSet<PeerId>,
+    }
+
+    impl FakeNetwork {
+        // same loop TauriApp runs against the real network.
+        fn dispatch(&mut self, scheduler: &mut Scheduler) -> Vec<(PeerId, Task)> {
+            let mut sent = Vec::new();
+            loop {
+                let assignments = scheduler.schedule();
+                if assignments.is_empty() {
+                    break;
+                }
+                let mut retry_later = false;
+                for (peer, task) in assignments {
+                    if self.unreachable.contains(&peer) {
+                        scheduler.reject(&peer);
+                    } else if self.busy.contains(&peer) {
+                        scheduler.release(&peer);
+                        retry_later = true;
+                    } else {
+                        sent.push((peer, task));
+                    }
+                }
+                if retry_later {
+                    break;
+                }
+            }
+            sent
+        }
+    }
+
+    fn task(job_id: Uuid) -> Task {
+        Task::new(
+            PeerId::random(),
+            job_id,
+            PathBuf::from("test.blend"),
+            Version::new(4, 2, 0),
+            1..10,
+            1,
+        )
+    }
+
+    #[test]
+    fn should_only_assign_idle_workers() {
+        let mut scheduler = Scheduler::default();
+        let mut network = FakeNetwork::default();
+        let (a, b) = (PeerId::random(), PeerId::random());
+        scheduler.add_worker(a);
+        scheduler.add_worker(b);
+
+        let job = Uuid::new_v4();
+        let tasks = (0..3).map(|_| task(job)).collect::<Vec<Task>>();
+        for t in &tasks {
+            scheduler.enqueue(t.clone(), 0);
+        }
+
+        let sent = network.dispatch(&mut scheduler);
+        assert_eq!(sent.len(), 2);
+        assert_ne!(sent[0].0, sent[1].0);
+        assert_eq!(scheduler.pending(), 1);
+        assert!(network.dispatch(&mut scheduler).is_empty());
+
+        // a report for a task nobody has doesn't free anyone.
+        assert!(scheduler.finish(&Uuid::new_v4()).is_none());
+        assert!(network.dispatch(&mut scheduler).is_empty());
+
+        // first worker report back and pick up the last task.
+        let done = scheduler.finish(&sent[0].1.id).unwrap();
+        assert_eq!(done.id, sent[0].1.id);
+        assert_eq!(scheduler.worker_state(&sent[0].0), Some(WorkerState::Idle));
+        let sent = network.dispatch(&mut scheduler);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].1.id, tasks[2].id);
+        assert_eq!(scheduler.pending(), 0);
+    }
+
+    #[test]
+    fn should_honour_priority_then_order() {
+        let mut scheduler = Scheduler::default();
+        let mut network = FakeNetwork::default();
+        let worker = PeerId::random();
+        scheduler.add_worker(worker);
+
+        let (low, first, second) = (
+            task(Uuid::new_v4()),
+            task(Uuid::new_v4()),
+            task(Uuid::new_v4()),
+        );
+        scheduler.enqueue(low.clone(), 0);
+        scheduler.enqueue(first.clone(), 5);
+        scheduler.enqueue(second.clone(), 5);
+
+        let mut order = Vec::new();
+        for _ in 0..3 {
+            let sent = network.dispatch(&mut scheduler);
+            assert_eq!(sent.len(), 1);
+            order.push(sent[0].1.id);
+            scheduler.finish(&sent[0].1.id);
+        }
+        assert_eq!(order, vec![first.id, second.id, low.id]);
+    }
+
+    #[test]
+    fn should_assign_pending_task_when_worker_joins() {
+        let mut scheduler = Scheduler::default();
+        let mut network = FakeNetwork::default();
+        scheduler.enqueue(task(Uuid::new_v4()), 0);
+        assert!(network.dispatch(&mut scheduler).is_empty());
+
+        let worker = PeerId::random();
+        scheduler.add_worker(worker);
+        let sent = network.dispatch(&mut scheduler);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].0, worker);
+        assert_ne!(scheduler.worker_state(&worker), Some(WorkerState::Idle));
+    }
+
+    #[test]
+    fn should_rebalance_when_worker_leaves() {
+        let mut scheduler = Scheduler::default();
+        let mut network = FakeNetwork::default();
+        let (a, b) = (PeerId::random(), PeerId::random());
+        scheduler.add_worker(a);
+        let lost = task(Uuid::new_v4());
+        scheduler.enqueue(lost.clone(), 0);
+        assert_eq!(network.dispatch(&mut scheduler)[0].0, a);
+
+        scheduler.add_worker(b);
+        scheduler.remove_worker(&a);
+        assert_eq!(scheduler.worker_state(&a), Some(WorkerState::Offline));
+
+        let sent = network.dispatch(&mut scheduler);
+        assert_eq!(sent.len(), 1);
+        assert_eq!((sent[0].0, sent[0].1.id), (b, lost.id));
+    }
+
+    #[test]
+    fn should_only_send_tasks_to_workers_with_the_device() {
+        let mut scheduler = Scheduler::default();
+        let mut network = FakeNetwork::default();
+        let (cpu, gpu) = (PeerId::random(), PeerId::random());
+        scheduler.add_worker(cpu);
+        scheduler.add_worker(gpu);
+        scheduler.set_devices(&gpu, vec![Device::CPU, Device::CUDA, Device::OPTIX]);
+
+        let mut optix = task(Uuid::new_v4());
+        optix.overrides.device = Some(Device::OPTIX_GPUONLY);
+        scheduler.enqueue(optix.clone(), 10);
+        scheduler.enqueue(task(Uuid::new_v4()), 0);
+
+        let sent = network
+            .dispatch(&mut scheduler)
+            .into_iter()
+            .collect::<HashMap<PeerId, Task>>();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(sent[&gpu].id, optix.id);
+        assert_eq!(sent[&gpu].overrides.device, Some(Device::OPTIX_GPUONLY));
+        // left up to us, so the worker renders with the best it has.
+        assert_eq!(sent[&cpu].overrides.device, Some(Device::CPU));
+
+        // nobody else has OptiX, the task waits for the gpu worker.
+        scheduler.finish(&sent[&cpu].id);
+        scheduler.enqueue(optix, 10);
+        assert!(network.dispatch(&mut scheduler).is_empty());
+        scheduler.finish(&sent[&gpu].id);
+        assert_eq!(network.dispatch(&mut scheduler)[0].0, gpu);
+    }
+
+    #[test]
+    fn should_avoid_workers_that_failed_before() {
+        let mut scheduler = Scheduler::default();
+        let mut network = FakeNetwork::default();
+        let (a, b) = (PeerId::random(), PeerId::random());
+        scheduler.add_worker(a);
+        scheduler.add_worker(b);
+
+        // b is busy, so the retry waits for b instead of going back to a.
+        scheduler.enqueue(task(Uuid::new_v4()), 10);
+        scheduler.enqueue(task(Uuid::new_v4()), 10);
+        let sent = network
+            .dispatch(&mut scheduler)
+            .into_iter()
+            .collect::<HashMap<PeerId, Task>>();
+        scheduler.finish(&sent[&a].id);
+        scheduler.enqueue_avoiding(task(Uuid::new_v4()), 0, vec![a]);
+        assert!(network.dispatch(&mut scheduler).is_empty());
+
+        scheduler.finish(&sent[&b].id);
+        let sent = network.dispatch(&mut scheduler);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].0, b);
+
+        // if a is the only one left, a gets it anyway.
+        scheduler.remove_worker(&b);
+        scheduler.enqueue_avoiding(task(Uuid::new_v4()), 0, vec![a]);
+        assert_eq!(network.dispatch(&mut scheduler)[0].0, a);
+    }
+
+    #[test]
+    fn should_requeue_when_delivery_fails() {
+        let mut scheduler = Scheduler::default();
+        let mut network = FakeNetwork::default();
+        let (a, b) = (PeerId::random(), PeerId::random());
+        scheduler.add_worker(a);
+        scheduler.add_worker(b);
+        network.unreachable.insert(a);
+
+        scheduler.enqueue(task(Uuid::new_v4()), 0);
+        scheduler.enqueue(task(Uuid::new_v4()), 0);
+        let sent = network.dispatch(&mut scheduler);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].0, b);
+        assert_eq!(scheduler.worker_state(&a), Some(WorkerState::Offline));
+        assert_eq!(scheduler.pending(), 1);
+    }
+
+    #[test]
+    fn should_keep_busy_worker_available() {
+        let mut scheduler = Scheduler::default();
+        let mut network = FakeNetwork::default();
+        let worker = PeerId::random();
+        scheduler.add_worker(worker);
+        network.busy.insert(worker);
+
+        scheduler.enqueue(task(Uuid::new_v4()), 0);
+        assert!(network.dispatch(&mut scheduler).is_empty());
+        assert_eq!(scheduler.worker_state(&worker), Some(WorkerState::Idle));
+        assert_eq!(scheduler.pending(), 1);
+
+        // the worker catches up and asks for work.
+        network.busy.clear();
+        assert_eq!(network.dispatch(&mut scheduler).len(), 1);
+    }
+
+    #[test]
+    fn should_drop_removed_job() {
+        let mut scheduler = Scheduler::default();
+        let mut network = FakeNetwork::default();
+        let worker = PeerId::random();
+        scheduler.add_worker(worker);
+
+        let (job, other) = (Uuid::new_v4(), Uuid::new_v4());
+        scheduler.enqueue(task(job), 0);
+        scheduler.enqueue(task(job), 0);
+        scheduler.enqueue(task(other), 0);
+        network.dispatch(&mut scheduler);
+
+        scheduler.remove_job(&job);
+        assert_eq!(scheduler.worker_state(&worker), Some(WorkerState::Idle));
+        let sent = network.dispatch(&mut scheduler);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].1.job_id, other);
+    }
+
+    #[test]
+    fn should_split_largest_task_for_idle_worker() {
+        let mut scheduler = Scheduler::default();
+        let mut network = FakeNetwork::default();
+        let (a, b, idle) = (PeerId::random(), PeerId::random(), PeerId::random());
+        scheduler.add_worker(a);
+        scheduler.add_worker(b);
+
+        let job = Uuid::new_v4();
+        let mut small = task(job);
+        small.range = 1..5;
+        let mut large = task(job);
+        large.range = 1..20;
+        scheduler.enqueue(small, 0);
+        scheduler.enqueue(large.clone(), 0);
+        let sent = network.dispatch(&mut scheduler);
+        let busy = sent.iter().find(|(_, t)| t.id == large.id).unwrap().0;
+
+        // nobody is idle, so there's no reason to split.
+        assert_eq!(scheduler.split_candidate(), None);
+
+        // the worker with the larger task is well ahead, but it still has more left than the other.
+        scheduler.progress(&busy, &job, 4);
+        scheduler.add_worker(idle);
+        assert_eq!(scheduler.split_candidate(), Some((busy, large.id)));
+        // don't ask twice while we wait for the answer.
+        assert_eq!(scheduler.split_candidate(), None);
+        // unless the request never made it.
+        scheduler.split_failed(&large.id);
+        assert_eq!(scheduler.split_candidate(), Some((busy, large.id)));
+
+        // worker gives away the tail end, which goes to the idle worker.
+        scheduler.split(&large.id, Some(12..20));
+        let sent = network.dispatch(&mut scheduler);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].0, idle);
+        assert_eq!(sent[0].1.range, 12..20);
+        assert_eq!(sent[0].1.job_id, job);
+
+        // if the busy worker goes offline, only the frames it still owns are handed out again.
+        scheduler.remove_worker(&busy);
+        scheduler.finish(&sent[0].1.id);
+        let sent = network.dispatch(&mut scheduler);
+        assert_eq!(sent[0].1.range, 5..12);
+    }
+}
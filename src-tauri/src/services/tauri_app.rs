@@ -1,25 +1,36 @@
-use super::blend_farm::BlendFarm;
+use super::{
+    blend_farm::BlendFarm,
+    scheduler::{Scheduler, WorkerState},
+};
 use crate::{
-    domains::{job_store::JobStore, worker_store::WorkerStore},
+    domains::{
+        job_store::{JobError, JobStore},
+        worker_store::WorkerStore,
+    },
     models::{
         app_state::AppState,
         computer_spec::ComputerSpec,
-        job::{Job, JobEvent},
+        job::{Frame, Job, JobEvent},
         message::{NetEvent, NetworkError},
         network::{NetworkController, HEARTBEAT, JOB, SPEC, STATUS},
+        package::Package,
+        retry_policy::FrameFailure,
         server_setting::ServerSetting,
         task::Task,
+        tile::{self, Tile},
+        transfer,
         worker::Worker,
     },
     routes::{job::*, remote_render::*, settings::*, util::*, worker::*},
 };
+use blender::blender::Blender;
 use blender::manager::Manager as BlenderManager;
-use blender::models::mode::Mode;
+use blender::models::{blender_render_setting::Window, mode::Mode};
 use libp2p::PeerId;
 use maud::html;
 use serde::Serialize;
-use std::{collections::HashMap, ops::Range, sync::Arc};
-use std::{path::PathBuf, thread::sleep, time::Duration};
+use std::path::{Path, PathBuf};
+use std::{collections::HashMap, ops::Range, str::FromStr, sync::Arc};
 use tauri::{self, command, App, AppHandle, Emitter, Manager};
 use tokio::{
     select, spawn,
@@ -41,14 +52,15 @@ pub enum UiCommand {
     RemoveJob(Uuid),
 }
 
-// TODO: make this user adjustable.
-const MAX_BLOCK_SIZE: i32 = 30;
-
 pub struct TauriApp {
     // I need the peer's address?
     peers: HashMap<PeerId, ComputerSpec>,
     worker_store: Arc<RwLock<(dyn WorkerStore + Send + Sync + 'static)>>,
     job_store: Arc<RwLock<(dyn JobStore + Send + Sync + 'static)>>,
+    // decides which worker renders what.
+    scheduler: Scheduler,
+    // regions of a frame we've received so far. Only kept in memory, after a restart the whole frame renders again.
+    tiles: HashMap<(Uuid, Frame), Vec<Tile>>,
 }
 
 #[derive(Clone, Serialize)]
@@ -93,6 +105,8 @@ impl TauriApp {
             peers: Default::default(),
             worker_store,
             job_store,
+            scheduler: Default::default(),
+            tiles: Default::default(),
         }
     }
 
@@ -132,8 +146,10 @@ impl TauriApp {
                 select_directory,
                 select_file,
                 create_job,
+                stop_job,
                 delete_job,
                 get_job,
+                encode_video,
                 setting_page,
                 edit_settings,
                 get_settings,
@@ -153,101 +169,295 @@ impl TauriApp {
             .build(tauri::generate_context!())
     }
 
-    // because this is async, we can make our function wait for a new peers available.
-    async fn get_idle_peers(&self) -> PeerId {
-        // this will destroy the vector anyway.
-        // TODO: Impl. Round Robin or pick first idle worker, whichever have the most common hardware first in query?
-        // This code doesn't quite make sense, at least not yet?
+    // Record the failed frame of the task the worker finished, and hand it out again if the job's retry policy allows it.
+    async fn handle_frame_failure(
+        &mut self,
+        client: &mut NetworkController,
+        peer_id: PeerId,
+        finished: Task,
+        frame: Frame,
+        error: String,
+        remaining: Option<Range<Frame>>,
+    ) -> Result<FrameFailure, JobError> {
+        // the remaining frames keep the step of the task they came from, and a retried region stays a region.
+        let job_id = finished.job_id;
+        let job = self.job_store.read().await.get_job(&job_id).await?;
+        let step = finished.step;
+        let region = finished.region;
+
+        // the rest of the task never got a chance to render. That's not their fault, so they don't count as an attempt.
+        if let Some(range) = remaining {
+            let task = Task::from(client.public_id, job.clone(), range, step);
+            self.scheduler
+                .enqueue_avoiding(task, job.priority, vec![peer_id]);
+        }
+
+        let mut failure = self
+            .job_store
+            .read()
+            .await
+            .get_failure(&job_id, frame)
+            .await?
+            .unwrap_or_else(|| FrameFailure::new(job_id, frame));
+        failure.record(peer_id.to_base58(), error);
+
+        let retry = job.retry_policy.should_retry(&failure);
+        failure.permanent = !retry;
+        self.job_store
+            .write()
+            .await
+            .save_failure(failure.clone())
+            .await?;
+
+        if retry {
+            let avoid = match job.retry_policy.retry_on_different_worker {
+                true => failure
+                    .peers
+                    .iter()
+                    .filter_map(|peer| PeerId::from_str(peer).ok())
+                    .collect(),
+                false => Vec::new(),
+            };
+
+            let priority = job.priority;
+            let mut task = Task::from(client.public_id, job, frame..frame + 1, 1);
+            task.region = region;
+            self.scheduler.enqueue_avoiding(task, priority, avoid);
+        }
+
+        Ok(failure)
+    }
+
+    // Send out whatever the scheduler hands us. If a worker can't be reached, the scheduler takes the task back and we try again with someone else.
+    async fn dispatch(&mut self, client: &mut NetworkController) {
         loop {
-            if let Some((peer, ..)) = self.peers.clone().into_iter().nth(0) {
-                return peer;
+            let assignments = self.scheduler.schedule();
+            if assignments.is_empty() {
+                break;
+            }
+
+            // a worker that is still around gets the task again the next time we dispatch, instead of right away.
+            let mut retry_later = false;
+            for (peer, task) in assignments {
+                match client.send_job_message(peer, JobEvent::Render(task)).await {
+                    Ok(()) => {}
+                    // couldn't reach the worker, someone else gets the task.
+                    Err(NetworkError::DeliveryFailed(e)) => {
+                        eprintln!("Fail to send task to {peer}! {e}");
+                        self.scheduler.reject(&peer);
+                    }
+                    Err(e) => {
+                        eprintln!("{peer} could not take the task right now! {e:?}");
+                        self.scheduler.release(&peer);
+                        retry_later = true;
+                    }
+                }
+            }
+            if retry_later {
+                break;
             }
-            sleep(Duration::from_secs(1));
         }
     }
 
-    fn generate_tasks(job: &Job, file_name: PathBuf, chunks: i32, requestor: PeerId) -> Vec<Task> {
-        // mode may be removed soon, we'll see?
-        let (time_start, time_end) = match &job.mode {
-            Mode::Animation(anim) => (anim.start, anim.end),
-            Mode::Frame(frame) => (frame.clone(), frame.clone()),
-        };
+    // Worker ran out of tasks. Hand it something from the queue, otherwise ask the busiest worker to share the rest of its task.
+    async fn request_job(&mut self, client: &mut NetworkController, peer_id: PeerId) {
+        self.dispatch(client).await;
+        if self.scheduler.worker_state(&peer_id) != Some(WorkerState::Idle) {
+            return;
+        }
+
+        if let Some((peer, task_id)) = self.scheduler.split_candidate() {
+            let event = JobEvent::SplitTask { task_id };
+            if let Err(e) = client.send_job_message(peer, event).await {
+                eprintln!("Fail to ask {peer} to share task {task_id}! {e:?}");
+                self.scheduler.split_failed(&task_id);
+            }
+        }
+    }
 
-        // What if it's in the negative? e.g. [-200, 2 ] ? would this result to -180 and what happen to the equation?
-        let step = time_end - time_start;
-        let max_step = step / chunks;
-        let mut tasks = Vec::with_capacity(max_step as usize);
+    // Share the project file and everything it needs on the network, each under its content hash, so workers never mix them up with another file of the same name.
+    // The files may have changed since the job was created, the hashes are taken again every time and saved with the job.
+    async fn provide_project(
+        &mut self,
+        client: &mut NetworkController,
+        job: &mut Job,
+    ) -> Result<(), JobError> {
+        let path = job.project_file.clone();
+        let (hash, package, root) = tokio::task::spawn_blocking(move || {
+            let hash = transfer::hash_file(&path)?;
+            // we can still render without them if blender can't read the file (e.g. compressed), the render will tell.
+            let dependencies = Blender::dependencies(&path).unwrap_or_else(|e| {
+                eprintln!("Unable to find what {path:?} depends on! {e:?}");
+                Vec::new()
+            });
+            let (package, root) = Package::pack(&path, &dependencies)?;
+            Ok::<_, std::io::Error>((hash, package, root))
+        })
+        .await
+        .map_err(|e| JobError::FailedToRun(e.to_string()))?
+        .map_err(|e| JobError::InvalidFile(e.to_string()))?;
+
+        if job.project_hash != hash || job.package != package {
+            if !job.project_hash.is_empty() {
+                println!(
+                    "Project {:?} changed since job {} started, rendering the new files from now on.",
+                    job.project_file, job.id
+                );
+            }
+            job.project_hash = hash.clone();
+            job.package = package;
+            self.job_store.write().await.update_job(job.clone()).await?;
+        }
 
-        for i in 0..=max_step {
-            // current start block location.
-            let block = time_start + i * chunks;
+        client.start_providing(hash, job.project_file.clone()).await;
+        for file in &job.package.files {
+            client
+                .start_providing(file.hash.clone(), root.join(&file.path))
+                .await;
+        }
+        Ok(())
+    }
 
-            let mut start = block;
-            if i > 0 {
-                // inclusive start
-                start += 1;
+    // Queue up the frames of the job, split into tasks by the chunk strategy from the settings.
+    fn queue_frames(&mut self, client: &NetworkController, job: &Job, frames: &[Frame]) {
+        // every region of the frame is a task of its own.
+        if let Mode::Section { columns, rows, .. } = job.mode {
+            for frame in frames {
+                for region in Window::grid(columns, rows) {
+                    let mut task = Task::from(client.public_id, job.clone(), *frame..*frame + 1, 1);
+                    task.region = Some(region);
+                    self.scheduler.enqueue(task, job.priority);
+                }
             }
+            return;
+        }
 
-            let end = block + chunks;
-            let end = match end.cmp(&time_end) {
-                std::cmp::Ordering::Less => end,
-                _ => time_end,
-            };
-            let range = Range { start, end };
-
-            let task = Task::new(
-                requestor,
-                job.id,
-                file_name.clone(),
-                job.get_version().clone(),
-                range,
-            );
-            tasks.push(task);
+        let step = job.mode.step();
+        let speeds = self.scheduler.speeds();
+        let chunks = client.settings.chunking.split(frames, step, &speeds);
+        for range in chunks {
+            let task = Task::from(client.public_id, job.clone(), range, step);
+            self.scheduler.enqueue(task, job.priority);
         }
+    }
 
-        tasks
+    // Hold on to the region until every region of the frame has arrived, then stitch them back into the frame.
+    async fn add_tile(
+        &mut self,
+        job_id: Uuid,
+        frame: Frame,
+        tile: Tile,
+        destination: &Path,
+    ) -> Option<PathBuf> {
+        let expected = match self.job_store.read().await.get_job(&job_id).await {
+            Ok(job) => match job.mode {
+                Mode::Section { columns, rows, .. } => Window::grid(columns, rows).len(),
+                _ => 1,
+            },
+            Err(e) => {
+                eprintln!("Unable to find job {job_id} for region of frame {frame}! {e:?}");
+                return None;
+            }
+        };
+
+        let tiles = self.tiles.entry((job_id, frame)).or_default();
+        // a region that was rendered again replaces the one we had.
+        tiles.retain(|t| t.region != tile.region);
+        tiles.push(tile);
+        if tiles.len() < expected {
+            return None;
+        }
+
+        let tiles = self.tiles.remove(&(job_id, frame)).unwrap_or_default();
+        match tile::stitch(&tiles, destination) {
+            Ok(path) => Some(path),
+            Err(e) => {
+                eprintln!("Fail to stitch frame {frame} of job {job_id}! {e:?}");
+                None
+            }
+        }
+    }
+
+    // Pick up unfinished jobs from the database after the manager restart.
+    // The project file needs to be provided again, and only the frames we haven't received are sent out.
+    async fn resume_jobs(&mut self, client: &mut NetworkController) {
+        let jobs = match self.job_store.read().await.list_all().await {
+            Ok(jobs) => jobs,
+            Err(e) => {
+                eprintln!("Unable to load jobs to resume! {e:?}");
+                return;
+            }
+        };
+
+        // the user stopped these on purpose, leave them be until they're started again.
+        for mut job in jobs.into_iter().filter(|job| !job.stopped) {
+            let failures = self
+                .job_store
+                .read()
+                .await
+                .list_failures(&job.id)
+                .await
+                .unwrap_or_default();
+            let skip = failures
+                .iter()
+                .filter(|f| f.permanent)
+                .map(|f| f.frame)
+                .collect::<Vec<Frame>>();
+
+            let frames = job.missing_frames(&skip);
+            if frames.is_empty() {
+                continue;
+            }
+
+            if !job.project_file.exists() {
+                eprintln!(
+                    "Unable to resume job {}, project file {:?} no longer exist!",
+                    job.id, job.project_file
+                );
+                continue;
+            }
+
+            println!("Resuming job {} with {} frames left", job.id, frames.len());
+            if let Err(e) = self.provide_project(client, &mut job).await {
+                eprintln!("Unable to resume job {}! {e:?}", job.id);
+                continue;
+            }
+
+            self.queue_frames(client, &job, &frames);
+        }
+        self.dispatch(client).await;
     }
 
     // command received from UI
     async fn handle_command(&mut self, client: &mut NetworkController, cmd: UiCommand) {
         match cmd {
-            // TODO: This may subject to change. 
-            // Issue: What if the app restarts? We no longer provide the file after reboot.
-            UiCommand::StartJob(job) => {
+            // TODO: This may subject to change.
+            // If the app restarts, resume_jobs() provides the file again and sends out the frames we're missing.
+            UiCommand::StartJob(mut job) => {
                 // first make the file available on the network
-                let file_name = job.project_file.file_name().unwrap();
-                let path = job.project_file.clone();
-
-                client
-                    .start_providing(file_name.to_str().unwrap().to_string(), path)
-                    .await;
-
-                let tasks = Self::generate_tasks(
-                    &job,
-                    PathBuf::from(file_name),
-                    MAX_BLOCK_SIZE,
-                    client.public_id.clone(),
-                );
-
-                // so here's the culprit. We're waiting for a peer to become idle and inactive waiting for the next job
-                for task in tasks {
-                    let peer = self.get_idle_peers().await; // this means I must wait for an active peers to become available?
-                    let event = JobEvent::Render(task);
-                    client.send_job_message(peer, event).await;
+                if let Err(e) = self.provide_project(client, &mut job).await {
+                    eprintln!("Unable to start job {}! {e:?}", job.id);
+                    return;
                 }
+
+                // tasks wait in the scheduler until a worker is free to take them.
+                self.queue_frames(client, &job, &job.frames());
+                self.dispatch(client).await;
             }
             UiCommand::UploadFile(path, file_name) => {
                 client.start_providing(file_name, path).await;
             }
-            UiCommand::StopJob(id) => {
-                println!(
-                    "Impl how to send a stop signal to stop the job and remove the job from queue {id:?}"
-                );
-            }
-            UiCommand::RemoveJob(id) => {
+            // workers cancel their active render and drop any task matching the job id.
+            UiCommand::StopJob(id) | UiCommand::RemoveJob(id) => {
+                self.scheduler.remove_job(&id);
+                self.tiles.retain(|(job_id, _), _| *job_id != id);
                 for (peer, _) in self.peers.clone() {
-                    client.send_job_message(peer, JobEvent::Remove(id)).await;
+                    if let Err(e) = client.send_job_message(peer, JobEvent::Remove(id)).await {
+                        eprintln!("Fail to tell {peer} to stop job {id}! {e:?}");
+                    }
                 }
+                // workers that were rendering this job are free now.
+                self.dispatch(client).await;
             }
         }
     }
@@ -275,7 +485,11 @@ impl TauriApp {
                     eprintln!("Error adding worker to database! {e:?}");
                 }
 
+                // we may have tasks waiting around for a worker.
+                self.scheduler.add_worker(peer_id);
+                self.scheduler.set_devices(&peer_id, spec.devices.clone());
                 self.peers.insert(peer_id, spec);
+                self.dispatch(client).await;
                 // let handle = app_handle.write().await;
                 // emit a signal to query the data. 
                 // TODO: See how this can be done: https://github.com/ChristianPavilonis/tauri-htmx-extension
@@ -288,43 +502,91 @@ impl TauriApp {
                 }
 
                 self.peers.remove(&peer_id);
+                // whatever this worker was rendering goes to someone else.
+                self.scheduler.remove_worker(&peer_id);
+                self.dispatch(client).await;
                 // let handle = app_handle.write().await;
                 // let _ = handle.emit("worker_update", ());
             }
             NetEvent::InboundRequest { request, channel } => {
-                if let Some(path) = client.providing_files.get(&request) {
-                    client
-                        .respond_file(std::fs::read(path).unwrap(), channel)
-                        .await
-                }
+                client.respond_file(request, channel).await
             }
-            NetEvent::JobUpdate(.., job_event) => match job_event {
+            NetEvent::JobUpdate(peer_id, job_event) => match job_event {
                 // when we receive a completed image, send a notification to the host and update job index to obtain the latest render image.
                 JobEvent::ImageCompleted {
                     job_id: id,
                     frame,
                     file_name,
+                    passes,
+                    region,
                 } => {
+                    self.scheduler.progress(&peer_id, &id, frame);
+
                     // create a destination with respective job id path.
                     let destination = client.settings.render_dir.join(id.to_string());
                     if let Err(e) = async_std::fs::create_dir_all(destination.clone()).await {
                         println!("Issue creating temp job directory! {e:?}");
                     }
 
-                    let handle = app_handle.write().await;
-                    if let Err(e) = handle.emit(
-                        "frame_update",
-                        FrameUpdatePayload {
-                            id,
-                            frame,
-                            file_name: file_name.clone(),
-                        },
-                    ) {
-                        eprintln!("Unable to send emit to app handler\n{e:?}");
+                    // let go of the handle before fetching the file, we need it again once the image is here.
+                    {
+                        let handle = app_handle.write().await;
+                        if let Err(e) = handle.emit(
+                            "frame_update",
+                            FrameUpdatePayload {
+                                id,
+                                frame,
+                                file_name: file_name.clone(),
+                            },
+                        ) {
+                            eprintln!("Unable to send emit to app handler\n{e:?}");
+                        }
                     }
 
                     // Fetch the completed image file from the network
-                    if let Ok(file) = client.get_file_from_peers(&file_name, &destination).await {
+                    // the file name starts with the job id, so it lands in the destination above.
+                    let render_dir = client.settings.render_dir.clone();
+                    if let Ok(file) = client.get_file_from_peers(&file_name, &render_dir).await {
+                        // passes land next to the image. Regions keep theirs in the region's folder, only the image gets stitched together.
+                        for pass in &passes {
+                            let path = match client.get_file_from_peers(pass, &render_dir).await {
+                                Ok(path) => path,
+                                Err(e) => {
+                                    eprintln!(
+                                        "Fail to fetch render pass {pass} of frame {frame}! {e:?}"
+                                    );
+                                    continue;
+                                }
+                            };
+                            let mut db = self.job_store.write().await;
+                            if let Err(e) = db.add_render_pass(&id, frame, &path).await {
+                                eprintln!("Fail to save render pass record {pass} of frame {frame}! {e:?}");
+                            }
+                        }
+
+                        // a region only counts once the rest of the frame is here.
+                        let file = match region {
+                            Some(region) => {
+                                // workers crop the image down to the region, see Task::run()
+                                let tile = Tile {
+                                    region,
+                                    path: file,
+                                    cropped: true,
+                                };
+                                self.add_tile(id, frame, tile, &destination).await
+                            }
+                            None => Some(file),
+                        };
+                        let Some(file) = file else {
+                            return;
+                        };
+
+                        // keep a record so we know this frame is done, even after the manager restarts.
+                        let mut db = self.job_store.write().await;
+                        if let Err(e) = db.add_render(&id, frame, &file).await {
+                            eprintln!("Fail to save render record of frame {frame}! {e:?}");
+                        }
+
                         let handle = app_handle.write().await;
                         if let Err(e) = handle.emit("job_image_complete", (id, frame, file)) {
                             eprintln!("Fail to publish image completion emit to front end! {e:?}");
@@ -332,19 +594,100 @@ impl TauriApp {
                     }
                 }
 
-                // when a job is complete, check the poll for next available job queue?
-                JobEvent::JobComplete => {} // Hmm how do I go about handling this one?
+                // worker finished the task, hand it the next one.
+                JobEvent::JobComplete { task_id } => {
+                    // we may have given up on the worker while it was busy rendering.
+                    self.scheduler.add_worker(peer_id);
+                    self.scheduler.finish(&task_id);
+                    self.dispatch(client).await;
+                }
+
+                // worker couldn't render the frame, see if we should give it another shot.
+                JobEvent::FrameFailed {
+                    task_id,
+                    job_id,
+                    frame,
+                    error,
+                    remaining,
+                } => {
+                    // a late report of a task we took back, it's already in the queue again or the job is gone.
+                    let Some(task) = self.scheduler.finish(&task_id) else {
+                        eprintln!(
+                            "Ignoring failed frame {frame} of job {job_id}, task {task_id} is no longer assigned."
+                        );
+                        self.dispatch(client).await;
+                        return;
+                    };
+                    match self
+                        .handle_frame_failure(client, peer_id, task, frame, error, remaining)
+                        .await
+                    {
+                        Ok(failure) => {
+                            let handle = app_handle.write().await;
+                            if let Err(e) = handle.emit("frame_failed", failure) {
+                                eprintln!("Fail to publish frame failure to front end! {e:?}");
+                            }
+                        }
+                        Err(e) => eprintln!(
+                            "Unable to handle failed frame {frame} of job {job_id}! {e:?}"
+                        ),
+                    }
+                    self.dispatch(client).await;
+                }
+
+                // The worker couldn't start the task at all (e.g. missing blender).
+                // Blame the first frame, so the retry policy decides whether someone else should try. The rest of the task goes back in the queue.
+                JobEvent::Error {
+                    task_id,
+                    error: job_error,
+                } => {
+                    eprintln!("Received error from {peer_id}: {job_error:?}");
+                    // nothing to requeue if we already took the task back, e.g. the job was removed.
+                    if let Some(task) = self.scheduler.finish(&task_id) {
+                        let (id, job_id) = (task.id, task.job_id);
+                        let frame = task.range.start;
+                        let remaining = frame + task.step..task.range.end;
+                        let remaining = (!remaining.is_empty()).then_some(remaining);
+                        if let Err(e) = self
+                            .handle_frame_failure(
+                                client,
+                                peer_id,
+                                task,
+                                frame,
+                                job_error.to_string(),
+                                remaining,
+                            )
+                            .await
+                        {
+                            eprintln!("Unable to requeue task {id} of job {job_id}! {e:?}");
+                        }
+                    }
+                    let handle = app_handle.write().await;
+                    if let Err(e) =
+                        handle.emit("job_error", (peer_id.to_base58(), job_error.to_string()))
+                    {
+                        eprintln!("Fail to publish job error to front end! {e:?}");
+                    }
+                    self.dispatch(client).await;
+                }
+
+                // idle worker would like something to do. It's clearly still around, even if a delivery failed earlier.
+                JobEvent::RequestJob => {
+                    self.scheduler.add_worker(peer_id);
+                    self.request_job(client, peer_id).await
+                }
 
-                // TODO: how do we handle error from node? What kind of errors are we expecting here and what can the host do about it?
-                JobEvent::Error(job_error) => {
-                    todo!("See how this can be replicated? {job_error:?}")
+                // busy worker gave up the tail end of its task, send it to whoever is idle.
+                JobEvent::TaskSplit { task_id, frames } => {
+                    self.scheduler.split(&task_id, frames);
+                    self.dispatch(client).await;
                 }
 
                 // send a render job
                 // this will soon go away - host should not be receiving render jobs.
                 JobEvent::Render(..) => {}
-                // this will soon go away - host should not receive request job.
-                JobEvent::RequestJob => {}
+                // host is the one asking to split tasks.
+                JobEvent::SplitTask { .. } => {}
                 // this will soon go away
                 JobEvent::Remove(_) => {
                     // Should I do anything on the manager side? Shouldn't matter at this point?
@@ -382,6 +725,9 @@ impl BlendFarm for TauriApp {
 
         // create a background loop to send and process network event
         spawn(async move {
+            // pick up where we left off if the manager shut down in the middle of a job.
+            self.resume_jobs(&mut client).await;
+
             loop {
                 select! {
                     Some(msg) = command.recv() => self.handle_command(&mut client, msg).await,